@@ -4,13 +4,21 @@
 use crate::tuple::{Tuple, point};
 use crate::shape::Shape;
 use crate::shape::cone::Cone;
+use crate::shape::paraboloid::Paraboloid;
+use crate::shape::torus::Torus;
 use crate::shape::cylinder::Cylinder;
 use num_traits::float::Float as NumFloat;
 use crate::shape::triangle::Triangle;
+use crate::shape::smooth_triangle::SmoothTriangle;
 use crate::float::Float;
 use crate::shape::group::Group;
+use crate::shape::csg::CSG;
 use crate::shape::shape_list::ShapeList;
 use crate::shape::cube::Cube;
+use crate::matrix::Matrix4;
+use crate::ray::Ray;
+use crate::FLOAT_THRESHOLD;
+use crate::transformation::{translation, scaling};
 
 
 #[derive(Debug, PartialEq, Clone)]
@@ -39,15 +47,24 @@ impl Bounds {
 
     /// Returns the bounds encapsulating a shape or group
     pub fn bounds(shape: Box<dyn Shape + Send>, shape_list: &mut ShapeList) -> Option<Bounds> {
+        let (min, max) = Bounds::object_bounds(*shape.as_shape(), shape_list);
+        Some(Bounds::new_with_bounds(min, max, shape_list))
+    }
+
+    /// Returns the minimum and maximum points encapsulating a shape or group
+    pub fn object_bounds(shape: &dyn Shape, shape_list: &ShapeList) -> (Tuple, Tuple) {
         // Bounds are returned in Object space
         // un-transformed
 
         match shape.shape_type().as_ref() {
             "sphere"|"cube" => {
-                Some(Bounds::new_with_bounds(point(-1.0, -1.0, -1.0), point(1.0, 1.0, 1.0), shape_list))
+                (point(-1.0, -1.0, -1.0), point(1.0, 1.0, 1.0))
             }
             "plane" => {
-                Some(Bounds::new_with_bounds(point(NumFloat::neg_infinity(), -0.01, NumFloat::neg_infinity()), point(NumFloat::infinity(), 0.01, NumFloat::infinity()), shape_list))
+                (point(NumFloat::neg_infinity(), -0.01, NumFloat::neg_infinity()), point(NumFloat::infinity(), 0.01, NumFloat::infinity()))
+            }
+            "disk" => {
+                (point(-1.0, -0.01, -1.0), point(1.0, 0.01, 1.0))
             }
             "cylinder" => {
                 // Downcast to shape to work with cylinder properties
@@ -66,36 +83,42 @@ impl Bounds {
                 } else {
                     max = point(1.0, cylinder.maximum, 1.0);
                 }
-                Some(Bounds::new_with_bounds(min, max, shape_list))
+                (min, max)
+            }
+            "torus" => {
+                let torus: &Torus = shape.as_any().downcast_ref::<Torus>().unwrap();
+                let extent = torus.major_radius + torus.minor_radius;
+                (point(-extent, -torus.minor_radius, -extent), point(extent, torus.minor_radius, extent))
+            }
+            "paraboloid" => {
+                let paraboloid: &Paraboloid = shape.as_any().downcast_ref::<Paraboloid>().unwrap();
+                // The widest point is at the top, y = r²
+                let radius = paraboloid.maximum.sqrt();
+                (point(-radius, paraboloid.minimum.max(0.0), -radius), point(radius, paraboloid.maximum, radius))
             }
             "cone" => {
                 // Downcast to shape to work with cone properties
                 let cone: &Cone = shape.as_any().downcast_ref::<Cone>().unwrap();
-                let min: Tuple;
-                let max: Tuple;
-
-                if cone.minimum == Float(NumFloat::neg_infinity()) {
-                    min = point(-1.0, NumFloat::neg_infinity(), -1.0);
-                } else {
-                    min = point(-1.0, cone.minimum, -1.0);
-                }
-
-                if cone.maximum == Float(NumFloat::infinity()) {
-                    max = point(1.0, NumFloat::infinity(), 1.0);
-                } else {
-                    max = point(1.0, cone.maximum, 1.0);
-                }
-                Some(Bounds::new_with_bounds(min, max, shape_list))
+                // The radius at height y is |y|, so the widest end sets the x and z extents,
+                // an unbounded cone is infinitely wide
+                let radius = cone.minimum.abs().max(cone.maximum.abs());
+                (point(-radius, cone.minimum, -radius), point(radius, cone.maximum, radius))
             }
-            "triangle" => {
+            "triangle" | "smooth_triangle" => {
                 // Downcast to shape to work with triangle properties
-                let triangle: &Triangle = shape.as_any().downcast_ref::<Triangle>().unwrap();
+                let points = match shape.as_any().downcast_ref::<Triangle>() {
+                    Some(triangle) => [triangle.p1, triangle.p2, triangle.p3],
+                    None => {
+                        let triangle: &SmoothTriangle = shape.as_any().downcast_ref::<SmoothTriangle>().unwrap();
+                        [triangle.p1, triangle.p2, triangle.p3]
+                    }
+                };
                 // Find lowest and highest x, y, and z values
                 let mut l_x: f64 = NumFloat::infinity(); let mut h_x: f64 = NumFloat::neg_infinity();
                 let mut l_y: f64 = NumFloat::infinity(); let mut h_y: f64 = NumFloat::neg_infinity();
                 let mut l_z: f64 = NumFloat::infinity(); let mut h_z: f64 = NumFloat::neg_infinity();
 
-                for point in [triangle.p1, triangle.p2, triangle.p3].iter() {
+                for point in points.iter() {
                     if point.x < Float(l_x) {
                         l_x = point.x.value();
                     }
@@ -116,7 +139,7 @@ impl Bounds {
                         h_z = point.z.value();
                     }
                 }
-                Some(Bounds::new_with_bounds(point(l_x, l_y, l_z), point(h_x, h_y, h_z), shape_list))
+                (point(l_x, l_y, l_z), point(h_x, h_y, h_z))
             }
             "group" => {
                 // Here's the interesting bit
@@ -129,11 +152,10 @@ impl Bounds {
                 let mut l_z: f64 = NumFloat::infinity(); let mut h_z: f64 = NumFloat::neg_infinity();
 
                 for id in group.children_ids.clone() {
-                    let child = shape_list.get(id);
-                    let child_bounds = Bounds::bounds(child.clone(), shape_list);
+                    let child = &shape_list[id as usize];
+                    let (child_min, child_max) = Bounds::object_bounds(*child.as_shape(), shape_list);
                     // Transform child bounds from object space to group space
-                    let group_min_point: Tuple = child.transform() * child_bounds.clone().unwrap().min_point;
-                    let group_max_point: Tuple = child.transform() * child_bounds.clone().unwrap().max_point;
+                    let (group_min_point, group_max_point) = Bounds::transform_bounds(child_min, child_max, &child.transform());
 
                     if group_min_point.x < Float(l_x) {
                         l_x = group_min_point.x.value();
@@ -155,15 +177,109 @@ impl Bounds {
                         h_z = group_max_point.z.value();
                     }
                 }
-                Some(Bounds::new_with_bounds(point(l_x, l_y, l_z), point(h_x, h_y, h_z), shape_list))
+                (point(l_x, l_y, l_z), point(h_x, h_y, h_z))
+            }
+            "csg" => {
+                let csg: &CSG = shape.as_any().downcast_ref::<CSG>().unwrap();
+
+                // Union of both operands in the csg's space
+                let mut min = point(NumFloat::infinity(), NumFloat::infinity(), NumFloat::infinity());
+                let mut max = point(NumFloat::neg_infinity(), NumFloat::neg_infinity(), NumFloat::neg_infinity());
+                for id in csg.left_id.iter().chain(csg.right_id.iter()) {
+                    let child = &shape_list[*id as usize];
+                    let (child_min, child_max) = Bounds::object_bounds(*child.as_shape(), shape_list);
+                    let (child_min, child_max) = Bounds::transform_bounds(child_min, child_max, &child.transform());
+                    min = point(min.x.value().min(child_min.x.value()), min.y.value().min(child_min.y.value()), min.z.value().min(child_min.z.value()));
+                    max = point(max.x.value().max(child_max.x.value()), max.y.value().max(child_max.y.value()), max.z.value().max(child_max.z.value()));
+                }
+                (min, max)
             }
             "test_shape" => {
-                Some(Bounds::new(shape_list))
+                (point(-1.0, -1.0, -1.0), point(1.0, 1.0, 1.0))
             }
             _ => {
-                Some(Bounds::new(shape_list))
+                // Unknown shapes are unbounded so they are never culled by their bounds
+                (point(NumFloat::neg_infinity(), NumFloat::neg_infinity(), NumFloat::neg_infinity()),
+                 point(NumFloat::infinity(), NumFloat::infinity(), NumFloat::infinity()))
+            }
+        }
+    }
+
+    /// Returns the axis aligned box encapsulating the box between `min` and `max`
+    /// after it has been transformed
+    ///
+    /// Each axis is found from the transform's coefficients rather than its 8 corners
+    /// so that unbounded shapes like planes don't produce NaNs
+    pub fn transform_bounds(min: Tuple, max: Tuple, transform: &Matrix4) -> (Tuple, Tuple) {
+        let min = [min.x.value(), min.y.value(), min.z.value()];
+        let max = [max.x.value(), max.y.value(), max.z.value()];
+        let mut new_min = [0.0; 3];
+        let mut new_max = [0.0; 3];
+
+        for i in 0..3 {
+            new_min[i] = transform[i][3].value();
+            new_max[i] = transform[i][3].value();
+            for j in 0..3 {
+                let coefficient = transform[i][j].value();
+                if coefficient == 0.0 {
+                    continue;
+                }
+                let a = coefficient * min[j];
+                let b = coefficient * max[j];
+                new_min[i] += a.min(b);
+                new_max[i] += a.max(b);
             }
         }
+        (point(new_min[0], new_min[1], new_min[2]), point(new_max[0], new_max[1], new_max[2]))
+    }
+
+    /// Returns the tight axis aligned bounds of this box after it has been transformed,
+    /// the same box found by transforming all 8 of its corners
+    ///
+    /// The returned cube is a copy fit to the new bounds that is not added to the shape list
+    pub fn transform_to_world(&self, shape_transform: Matrix4) -> Bounds {
+        let (min_point, max_point) = Bounds::transform_bounds(self.min_point, self.max_point, &shape_transform);
+
+        let center: Tuple = (max_point + min_point) / 2.0;
+        let half_size: Tuple = (max_point - min_point) / 2.0;
+        let mut cube = self.cube.clone();
+        cube.transform = translation(center.x.value(), center.y.value(), center.z.value()) *
+            scaling(half_size.x.value(), half_size.y.value(), half_size.z.value());
+
+        Bounds { min_point, max_point, cube }
+    }
+
+    /// Returns whether the ray hits the box between `min_point` and `max_point`
+    ///
+    /// Uses the slab test without creating any intersections, the ray is
+    /// expected to already be in the same space as the bounds
+    pub fn check_hit(&self, ray: &Ray) -> bool {
+        Bounds::ray_hits_box(ray, self.min_point, self.max_point)
+    }
+
+    /// Slab test of a ray against the axis aligned box between `min` and `max`
+    pub fn ray_hits_box(ray: &Ray, min: Tuple, max: Tuple) -> bool {
+        let origin = [ray.origin.x.value(), ray.origin.y.value(), ray.origin.z.value()];
+        let direction = [ray.direction.x.value(), ray.direction.y.value(), ray.direction.z.value()];
+        let min = [min.x.value(), min.y.value(), min.z.value()];
+        let max = [max.x.value(), max.y.value(), max.z.value()];
+
+        let mut tmin: f64 = NumFloat::neg_infinity();
+        let mut tmax: f64 = NumFloat::infinity();
+        for i in 0..3 {
+            if direction[i].abs() < FLOAT_THRESHOLD {
+                // Parallel to the slab, the origin has to be between its planes
+                if origin[i] < min[i] || origin[i] > max[i] {
+                    return false;
+                }
+                continue;
+            }
+            let t1 = (min[i] - origin[i]) / direction[i];
+            let t2 = (max[i] - origin[i]) / direction[i];
+            tmin = tmin.max(t1.min(t2));
+            tmax = tmax.min(t1.max(t2));
+        }
+        tmin <= tmax
     }
 }
 
@@ -175,7 +291,9 @@ mod tests {
     use crate::shape::sphere::Sphere;
     use crate::ray::Ray;
     use crate::tuple::vector;
-    use crate::transformation::{translation, scaling};
+    use crate::transformation::rotation_z;
+    use crate::shape::plane::Plane;
+    use std::f64::consts::PI;
 
     #[test]
     fn bounds_creation() {
@@ -214,6 +332,24 @@ mod tests {
         assert_eq!(xb[1].t, 0.8);
     }
 
+    #[test]
+    fn bounds_check_hit() {
+        let shape_list = &mut ShapeList::new();
+        let b = Bounds::new_with_bounds(point(-1.0, -2.0, -1.0), point(1.0, 2.0, 3.0), shape_list);
+
+        assert!(b.check_hit(&Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0))));
+        assert!(b.check_hit(&Ray::new(point(0.0, 1.9, -5.0), vector(0.0, 0.0, 1.0))));
+        assert!(b.check_hit(&Ray::new(point(5.0, 5.0, 5.0), vector(-1.0, -1.0, -1.0))));
+        assert!(!b.check_hit(&Ray::new(point(0.0, 2.1, -5.0), vector(0.0, 0.0, 1.0))));
+        assert!(!b.check_hit(&Ray::new(point(-2.0, 0.0, -5.0), vector(1.0, 0.0, 0.2))));
+
+        // Unbounded boxes like a plane's are still hit
+        let (min, max) = Bounds::object_bounds(&Plane::new(shape_list), shape_list);
+        let b = Bounds { min_point: min, max_point: max, cube: Cube::new(shape_list) };
+        assert!(b.check_hit(&Ray::new(point(3.0, 5.0, 1.0), vector(0.1, -1.0, 0.0))));
+        assert!(!b.check_hit(&Ray::new(point(3.0, 5.0, 1.0), vector(1.0, 0.0, 0.0))));
+    }
+
     #[test]
     fn bounds_group_object() {
         let shape_list = &mut ShapeList::new();
@@ -279,4 +415,86 @@ mod tests {
         assert_eq!(xb[0].t, 2.0);
         assert_eq!(xb[1].t, 8.0);
     }
+
+    #[test]
+    fn bounds_transform_bounds() {
+        let (min, max) = Bounds::transform_bounds(point(-1.0, -1.0, -1.0), point(1.0, 1.0, 1.0),
+                                                  &(translation(1.0, 0.0, 0.0) * scaling(2.0, 1.0, 1.0)));
+        assert_eq!(min, point(-1.0, -1.0, -1.0));
+        assert_eq!(max, point(3.0, 1.0, 1.0));
+
+        // Rotating a box grows its axis aligned bounds
+        let (min, max) = Bounds::transform_bounds(point(-1.0, -1.0, -1.0), point(1.0, 1.0, 1.0),
+                                                  &rotation_z(PI/4.0));
+        assert_eq!(min, point(-2.0f64.sqrt(), -2.0f64.sqrt(), -1.0));
+        assert_eq!(max, point(2.0f64.sqrt(), 2.0f64.sqrt(), 1.0));
+    }
+
+    #[test]
+    fn bounds_transform_to_world() {
+        let shape_list = &mut ShapeList::new();
+        let b = Bounds::new_with_bounds(point(-1.0, 0.0, -1.0), point(1.0, 2.0, 1.0), shape_list);
+        let world = b.transform_to_world(translation(0.0, 0.0, 5.0) * rotation_z(PI/2.0));
+        assert_eq!(world.min_point, point(-2.0, -1.0, 4.0));
+        assert_eq!(world.max_point, point(0.0, 1.0, 6.0));
+
+        // The cube covers the new bounds
+        let r = Ray::new(point(-1.0, 0.0, 0.0), vector(0.0, 0.0, 1.0));
+        let xs = world.cube.intersects(&r, shape_list);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.0);
+        assert_eq!(xs[1].t, 6.0);
+        assert!(world.check_hit(&r));
+    }
+
+    #[test]
+    fn bounds_csg() {
+        let shape_list = &mut ShapeList::new();
+        let s1 = Sphere::new(shape_list);
+        let mut s2 = Sphere::new(shape_list);
+        s2.set_transform(translation(3.0, 0.0, 0.0), shape_list);
+        let c = CSG::new_with_operation("union", s1.id, s2.id, shape_list);
+
+        assert_eq!(Bounds::object_bounds(&c, shape_list), (point(-1.0, -1.0, -1.0), point(4.0, 1.0, 1.0)));
+
+        // Rays only hitting the translated operand aren't culled
+        let r = Ray::new(point(3.5, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        assert!(c.intersects_bounds(&r, shape_list));
+        assert_eq!(c.intersects(&r, shape_list).len(), 2);
+        let r = Ray::new(point(3.5, 2.0, -5.0), vector(0.0, 0.0, 1.0));
+        assert!(!c.intersects_bounds(&r, shape_list));
+    }
+
+    #[test]
+    fn bounds_world() {
+        let shape_list = &mut ShapeList::new();
+        let mut s = Sphere::new(shape_list);
+        s.set_transform(translation(1.0, 2.0, 3.0) * scaling(0.5, 2.0, 1.0), shape_list);
+        assert_eq!(s.bbox_world(shape_list), (point(0.5, 0.0, 2.0), point(1.5, 4.0, 4.0)));
+
+        let mut g = Group::new(shape_list);
+        let mut child: Box<dyn Shape + Send> = Box::new(s);
+        g.add_child(&mut child, shape_list);
+        g.set_transform(translation(-1.0, 0.0, 0.0), shape_list);
+        assert_eq!(g.bbox_world(shape_list), (point(-0.5, 0.0, 2.0), point(0.5, 4.0, 4.0)));
+
+        // Children include their parents' transforms
+        let child = shape_list.get(child.id());
+        assert_eq!(child.bbox_world(shape_list), (point(-0.5, 0.0, 2.0), point(0.5, 4.0, 4.0)));
+        let mut outer = Group::new(shape_list);
+        let mut inner: Box<dyn Shape + Send> = Box::new(g);
+        outer.add_child(&mut inner, shape_list);
+        outer.set_transform(scaling(2.0, 2.0, 2.0), shape_list);
+        assert_eq!(child.bbox_world(shape_list), (point(-1.0, 0.0, 4.0), point(1.0, 8.0, 8.0)));
+        assert_eq!(shape_list.get(inner.id()).bbox_world(shape_list), (point(-1.0, 0.0, 4.0), point(1.0, 8.0, 8.0)));
+
+        // Planes stay unbounded along x and z
+        let mut p = Plane::new(shape_list);
+        p.set_transform(translation(0.0, 1.0, 0.0), shape_list);
+        let (min, max) = p.bbox_world(shape_list);
+        assert!(min.x.value() == f64::NEG_INFINITY && min.z.value() == f64::NEG_INFINITY);
+        assert!(max.x.value() == f64::INFINITY && max.z.value() == f64::INFINITY);
+        assert_eq!(min.y, 0.99);
+        assert_eq!(max.y, 1.01);
+    }
 }
@@ -4,12 +4,156 @@
 use crate::float::Float;
 use crate::matrix::Matrix4;
 use crate::ray::Ray;
-use crate::tuple::point;
+use crate::tuple::{Tuple, point, vector};
+use crate::transformation::view_transform;
 use crate::world::World;
 use crate::canvas::Canvas;
+use crate::color::Color;
 use indicatif::ProgressStyle;
 use crate::shape::shape_list::ShapeList;
 use std::thread;
+use std::sync::Mutex;
+use rayon::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{self, Read, Error, ErrorKind};
+use serde::{Serialize, Deserialize};
+
+const DEFAULT_MIN_SAMPLES: usize = 1;
+const DEFAULT_MAX_SAMPLES: usize = 16;
+const DEFAULT_DOF_SAMPLES: usize = 16;
+const BLUE_NOISE_CANDIDATES: usize = 10;
+
+/// Width in millimeters of a 35mm full frame sensor
+pub const SENSOR_FULL_FRAME: f64 = 36.0;
+/// Width in millimeters of an APS-C sensor
+pub const SENSOR_APS_C: f64 = 23.6;
+/// Width in millimeters of a Micro Four Thirds sensor
+pub const SENSOR_MICRO_FOUR_THIRDS: f64 = 17.3;
+
+/// How pixels on the canvas are mapped to rays
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Projection {
+    /// Rays pass through a canvas one unit in front of the camera
+    Perspective,
+    /// Rays cover every direction around the camera, columns map to
+    /// azimuth and rows map to elevation
+    Spherical,
+}
+
+/// Where the rays fired through a pixel are placed inside of it for antialiasing
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum SamplingPattern {
+    /// Regular n x n grid, `Grid(1)` is a single ray through the center
+    Grid(usize),
+    /// n uniformly random positions
+    Random(usize),
+    /// First n points of the 2, 3 Halton sequence, low-discrepancy coverage without clumping
+    Halton(usize),
+    /// n random positions spread apart with best candidate sampling
+    BlueNoise(usize),
+}
+
+impl SamplingPattern {
+    /// Returns the amount of rays fired through each pixel
+    pub fn samples(&self) -> usize {
+        match *self {
+            SamplingPattern::Grid(n) => n * n,
+            SamplingPattern::Random(n) | SamplingPattern::Halton(n) | SamplingPattern::BlueNoise(n) => n,
+        }
+    }
+
+    /// Returns the (x, y) offsets inside of a pixel to fire rays through, each in [0, 1)
+    pub fn offsets(&self, rng: &mut impl Rng) -> Vec<(f64, f64)> {
+        match *self {
+            SamplingPattern::Grid(n) => {
+                let cell_size = 1.0 / n as f64;
+                (0..n * n).map(|i| (((i % n) as f64 + 0.5) * cell_size, ((i / n) as f64 + 0.5) * cell_size)).collect()
+            },
+            SamplingPattern::Random(n) => (0..n).map(|_| (rng.gen::<f64>(), rng.gen::<f64>())).collect(),
+            SamplingPattern::Halton(n) => (1..=n).map(|i| (halton(i, 2), halton(i, 3))).collect(),
+            SamplingPattern::BlueNoise(n) => {
+                // Distance wrapping around the pixel so samples spread evenly up to its edges
+                let distance_squared = |a: (f64, f64), b: (f64, f64)| {
+                    let dx = (a.0 - b.0).abs().min(1.0 - (a.0 - b.0).abs());
+                    let dy = (a.1 - b.1).abs().min(1.0 - (a.1 - b.1).abs());
+                    dx * dx + dy * dy
+                };
+
+                let mut samples: Vec<(f64, f64)> = vec![];
+                while samples.len() < n {
+                    // Keep the candidate farthest from every existing sample
+                    let candidates = samples.len() * BLUE_NOISE_CANDIDATES + 1;
+                    let best = (0..candidates)
+                        .map(|_| (rng.gen::<f64>(), rng.gen::<f64>()))
+                        .map(|candidate| {
+                            let nearest = samples.iter().map(|s| distance_squared(*s, candidate)).fold(f64::INFINITY, f64::min);
+                            (candidate, nearest)
+                        })
+                        .fold(((0.5, 0.5), -1.0), |best, next| if next.1 > best.1 { next } else { best });
+                    samples.push(best.0);
+                }
+                samples
+            },
+        }
+    }
+}
+
+impl Default for SamplingPattern {
+    fn default() -> Self {
+        SamplingPattern::Grid(1)
+    }
+}
+
+/// Returns the `index`th value of the van der Corput sequence in `base`
+fn halton(index: usize, base: usize) -> f64 {
+    let mut result = 0.0;
+    let mut fraction = 1.0 / base as f64;
+    let mut i = index;
+    while i > 0 {
+        result += (i % base) as f64 * fraction;
+        i /= base;
+        fraction /= base as f64;
+    }
+    result
+}
+
+/// Everything needed to reproduce a render exactly, stored as JSON
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RenderConfig {
+    pub canvas_width: i32,
+    pub canvas_height: i32,
+    pub fov: f64,
+    pub max_recursion: i32,
+    /// Jittered rays per pixel, 1 fires a single ray through the pixel's center
+    pub aa_samples: usize,
+    /// Seed for the camera's random sampling
+    pub seed: u64,
+}
+
+impl RenderConfig {
+    pub fn from_json(json: &str) -> io::Result<RenderConfig> {
+        serde_json::from_str(json).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Reads a config written by `to_json`
+    pub fn from_file(path: &str) -> io::Result<RenderConfig> {
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+        RenderConfig::from_json(&contents)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Unable to serialize render config")
+    }
+
+    /// Sets the world's settings that are part of the config
+    pub fn configure_world(&self, world: &mut World) {
+        world.max_recursion = self.max_recursion;
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Camera {
@@ -20,6 +164,23 @@ pub struct Camera {
     pub transform: Matrix4,
     pub half_width: f64,
     pub half_height: f64,
+    /// Diameter of the lens, 0.0 is a perfect pinhole camera
+    pub aperture: f64,
+    /// Distance from the camera to the plane that is in perfect focus
+    pub focus_distance: f64,
+    /// Rays fired through the lens for each pixel when the camera has an aperture
+    pub dof_samples: usize,
+    /// Precomputed (x, y) positions on the unit lens disk
+    pub lens_samples: Vec<(f64, f64)>,
+    /// Rays fired per pixel where a sample map is black
+    pub min_samples: usize,
+    /// Rays fired per pixel where a sample map is white
+    pub max_samples: usize,
+    pub projection: Projection,
+    /// Placement of the rays fired through each pixel
+    pub sampling: SamplingPattern,
+    /// Seeds each pixel's random sampling so renders can be reproduced, `None` uses fresh randomness
+    pub seed: Option<u64>,
 }
 
 impl Camera {
@@ -48,14 +209,130 @@ impl Camera {
             transform: Matrix4::identity(),
             half_width,
             half_height,
+            aperture: 0.0,
+            focus_distance: 1.0,
+            dof_samples: DEFAULT_DOF_SAMPLES,
+            lens_samples: vec![],
+            min_samples: DEFAULT_MIN_SAMPLES,
+            max_samples: DEFAULT_MAX_SAMPLES,
+            projection: Projection::Perspective,
+            sampling: SamplingPattern::default(),
+            seed: None,
+        }
+    }
+
+    /// Creates a camera from a render config looking from `from` towards `to`
+    pub fn from_config(config: &RenderConfig, from: Tuple, to: Tuple, up: Tuple) -> Camera {
+        let mut camera = Camera::new(config.canvas_width, config.canvas_height, config.fov);
+        camera.transform = view_transform(from, to, up);
+        if config.aa_samples > 1 {
+            camera.sampling = SamplingPattern::Random(config.aa_samples);
+        }
+        camera.seed = Some(config.seed);
+        camera
+    }
+
+    /// Creates a thin lens camera with `DEFAULT_DOF_SAMPLES` rays per pixel through the lens,
+    /// see `with_aperture`. An aperture of 0.0 renders the same as a pinhole camera.
+    pub fn new_with_dof(h_size: i32, v_size: i32, field_of_view: f64, aperture: f64, focus_distance: f64) -> Camera {
+        Camera::new(h_size, v_size, field_of_view).with_aperture(aperture, focus_distance, DEFAULT_DOF_SAMPLES)
+    }
+
+    /// Creates a camera that renders a 360 degree panorama of the full sphere
+    /// of directions around it, the center of the image looks down the -z axis
+    pub fn new_spherical(h_size: i32, v_size: i32) -> Camera {
+        let mut camera = Camera::new(h_size, v_size, PI / 2.0);
+        camera.projection = Projection::Spherical;
+        camera
+    }
+
+    /// Builder setting the field of view from a physical lens
+    ///
+    /// # Arguments
+    ///
+    /// * `focal_length` - Focal length of the lens in millimeters
+    /// * `sensor_width` - Width of the sensor in millimeters, e.g. `SENSOR_FULL_FRAME`
+    pub fn with_focal_length(mut self, focal_length: f64, sensor_width: f64) -> Camera {
+        let field_of_view = 2.0 * (sensor_width / (2.0 * focal_length)).atan();
+        let resized = Camera::new(self.h_size, self.v_size, field_of_view);
+        self.field_of_view = resized.field_of_view;
+        self.pixel_size = resized.pixel_size;
+        self.half_width = resized.half_width;
+        self.half_height = resized.half_height;
+        self
+    }
+
+    /// Builder setting the range of rays per pixel used by `render_with_sample_map`
+    pub fn with_samples(mut self, min_samples: usize, max_samples: usize) -> Camera {
+        assert!(min_samples >= 1 && min_samples <= max_samples);
+        self.min_samples = min_samples;
+        self.max_samples = max_samples;
+        self
+    }
+
+    /// Builder setting how rays are placed inside of each pixel for antialiasing,
+    /// the pattern has to fire at least one ray
+    pub fn with_sampling(mut self, sampling: SamplingPattern) -> Camera {
+        assert!(sampling.samples() >= 1);
+        self.sampling = sampling;
+        self
+    }
+
+    /// Builder that turns the camera into a thin lens camera for depth-of-field
+    ///
+    /// The lens is sampled with a stratified grid that is jittered once here and
+    /// reused for every pixel
+    pub fn with_aperture(mut self, aperture: f64, focus_distance: f64, lens_samples: usize) -> Camera {
+        self.aperture = aperture;
+        self.focus_distance = focus_distance;
+        self.dof_samples = lens_samples;
+        self.lens_samples = Camera::stratified_lens_samples(lens_samples);
+        self
+    }
+
+    /// Returns points on the unit disk from a jittered grid using a concentric mapping
+    fn stratified_lens_samples(count: usize) -> Vec<(f64, f64)> {
+        let mut rng = rand::thread_rng();
+        let grid_size = (count as f64).sqrt().ceil() as usize;
+        let cell_size = 1.0 / grid_size as f64;
+
+        let mut samples = vec![];
+        for j in 0..grid_size {
+            for i in 0..grid_size {
+                // Jittered position in [-1, 1] x [-1, 1]
+                let a = ((i as f64 + rng.gen::<f64>()) * cell_size) * 2.0 - 1.0;
+                let b = ((j as f64 + rng.gen::<f64>()) * cell_size) * 2.0 - 1.0;
+
+                // Concentric square to disk mapping keeps the strata intact
+                let (r, theta) = if a == 0.0 && b == 0.0 {
+                    (0.0, 0.0)
+                } else if a.abs() > b.abs() {
+                    (a, (PI / 4.0) * (b / a))
+                } else {
+                    (b, (PI / 2.0) - (PI / 4.0) * (a / b))
+                };
+                samples.push((r * theta.cos(), r * theta.sin()));
+            }
         }
+        samples.truncate(count);
+        samples
     }
 
     /// Returns a ray starting at the camera and passes through the (x, y) pixel
     pub fn ray_for_pixel(&self, x: i32, y: i32) -> Ray {
-        // Offset from the edge of the canvas to the pixel's center
-        let x_offset = (x as f64 + 0.5) * self.pixel_size.value();
-        let y_offset = (y as f64 + 0.5) * self.pixel_size.value();
+        self.ray_for_pixel_offset(x, y, 0.5, 0.5)
+    }
+
+    /// Returns a ray starting at the camera and passes through the (x, y) pixel
+    /// at an offset inside of the pixel, (0.5, 0.5) being the pixel's center
+    pub fn ray_for_pixel_offset(&self, x: i32, y: i32, pixel_x_offset: f64, pixel_y_offset: f64) -> Ray {
+        if self.projection == Projection::Spherical {
+            return self.spherical_ray_for_pixel_offset(x, y, pixel_x_offset, pixel_y_offset);
+        }
+
+        // Offset from the edge of the canvas to the point in the pixel
+        let x_offset = (x as f64 + pixel_x_offset) * self.pixel_size.value();
+        let y_offset = (y as f64 + pixel_y_offset) * self.pixel_size.value();
 
         // Untransformed coordinates of the pixel in world space
         let world_x = self.half_width - x_offset;
@@ -70,7 +347,125 @@ impl Camera {
         Ray::new(origin, direction)
     }
 
-    pub fn render(&self, world: World, shape_list: &mut ShapeList) -> Canvas {
+    /// Returns a ray of a spherical camera, the pixel's horizontal position maps
+    /// to an azimuth in [0, 2π] and its vertical position to an angle in [0, π]
+    /// measured down from straight up
+    fn spherical_ray_for_pixel_offset(&self, x: i32, y: i32, pixel_x_offset: f64, pixel_y_offset: f64) -> Ray {
+        let u = (x as f64 + pixel_x_offset) / self.h_size as f64;
+        let v = (y as f64 + pixel_y_offset) / self.v_size as f64;
+        let azimuth = u * 2.0 * PI;
+        let polar = v * PI;
+
+        let inverse = self.transform.inverse();
+        let direction = vector(polar.sin() * azimuth.sin(), polar.cos(), polar.sin() * azimuth.cos());
+        let origin = inverse * point(0.0, 0.0, 0.0);
+
+        Ray::new(origin, (inverse * direction).normalize())
+    }
+
+    /// Returns a ray starting at a point on the lens that passes through the
+    /// (x, y) pixel's point on the focal plane
+    ///
+    /// `lens_sample` is a point on the unit disk
+    pub fn ray_for_pixel_through_lens(&self, x: i32, y: i32, lens_sample: (f64, f64)) -> Ray {
+        self.ray_for_pixel_offset_through_lens(x, y, 0.5, 0.5, lens_sample)
+    }
+
+    /// Returns a ray starting at a point on the lens that passes through the
+    /// focal plane at an offset inside of the (x, y) pixel
+    pub fn ray_for_pixel_offset_through_lens(&self, x: i32, y: i32, pixel_x_offset: f64, pixel_y_offset: f64, lens_sample: (f64, f64)) -> Ray {
+        let x_offset = (x as f64 + pixel_x_offset) * self.pixel_size.value();
+        let y_offset = (y as f64 + pixel_y_offset) * self.pixel_size.value();
+
+        let world_x = self.half_width - x_offset;
+        let world_y = self.half_height - y_offset;
+
+        // The canvas sits at z = -1, so scaling by the focus distance
+        // gives the point on the focal plane
+        let focal_point = point(world_x * self.focus_distance, world_y * self.focus_distance, -self.focus_distance);
+        let lens_radius = self.aperture / 2.0;
+        let lens_point = point(lens_sample.0 * lens_radius, lens_sample.1 * lens_radius, 0.0);
+
+        let inverse = self.transform.inverse();
+        let pixel = inverse * focal_point;
+        let origin = inverse * lens_point;
+        let direction = (pixel - origin).normalize();
+
+        Ray::new(origin, direction)
+    }
+
+    /// Returns the color seen through the (x, y) pixel, averaging the rays placed
+    /// by the sampling pattern, or the lens samples if the camera has an aperture
+    pub fn color_at_pixel(&self, x: i32, y: i32, world: &World, shape_list: &mut ShapeList) -> Color {
+        let offsets = self.pixel_offsets(x, y, self.sampling);
+        self.color_at_pixel_offsets(x, y, &offsets, world, shape_list)
+    }
+
+    /// Returns the offsets placed by `sampling` inside of the (x, y) pixel
+    fn pixel_offsets(&self, x: i32, y: i32, sampling: SamplingPattern) -> Vec<(f64, f64)> {
+        match self.seed {
+            Some(seed) => {
+                // Every pixel gets its own stream so the result doesn't depend on render order
+                let pixel_index = y as u64 * self.h_size as u64 + x as u64;
+                sampling.offsets(&mut StdRng::seed_from_u64(seed ^ pixel_index.wrapping_mul(0x9E37_79B9_7F4A_7C15)))
+            },
+            None => sampling.offsets(&mut rand::thread_rng()),
+        }
+    }
+
+    /// Returns the color seen through the (x, y) pixel averaging a ray through each offset,
+    /// with an aperture `dof_samples` rays also go through the lens, cycling whichever list is shorter
+    fn color_at_pixel_offsets(&self, x: i32, y: i32, offsets: &[(f64, f64)], world: &World, shape_list: &mut ShapeList) -> Color {
+        if self.aperture <= 0.0 || self.dof_samples == 0 || self.projection == Projection::Spherical {
+            let mut color = Color::black();
+            for (x_offset, y_offset) in offsets.iter() {
+                let ray = self.ray_for_pixel_offset(x, y, *x_offset, *y_offset);
+                color = color + world.color_at(&ray, shape_list);
+            }
+            return color * (1.0 / offsets.len() as f64);
+        }
+
+        // Lens samples are only precomputed by `with_aperture`, an aperture or sample count
+        // set on the fields directly gets fresh samples for the pixel
+        let fresh_samples;
+        let lens_samples = if self.lens_samples.len() == self.dof_samples {
+            &self.lens_samples
+        } else {
+            fresh_samples = Camera::stratified_lens_samples(self.dof_samples);
+            &fresh_samples
+        };
+
+        // Each lens sample goes through the next pixel offset
+        let rays = offsets.len().max(lens_samples.len());
+        let mut color = Color::black();
+        for i in 0..rays {
+            let (x_offset, y_offset) = offsets[i % offsets.len()];
+            let sample = lens_samples[i % lens_samples.len()];
+            let ray = self.ray_for_pixel_offset_through_lens(x, y, x_offset, y_offset, sample);
+            color = color + world.color_at(&ray, shape_list);
+        }
+        color * (1.0 / rays as f64)
+    }
+
+    /// Returns the amount of rays to fire through the (x, y) pixel
+    /// given a grayscale sample map
+    pub fn samples_for_pixel(&self, sample_map: &Canvas, x: i32, y: i32) -> usize {
+        let color = sample_map.pixel_at(y, x);
+        let weight = Float((color.red + color.green + color.blue).value() / 3.0).clamp(0.0, 1.0);
+        let extra_samples = ((self.max_samples - self.min_samples) as f64 * weight).round() as usize;
+        self.min_samples + extra_samples
+    }
+
+    /// Renders the world firing a varying amount of rays per pixel
+    ///
+    /// `sample_map` is a grayscale canvas the size of the camera where black pixels
+    /// get `min_samples` rays and white pixels get `max_samples` rays, so areas
+    /// like caustics and sharp reflections can be given more samples.
+    /// Every pixel's rays are jittered randomly, whatever the camera's own sampling pattern is
+    pub fn render_with_sample_map(&self, world: World, shape_list: &mut ShapeList, sample_map: &Canvas) -> Canvas {
+        assert_eq!(sample_map.width, self.h_size);
+        assert_eq!(sample_map.height, self.v_size);
+
         let mut image = Canvas::new(self.h_size, self.v_size);
 
         let pb = indicatif::ProgressBar::new(self.v_size as u64);
@@ -79,8 +474,10 @@ impl Camera {
 
         for y in 0..self.v_size {
             for x in 0..self.h_size {
-                let ray = self.ray_for_pixel(x, y);
-                let color = world.color_at(&ray, shape_list);
+                // Samples are jittered inside of the pixel, still going through the lens
+                let samples = self.samples_for_pixel(sample_map, x, y);
+                let offsets = self.pixel_offsets(x, y, SamplingPattern::Random(samples));
+                let color = self.color_at_pixel_offsets(x, y, &offsets, &world, shape_list);
                 image.write_pixel(y, x, &color);
             }
             pb.inc(1);
@@ -89,6 +486,36 @@ impl Camera {
         image
     }
 
+    /// Renders the world with its rows spread over rayon's global thread pool
+    pub fn render(&self, world: World, shape_list: &mut ShapeList) -> Canvas {
+        let pb = indicatif::ProgressBar::new(self.v_size as u64);
+        pb.set_style(ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] {bar:50} {pos:>7}/{len:7} {msg}"));
+
+        // Shapes can be sent between threads but not shared,
+        // so each rayon job renders its rows with its own copy of the scene
+        let scene = Mutex::new((world, shape_list.clone()));
+        let rows: Vec<Vec<Color>> = (0..self.v_size).into_par_iter()
+            .map_init(|| scene.lock().unwrap().clone(), |(world, shape_list), y| {
+                let row = (0..self.h_size).map(|x| self.color_at_pixel(x, y, world, shape_list)).collect();
+                pb.inc(1);
+                row
+            })
+            .collect();
+        pb.finish_with_message("Finished Rendering!");
+
+        let mut image = Canvas::new(self.h_size, self.v_size);
+        image.pixels = rows;
+        image
+    }
+
+    /// Renders the world like `render` on a thread pool of `num_threads` threads
+    pub fn render_with_threads(&self, world: World, shape_list: &mut ShapeList, num_threads: usize) -> Canvas {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(num_threads).build()
+            .expect("Unable to create render thread pool");
+        pool.install(|| self.render(world, shape_list))
+    }
+
     pub fn multithead_render(&self, world: World, thread_count: i32, shape_list: &mut ShapeList) -> Canvas {
 
         let mut thread_handles = vec![];
@@ -102,8 +529,7 @@ impl Camera {
                 for y in 0..thread_camera.v_size {
                     for x in 0..thread_camera.h_size {
                         if x % thread_count == i {
-                            let ray = thread_camera.ray_for_pixel(x, y);
-                            let color = thread_world.color_at(&ray, &mut thread_shape_list);
+                            let color = thread_camera.color_at_pixel(x, y, &thread_world, &mut thread_shape_list);
                             thread_image.write_pixel(y, x, &color);
                         }
                     }
@@ -122,8 +548,7 @@ impl Camera {
         for y in 0..self.v_size {
             for x in 0..self.h_size {
                 if x % thread_count == thread_count-1 {
-                    let ray = self.ray_for_pixel(x, y);
-                    let color = world.color_at(&ray, shape_list);
+                    let color = self.color_at_pixel(x, y, &world, shape_list);
                     image.write_pixel(y, x, &color);
                 }
             }
@@ -145,11 +570,14 @@ impl Camera {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::f64::consts::PI;
-    use crate::transformation::{rotation_y, translation, view_transform};
+    use crate::assert_color_approx_eq;
+    use crate::transformation::{rotation_y, translation, scaling, view_transform};
+    use crate::light::Light;
+    use crate::shape::sphere::Sphere;
     use crate::color::Color;
     use crate::tuple::vector;
     use crate::shape::shape_list::ShapeList;
+    use crate::FLOAT_THRESHOLD;
 
     #[test]
     fn camera_creation() {
@@ -169,6 +597,17 @@ mod tests {
         assert_eq!(c.pixel_size, 0.01);
     }
 
+    #[test]
+    fn camera_with_focal_length() {
+        // A 18mm lens on a full frame sensor is exactly 90 degrees wide
+        let c = Camera::new(200, 125, 1.0).with_focal_length(18.0, SENSOR_FULL_FRAME);
+        assert_eq!(c.field_of_view, PI / 2.0);
+        assert_eq!(c.pixel_size, 0.01);
+
+        let c = Camera::new(200, 125, 1.0).with_focal_length(50.0, SENSOR_FULL_FRAME);
+        assert_eq!(c.field_of_view, 0.6911112);
+    }
+
     #[test]
     fn camera_rays() {
         // Ray through center of canvas
@@ -201,6 +640,232 @@ mod tests {
         let up = vector(0.0, 1.0, 0.0);
         c.transform = view_transform(from, to, up);
         let image = c.render(w, &mut shape_list);
-        assert_eq!(image.pixel_at(5, 5), &Color::new(0.38066, 0.47583, 0.2855));
+        assert_color_approx_eq!(image.pixel_at(5, 5), &Color::new(0.38066, 0.47583, 0.2855), 0.0001);
+    }
+
+    #[test]
+    fn camera_render_with_threads() {
+        let mut shape_list = ShapeList::new();
+        let w = World::default_world(&mut shape_list);
+        let mut c = Camera::new(21, 15, PI/2.0).with_sampling(SamplingPattern::Grid(2));
+        c.transform = view_transform(point(0.0, 0.5, -5.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0));
+
+        let single = c.render_with_threads(w.clone(), &mut shape_list, 1);
+        let multi = c.render_with_threads(w.clone(), &mut shape_list, 4);
+        assert_eq!(single.pixels, multi.pixels);
+        assert_eq!(c.render(w.clone(), &mut shape_list).pixels, single.pixels);
+        assert_eq!(single.pixel_at(7, 10), &c.color_at_pixel(10, 7, &w, &mut shape_list));
+    }
+
+    #[test]
+    fn camera_spherical_rays() {
+        let c = Camera::new_spherical(4, 2);
+        assert_eq!(c.projection, Projection::Spherical);
+
+        let r = c.ray_for_pixel(1, 0);
+        assert_eq!(r.origin, point(0.0, 0.0, 0.0));
+        assert_eq!(r.direction, vector(0.5, 2.0f64.sqrt()/2.0, -0.5));
+
+        let r = c.ray_for_pixel(2, 1);
+        assert_eq!(r.direction, vector(-0.5, -2.0f64.sqrt()/2.0, -0.5));
+
+        // The center of the image looks forward and the edges look behind
+        let r = c.ray_for_pixel_offset(2, 1, 0.0, 0.0);
+        assert_eq!(r.direction, vector(0.0, 0.0, -1.0));
+        let r = c.ray_for_pixel_offset(0, 1, 0.0, 0.0);
+        assert_eq!(r.direction, vector(0.0, 0.0, 1.0));
+
+        // Ray at a transformed camera
+        let mut c = Camera::new_spherical(4, 2);
+        c.transform = rotation_y(PI/4.0) * translation(0.0, -2.0, 5.0);
+        let r = c.ray_for_pixel_offset(2, 1, 0.0, 0.0);
+        assert_eq!(r.origin, point(0.0, 2.0, -5.0));
+        assert_eq!(r.direction, vector(2.0f64.sqrt()/2.0, 0.0, -2.0f64.sqrt()/2.0));
+    }
+
+    #[test]
+    fn camera_with_aperture() {
+        let c = Camera::new(201, 101, PI/2.0).with_aperture(0.5, 4.0, 16);
+        assert_eq!(c.aperture, 0.5);
+        assert_eq!(c.focus_distance, 4.0);
+        assert_eq!(c.lens_samples.len(), 16);
+        for sample in c.lens_samples.iter() {
+            assert!(sample.0 * sample.0 + sample.1 * sample.1 <= 1.0 + FLOAT_THRESHOLD);
+        }
+
+        // Every lens ray converges on the same point of the focal plane
+        let pinhole = c.ray_for_pixel(0, 0);
+        let focus = pinhole.position(4.0 / -pinhole.direction.z.value());
+        for sample in c.lens_samples.iter() {
+            let r = c.ray_for_pixel_through_lens(0, 0, *sample);
+            let t = (focus.z - r.origin.z).value() / r.direction.z.value();
+            assert_eq!(r.position(t), focus);
+        }
+
+        // The center of the lens is the pinhole ray
+        let r = c.ray_for_pixel_through_lens(100, 50, (0.0, 0.0));
+        assert_eq!(r.origin, point(0.0, 0.0, 0.0));
+        assert_eq!(r.direction, vector(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn camera_new_with_dof() {
+        let c = Camera::new(11, 11, PI/2.0);
+        assert_eq!(c.aperture, 0.0);
+        assert_eq!(c.focus_distance, 1.0);
+        assert_eq!(c.dof_samples, 16);
+
+        let c = Camera::new_with_dof(11, 11, PI/2.0, 1.0, 10.0);
+        assert_eq!(c.aperture, 1.0);
+        assert_eq!(c.focus_distance, 10.0);
+        assert_eq!(c.dof_samples, DEFAULT_DOF_SAMPLES);
+        assert_eq!(c.lens_samples.len(), DEFAULT_DOF_SAMPLES);
+
+        // No aperture is the same as a pinhole camera
+        let mut shape_list = ShapeList::new();
+        let w = World::default_world(&mut shape_list);
+        let mut pinhole = Camera::new(11, 11, PI/2.0);
+        pinhole.transform = view_transform(point(0.0, 0.0, -5.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0));
+        let mut no_aperture = Camera::new_with_dof(11, 11, PI/2.0, 0.0, 5.0);
+        no_aperture.transform = pinhole.transform;
+        assert_eq!(no_aperture.color_at_pixel(5, 5, &w, &mut shape_list), pinhole.color_at_pixel(5, 5, &w, &mut shape_list));
+
+        // A sphere in front of the focal plane spreads into pixels a pinhole camera sees past it
+        let mut shape_list = ShapeList::new();
+        let mut w = World::new();
+        w.lights.push(Light::point_light(&point(-10.0, 10.0, 10.0), &Color::new(1.0, 1.0, 1.0)));
+        let mut sphere = Sphere::new(&mut shape_list);
+        sphere.transform = translation(0.0, 0.0, -2.0) * scaling(0.5, 0.5, 0.5);
+        w.objects.push(Box::new(sphere));
+        assert!(Camera::new(11, 11, PI/2.0).color_at_pixel(3, 5, &w, &mut shape_list).is_black());
+        assert!(!c.color_at_pixel(3, 5, &w, &mut shape_list).is_black());
+
+        // Setting the fields directly blurs the same way
+        let mut fields = Camera::new(11, 11, PI/2.0);
+        fields.aperture = 1.0;
+        fields.focus_distance = 10.0;
+        fields.dof_samples = 64;
+        assert!(!fields.color_at_pixel(3, 5, &w, &mut shape_list).is_black());
+    }
+
+    #[test]
+    fn camera_samples_for_pixel() {
+        let c = Camera::new(2, 1, PI/2.0).with_samples(1, 9);
+        let mut sample_map = Canvas::new(2, 1);
+        sample_map.write_pixel(0, 1, &Color::new(0.5, 0.5, 0.5));
+        assert_eq!(c.samples_for_pixel(&sample_map, 0, 0), 1);
+        assert_eq!(c.samples_for_pixel(&sample_map, 1, 0), 5);
+
+        sample_map.write_pixel(0, 1, &Color::new(2.0, 2.0, 2.0));
+        assert_eq!(c.samples_for_pixel(&sample_map, 1, 0), 9);
+    }
+
+    #[test]
+    fn camera_render_with_sample_map() {
+        let mut shape_list = ShapeList::new();
+        let w = World::default_world(&mut shape_list);
+        let mut c = Camera::new(11, 11, PI/2.0).with_samples(2, 6).with_sampling(SamplingPattern::Grid(3));
+        c.transform = view_transform(point(0.0, 0.0, -5.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0));
+        c.seed = Some(3);
+
+        // Black pixels fire exactly min_samples rays and white pixels exactly max_samples,
+        // ignoring the camera's own sampling pattern
+        let mut sample_map = Canvas::new(11, 11);
+        sample_map.write_pixel(5, 6, &Color::white());
+        let image = c.render_with_sample_map(w.clone(), &mut shape_list, &sample_map);
+        let min = c.clone().with_sampling(SamplingPattern::Random(2));
+        let max = c.clone().with_sampling(SamplingPattern::Random(6));
+        assert_eq!(*image.pixel_at(5, 5), min.color_at_pixel(5, 5, &w, &mut shape_list));
+        assert_eq!(*image.pixel_at(5, 6), max.color_at_pixel(6, 5, &w, &mut shape_list));
+        assert_ne!(*image.pixel_at(5, 5), c.color_at_pixel(5, 5, &w, &mut shape_list));
+        assert_color_approx_eq!(image.pixel_at(5, 5), &Color::new(0.38066, 0.47583, 0.2855), 0.05);
+
+        // Extra samples still go through the lens, a sphere in front of the focal plane
+        // spreads into pixels a pinhole camera sees past it
+        let mut shape_list = ShapeList::new();
+        let mut w = World::new();
+        w.lights.push(Light::point_light(&point(-10.0, 10.0, 10.0), &Color::new(1.0, 1.0, 1.0)));
+        let mut sphere = Sphere::new(&mut shape_list);
+        sphere.transform = translation(0.0, 0.0, -2.0) * scaling(0.5, 0.5, 0.5);
+        w.objects.push(Box::new(sphere));
+        let mut c = Camera::new_with_dof(11, 11, PI/2.0, 1.0, 10.0).with_samples(1, 8);
+        c.seed = Some(7);
+        let mut sample_map = Canvas::new(11, 11);
+        sample_map.write_pixel(5, 3, &Color::white());
+        let image = c.render_with_sample_map(w.clone(), &mut shape_list, &sample_map);
+        assert!(!image.pixel_at(5, 3).is_black());
+        // The same rays as rendering the pixel with that many jittered samples
+        let jittered = c.clone().with_sampling(SamplingPattern::Random(8));
+        assert_eq!(*image.pixel_at(5, 3), jittered.color_at_pixel(3, 5, &w, &mut shape_list));
+        assert!(Camera::new(11, 11, PI/2.0).render_with_sample_map(w, &mut shape_list, &sample_map).pixel_at(5, 3).is_black());
+    }
+
+    #[test]
+    fn camera_sampling_patterns() {
+        let mut rng = rand::thread_rng();
+        assert_eq!(Camera::new(10, 10, PI/2.0).sampling, SamplingPattern::Grid(1));
+        assert_eq!(SamplingPattern::Grid(1).offsets(&mut rng), vec![(0.5, 0.5)]);
+        assert_eq!(SamplingPattern::Grid(2).offsets(&mut rng), vec![(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)]);
+        assert_eq!(SamplingPattern::Halton(4).offsets(&mut rng),
+                   vec![(0.5, 1.0 / 3.0), (0.25, 2.0 / 3.0), (0.75, 1.0 / 9.0), (0.125, 4.0 / 9.0)]);
+
+        for pattern in [SamplingPattern::Random(16), SamplingPattern::BlueNoise(16)].iter() {
+            let offsets = pattern.offsets(&mut rng);
+            assert_eq!(offsets.len(), 16);
+            assert!(offsets.iter().all(|(x, y)| (0.0..1.0).contains(x) && (0.0..1.0).contains(y)));
+        }
+
+        // Antialiased pixels average rays around the center
+        let mut shape_list = ShapeList::new();
+        let w = World::default_world(&mut shape_list);
+        let mut c = Camera::new(11, 11, PI/2.0).with_sampling(SamplingPattern::Halton(16));
+        c.transform = view_transform(point(0.0, 0.0, -5.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0));
+        assert_color_approx_eq!(c.color_at_pixel(5, 5, &w, &mut shape_list), &Color::new(0.38066, 0.47583, 0.2855), 0.01);
+        // A pixel on the sphere's edge mixes the sphere with the background
+        // where a single center ray would miss the sphere
+        let edge = c.color_at_pixel(6, 6, &w, &mut shape_list);
+        assert!(!edge.is_black());
+        let center_only = c.clone().with_sampling(SamplingPattern::Grid(1));
+        assert!(center_only.color_at_pixel(6, 6, &w, &mut shape_list).is_black());
+
+        assert_eq!(SamplingPattern::Grid(3).samples(), 9);
+        assert_eq!(SamplingPattern::BlueNoise(5).samples(), 5);
+        assert_eq!(SamplingPattern::Random(0).samples(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn camera_sampling_pattern_without_samples() {
+        Camera::new(10, 10, PI/2.0).with_sampling(SamplingPattern::Halton(0));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn camera_render_config() {
+        let config = RenderConfig {canvas_width: 11, canvas_height: 11, fov: PI/2.0, max_recursion: 3, aa_samples: 8, seed: 42};
+        let json = config.to_json();
+        assert_eq!(RenderConfig::from_json(&json).unwrap(), config);
+        assert_eq!(RenderConfig::from_json("{\"canvas_width\": 11}").unwrap_err().kind(), ErrorKind::InvalidData);
+
+        let path = std::env::temp_dir().join("raytracer_render_config.json");
+        crate::file::write_to_file(json, path.to_str().unwrap().to_string());
+        assert_eq!(RenderConfig::from_file(path.to_str().unwrap()).unwrap(), config);
+
+        let mut shape_list = ShapeList::new();
+        let mut w = World::default_world(&mut shape_list);
+        config.configure_world(&mut w);
+        assert_eq!(w.max_recursion, 3);
+
+        let from = point(0.0, 0.0, -5.0);
+        let to = point(0.0, 0.0, 0.0);
+        let up = vector(0.0, 1.0, 0.0);
+        let c = Camera::from_config(&config, from, to, up);
+        assert_eq!(c.h_size, 11);
+        assert_eq!(c.field_of_view, PI/2.0);
+        assert_eq!(c.transform, view_transform(from, to, up));
+        assert_eq!(c.sampling, SamplingPattern::Random(8));
+
+        // The same seed renders the same jittered pixel every time
+        let edge = c.color_at_pixel(6, 6, &w, &mut shape_list);
+        assert_eq!(Camera::from_config(&config, from, to, up).color_at_pixel(6, 6, &w, &mut shape_list), edge);
+    }
+}
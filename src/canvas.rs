@@ -4,6 +4,13 @@
 use std::iter::Iterator;
 use super::color::Color;
 
+/// Operator used to bring a rendered image into displayable range
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ToneMapOperator {
+    Aces,
+    Reinhard,
+}
+
 #[derive(Debug, Clone)]
 pub struct Canvas {
     pub width: i32,
@@ -23,13 +30,26 @@ impl Canvas {
         &self.pixels[row as usize][col as usize]
     }
 
+    /// Writes the color at (row, col), writes outside of the canvas are silently ignored
     pub fn write_pixel(&mut self, row: i32, col: i32, color: &Color) {
         // ignore writing outside of canvas
-        if row >= 0 && row < self.height && col >= 0 && col < self.width {
+        if self.contains(row, col) {
             self.pixels[row as usize][col as usize] = Color::new(color.red.value(), color.green.value(), color.blue.value());
         }
     }
 
+    /// Same as `write_pixel` but panics on writes outside of the canvas in debug builds,
+    /// release builds ignore them
+    pub fn write_pixel_checked(&mut self, row: i32, col: i32, color: &Color) {
+        debug_assert!(self.contains(row, col),
+                      "pixel ({}, {}) is outside of the {}x{} canvas", row, col, self.width, self.height);
+        self.write_pixel(row, col, color);
+    }
+
+    fn contains(&self, row: i32, col: i32) -> bool {
+        row >= 0 && row < self.height && col >= 0 && col < self.width
+    }
+
     pub fn combine(canvas_1: &Canvas, canvas_2: &Canvas) -> Canvas {
         let mut canvas = Canvas::new(canvas_1.width, canvas_1.height);
         let background = canvas_1.background_color;
@@ -48,7 +68,148 @@ impl Canvas {
 //        canvas_2
     }
 
+    /// Tiles canvases left to right, top to bottom into a grid
+    ///
+    /// Each cell is the size of the largest canvas and cells are separated
+    /// by `padding` pixels of the background color
+    /// # Arguments
+    /// * `images` Canvases to tile
+    /// * `columns` Amount of canvases per row
+    /// * `padding` Pixels between neighbouring cells
+    pub fn contact_sheet(images: &[Canvas], columns: usize, padding: usize) -> Canvas {
+        assert!(columns > 0);
+        if images.is_empty() {
+            return Canvas::new(0, 0);
+        }
+
+        let columns = columns.min(images.len());
+        let rows = images.len().div_ceil(columns);
+        let cell_width = images.iter().map(|c| c.width).max().unwrap();
+        let cell_height = images.iter().map(|c| c.height).max().unwrap();
+        let padding = padding as i32;
+
+        let width = columns as i32 * (cell_width + padding) - padding;
+        let height = rows as i32 * (cell_height + padding) - padding;
+        let mut sheet = Canvas::new(width, height);
+
+        for (n, image) in images.iter().enumerate() {
+            let left = (n % columns) as i32 * (cell_width + padding);
+            let top = (n / columns) as i32 * (cell_height + padding);
+            for row in 0..image.height {
+                for col in 0..image.width {
+                    sheet.write_pixel(top + row, left + col, image.pixel_at(row, col));
+                }
+            }
+        }
+        sheet
+    }
+
+    /// Adds every pixel of `other` scaled by `weight` to this canvas
+    ///
+    /// Accumulating n frames with a weight of 1/n averages them, which is
+    /// the update step of progressive rendering. Panics if the sizes differ.
+    pub fn accumulate(&mut self, other: &Canvas, weight: f64) {
+        assert!(self.width == other.width && self.height == other.height, "canvases must be the same size");
+        for (pixel, other_pixel) in self.pixels.iter_mut().flatten().zip(other.pixels.iter().flatten()) {
+            *pixel = *pixel + *other_pixel * weight;
+        }
+    }
+
+    /// Returns the color at a fractional column `x` and row `y`, bilinearly
+    /// interpolated between the 4 nearest pixels
+    ///
+    /// Pixel (row, col) sits at x = col, y = row, coordinates outside of the canvas
+    /// are clamped to its edge. Panics on an empty canvas.
+    pub fn sample(&self, x: f64, y: f64) -> Color {
+        assert!(self.width > 0 && self.height > 0, "can't sample an empty canvas");
+        let x = x.clamp(0.0, (self.width - 1) as f64);
+        let y = y.clamp(0.0, (self.height - 1) as f64);
+
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width as usize - 1), (y0 + 1).min(self.height as usize - 1));
+        let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+
+        let top = Color::lerp(self.pixels[y0][x0], self.pixels[y0][x1], fx);
+        let bottom = Color::lerp(self.pixels[y1][x0], self.pixels[y1][x1], fx);
+        Color::lerp(top, bottom, fy)
+    }
+
+    /// Returns the red component of every pixel, row by row
+    pub fn channel_r(&self) -> Vec<f64> {
+        self.pixels.iter().flatten().map(|pixel| pixel.red.value()).collect()
+    }
+
+    /// Returns the green component of every pixel, row by row
+    pub fn channel_g(&self) -> Vec<f64> {
+        self.pixels.iter().flatten().map(|pixel| pixel.green.value()).collect()
+    }
+
+    /// Returns the blue component of every pixel, row by row
+    pub fn channel_b(&self) -> Vec<f64> {
+        self.pixels.iter().flatten().map(|pixel| pixel.blue.value()).collect()
+    }
+
+    /// Creates a canvas from row by row channels like those from `channel_r`
+    ///
+    /// Panics if a channel doesn't have `width * height` values
+    pub fn from_channels(width: i32, height: i32, r: &[f64], g: &[f64], b: &[f64]) -> Canvas {
+        let size = (width * height) as usize;
+        assert!(r.len() == size && g.len() == size && b.len() == size, "channels must have width * height values");
+
+        let mut canvas = Canvas::new(width, height);
+        for (i, pixel) in canvas.pixels.iter_mut().flatten().enumerate() {
+            *pixel = Color::new(r[i], g[i], b[i]);
+        }
+        canvas
+    }
+
+    /// Returns a copy of the canvas with every pixel tone mapped
+    pub fn tone_map(&self, operator: ToneMapOperator) -> Canvas {
+        let mut canvas = self.clone();
+        for row in canvas.pixels.iter_mut() {
+            for pixel in row.iter_mut() {
+                *pixel = match operator {
+                    ToneMapOperator::Aces => pixel.tone_map_aces(),
+                    ToneMapOperator::Reinhard => pixel.tone_map_reinhard(),
+                };
+            }
+        }
+        canvas
+    }
+
+    /// Returns a copy of the canvas with every color component clamped to [0.0, 1.0]
+    pub fn clamp(&self) -> Canvas {
+        let mut canvas = self.clone();
+        for row in canvas.pixels.iter_mut() {
+            for pixel in row.iter_mut() {
+                *pixel = Color::new(pixel.red.clamp(0.0, 1.0), pixel.green.clamp(0.0, 1.0), pixel.blue.clamp(0.0, 1.0));
+            }
+        }
+        canvas
+    }
+
+    /// Returns the canvas encoded as an 8-bit RGB PNG, colors are clamped to [0.0, 1.0] first
+    pub fn to_png_bytes(&self) -> Vec<u8> {
+        let canvas = self.clamp();
+        let mut data = Vec::with_capacity((self.width * self.height * 3) as usize);
+        for color in canvas.pixels.iter().flatten() {
+            data.push((color.red.value() * 255.0).round() as u8);
+            data.push((color.green.value() * 255.0).round() as u8);
+            data.push((color.blue.value() * 255.0).round() as u8);
+        }
+
+        let mut bytes = vec![];
+        let mut encoder = png::Encoder::new(&mut bytes, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().expect("Unable to write PNG header");
+        writer.write_image_data(&data).expect("Unable to encode PNG");
+        writer.finish().expect("Unable to finish PNG");
+        bytes
+    }
+
     pub fn to_ppm(&self) -> String {
+        let canvas = self.clamp();
         let mut str = String::new();
         let max_color_val = 255.0;
 
@@ -61,10 +222,10 @@ impl Canvas {
         let mut line = String::new();
         for i in 0..self.height {
             for j in 0..self.width {
-                let color = self.pixel_at(i, j);
-                let red = (&color.red * max_color_val).clamp(0.0, max_color_val);
-                let green = (&color.green * max_color_val).clamp(0.0, max_color_val);
-                let blue = (&color.blue * max_color_val).clamp(0.0, max_color_val);
+                let color = canvas.pixel_at(i, j);
+                let red = (color.red * max_color_val).value();
+                let green = (color.green * max_color_val).value();
+                let blue = (color.blue * max_color_val).value();
 
                 // Ensure that no line is greater than 70 characters
                 // Although I think Preview does not have an issue regardless
@@ -125,6 +286,142 @@ mod tests {
 
         &c.write_pixel(2, 3, &red);
         assert_eq!(c.pixel_at(2, 3), &red);
+
+        // Writes outside of the canvas are ignored
+        c.write_pixel(20, 3, &red);
+        c.write_pixel(-1, 0, &red);
+
+        c.write_pixel_checked(19, 9, &red);
+        assert_eq!(c.pixel_at(19, 9), &red);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn canvas_write_pixel_checked_out_of_bounds() {
+        let mut c = Canvas::new(10, 20);
+        c.write_pixel_checked(20, 3, &Color::white());
+    }
+
+    #[test]
+    fn canvas_tone_map() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel(0, 1, &Color::new(1.0, 3.0, 0.0));
+
+        let mapped = c.tone_map(ToneMapOperator::Reinhard);
+        assert_eq!(mapped.pixel_at(0, 0), &Color::black());
+        assert_eq!(mapped.pixel_at(0, 1), &Color::new(0.5, 0.75, 0.0));
+
+        let mapped = c.tone_map(ToneMapOperator::Aces);
+        assert_eq!(mapped.pixel_at(0, 1), &Color::new(1.0, 3.0, 0.0).tone_map_aces());
+        // The original canvas is left untouched
+        assert_eq!(c.pixel_at(0, 1), &Color::new(1.0, 3.0, 0.0));
+    }
+
+    #[test]
+    fn canvas_clamp() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel(0, 0, &Color::new(0.2, 0.5, 1.0));
+        c.write_pixel(0, 1, &Color::new(1.5, -0.5, 3.0));
+
+        let clamped = c.clamp();
+        assert_eq!(clamped.pixel_at(0, 0), &Color::new(0.2, 0.5, 1.0));
+        assert_eq!(clamped.pixel_at(0, 1), &Color::new(1.0, 0.0, 1.0));
+        assert_eq!(c.pixel_at(0, 1), &Color::new(1.5, -0.5, 3.0));
+        assert_eq!(c.to_ppm(), clamped.to_ppm());
+    }
+
+    #[test]
+    fn canvas_accumulate() {
+        let mut frames = [Canvas::new(2, 1), Canvas::new(2, 1), Canvas::new(2, 1)];
+        frames[0].write_pixel(0, 0, &Color::new(0.3, 0.0, 0.6));
+        frames[1].write_pixel(0, 0, &Color::new(0.6, 0.3, 0.0));
+        frames[2].write_pixel(0, 1, &Color::new(0.9, 0.9, 0.9));
+
+        let mut average = Canvas::new(2, 1);
+        for frame in frames.iter() {
+            average.accumulate(frame, 1.0 / frames.len() as f64);
+        }
+        assert_eq!(average.pixel_at(0, 0), &Color::new(0.3, 0.1, 0.2));
+        assert_eq!(average.pixel_at(0, 1), &Color::new(0.3, 0.3, 0.3));
+    }
+
+    #[test]
+    #[should_panic]
+    fn canvas_accumulate_wrong_size() {
+        Canvas::new(2, 2).accumulate(&Canvas::new(2, 1), 1.0);
+    }
+
+    #[test]
+    fn canvas_sample() {
+        let mut c = Canvas::new(2, 2);
+        c.write_pixel(0, 0, &Color::new(1.0, 0.0, 0.0));
+        c.write_pixel(0, 1, &Color::new(0.0, 1.0, 0.0));
+        c.write_pixel(1, 0, &Color::new(0.0, 0.0, 1.0));
+        c.write_pixel(1, 1, &Color::new(1.0, 1.0, 1.0));
+
+        // Whole coordinates land exactly on pixels
+        assert_eq!(c.sample(1.0, 0.0), Color::new(0.0, 1.0, 0.0));
+        assert_eq!(c.sample(0.0, 1.0), Color::new(0.0, 0.0, 1.0));
+
+        assert_eq!(c.sample(0.5, 0.0), Color::new(0.5, 0.5, 0.0));
+        assert_eq!(c.sample(0.5, 0.5), Color::new(0.5, 0.5, 0.5));
+        assert_eq!(c.sample(0.25, 1.0), Color::new(0.25, 0.25, 1.0));
+
+        // Outside of the canvas clamps to the edge
+        assert_eq!(c.sample(-3.0, 0.0), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(c.sample(5.0, 5.0), Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn canvas_channels() {
+        let mut c = Canvas::new(2, 2);
+        c.write_pixel(0, 1, &Color::new(1.0, 0.5, 0.25));
+        c.write_pixel(1, 0, &Color::new(0.0, 2.0, 0.0));
+        assert_eq!(c.channel_r(), vec![0.0, 1.0, 0.0, 0.0]);
+        assert_eq!(c.channel_g(), vec![0.0, 0.5, 2.0, 0.0]);
+        assert_eq!(c.channel_b(), vec![0.0, 0.25, 0.0, 0.0]);
+
+        // Splitting and merging is lossless
+        let merged = Canvas::from_channels(2, 2, &c.channel_r(), &c.channel_g(), &c.channel_b());
+        assert_eq!(merged.pixels, c.pixels);
+
+        // Channels can be swapped around
+        let swapped = Canvas::from_channels(2, 2, &c.channel_b(), &c.channel_g(), &c.channel_r());
+        assert_eq!(swapped.pixel_at(0, 1), &Color::new(0.25, 0.5, 1.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn canvas_from_channels_wrong_size() {
+        Canvas::from_channels(2, 2, &[0.0; 4], &[0.0; 3], &[0.0; 4]);
+    }
+
+    #[test]
+    fn canvas_contact_sheet() {
+        let red = Color::new(1.0, 0.0, 0.0);
+        let blue = Color::new(0.0, 0.0, 1.0);
+        let mut a = Canvas::new(2, 2);
+        a.write_pixel(0, 0, &red);
+        let mut b = Canvas::new(3, 1);
+        b.write_pixel(0, 2, &blue);
+        let c = Canvas::new(1, 1);
+
+        let sheet = Canvas::contact_sheet(&[a.clone(), b.clone(), c], 2, 1);
+        assert_eq!(sheet.width, 7);
+        assert_eq!(sheet.height, 5);
+        assert_eq!(sheet.pixel_at(0, 0), &red);
+        assert_eq!(sheet.pixel_at(0, 6), &blue);
+        assert_eq!(sheet.pixel_at(3, 0), &Color::black());
+
+        // More columns than images shrinks the grid to a single row
+        let sheet = Canvas::contact_sheet(&[a, b], 4, 0);
+        assert_eq!(sheet.width, 6);
+        assert_eq!(sheet.height, 2);
+        assert_eq!(sheet.pixel_at(0, 5), &blue);
+
+        let sheet = Canvas::contact_sheet(&[], 2, 1);
+        assert_eq!(sheet.width, 0);
     }
 
     #[test]
@@ -3,6 +3,7 @@
 
 use std::ops;
 use super::float::Float;
+use crate::FLOAT_THRESHOLD;
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct Color {
@@ -24,6 +25,12 @@ impl Color {
         Color {red: Float(r), green: Float(g), blue: Float(b)}
     }
 
+    /// Returns the color as a hex string like "FF0000", clamping components to [0.0, 1.0]
+    pub fn to_hex(&self) -> String {
+        let byte = |c: &Float| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        format!("{:02X}{:02X}{:02X}", byte(&self.red), byte(&self.green), byte(&self.blue))
+    }
+
     // Common colors
 
     pub fn black() -> Color {
@@ -33,9 +40,62 @@ impl Color {
     pub fn white() -> Color {
         Color::new(1.0, 1.0, 1.0)
     }
+
+    /// Returns the color `t` of the way from `a` to `b`
+    pub fn lerp(a: Color, b: Color, t: f64) -> Color {
+        a + (b - a) * t
+    }
+
+    /// Returns true if every component is within `eps` of the other color's
+    pub fn approx_eq(&self, other: &Color, eps: f64) -> bool {
+        (self.red.value() - other.red.value()).abs() <= eps &&
+            (self.green.value() - other.green.value()).abs() <= eps &&
+            (self.blue.value() - other.blue.value()).abs() <= eps
+    }
+
+    /// Returns true if every component is at most zero, meaning the color contributes nothing
+    pub fn is_black(&self) -> bool {
+        self.red.value() <= FLOAT_THRESHOLD && self.green.value() <= FLOAT_THRESHOLD && self.blue.value() <= FLOAT_THRESHOLD
+    }
+
+    /// Returns true if every component is at least one
+    pub fn is_white(&self) -> bool {
+        let threshold = 1.0 - FLOAT_THRESHOLD;
+        self.red.value() >= threshold && self.green.value() >= threshold && self.blue.value() >= threshold
+    }
+
+    // Tone mapping
+
+    /// Maps a high dynamic range color into [0, 1] with the ACES filmic curve
+    pub fn tone_map_aces(&self) -> Color {
+        let aces = |x: Float| {
+            let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+            let x = x.value().max(0.0);
+            Float((x * (a * x + b)) / (x * (c * x + d) + e)).clamp(0.0, 1.0)
+        };
+        Color::new(aces(self.red), aces(self.green), aces(self.blue))
+    }
+
+    /// Maps a high dynamic range color into [0, 1] with the Reinhard operator
+    pub fn tone_map_reinhard(&self) -> Color {
+        let reinhard = |x: Float| {
+            let x = x.value().max(0.0);
+            x / (1.0 + x)
+        };
+        Color::new(reinhard(self.red), reinhard(self.green), reinhard(self.blue))
+    }
 }
 
 
+/// Asserts that two colors are equal to within an explicit tolerance
+#[macro_export]
+macro_rules! assert_color_approx_eq {
+    ($left:expr, $right:expr, $eps:expr) => {{
+        let (left, right, eps) = (&$left, &$right, $eps);
+        assert!(left.approx_eq(right, eps),
+                "assertion failed: `(left ≈ right)`\n  left: `{:?}`,\n right: `{:?}`,\n   eps: `{}`", left, right, eps);
+    }};
+}
 
 // Addition
 impl_op_ex!(+ |a: &Color, b: &Color| -> Color { Color {red: &a.red + &b.red, green: &a.green + &b.green, blue: &a.blue + &b.blue} });
@@ -86,4 +146,50 @@ mod tests {
         let b  = Color::new(0.9, 1.0, 0.1);
         assert_eq!(&a * &b, Color::new(0.9, 0.2, 0.04));
     }
+
+    #[test]
+    fn color_approx_eq() {
+        let a = Color::new(0.38066, 0.47583, 0.2855);
+        assert!(a.approx_eq(&Color::new(0.38067, 0.47582, 0.2855), 0.0001));
+        assert!(!a.approx_eq(&Color::new(0.381, 0.47583, 0.2855), 0.0001));
+        assert!(a.approx_eq(&Color::new(0.381, 0.47583, 0.2855), 0.001));
+
+        assert_color_approx_eq!(a, Color::new(0.38066, 0.47583, 0.28551), 0.0001);
+    }
+
+    #[test]
+    #[should_panic]
+    fn color_assert_approx_eq_fails() {
+        assert_color_approx_eq!(Color::black(), Color::white(), 0.5);
+    }
+
+    #[test]
+    fn color_lerp() {
+        let a = Color::new(1.0, 0.0, 0.5);
+        let b = Color::new(0.0, 1.0, 0.5);
+        assert_eq!(Color::lerp(a, b, 0.0), a);
+        assert_eq!(Color::lerp(a, b, 1.0), b);
+        assert_eq!(Color::lerp(a, b, 0.25), Color::new(0.75, 0.25, 0.5));
+    }
+
+    #[test]
+    fn color_predicates() {
+        assert!(Color::black().is_black());
+        assert!(Color::new(0.000001, -0.5, 0.0).is_black());
+        assert!(!Color::new(0.0, 0.01, 0.0).is_black());
+
+        assert!(Color::white().is_white());
+        assert!(Color::new(0.999999, 1.5, 1.0).is_white());
+        assert!(!Color::new(1.0, 0.99, 1.0).is_white());
+    }
+
+    #[test]
+    fn color_tone_map() {
+        let c = Color::new(0.5, 1.0, 10.0);
+        assert_eq!(c.tone_map_aces(), Color::new(0.616307, 0.803797, 1.0));
+        assert_eq!(Color::new(-1.0, 0.0, 3.0).tone_map_aces(), Color::new(0.0, 0.0, 0.953742));
+
+        assert_eq!(c.tone_map_reinhard(), Color::new(0.333333, 0.5, 0.909090));
+        assert_eq!(Color::new(-1.0, 0.0, 3.0).tone_map_reinhard(), Color::new(0.0, 0.0, 0.75));
+    }
 }
@@ -24,10 +24,12 @@ use crate::pattern::stripe_pattern::StripePattern;
 use crate::pattern::ring_pattern::RingPattern;
 use crate::pattern::Pattern;
 use crate::pattern::gradient_pattern::GradientPattern;
+use crate::pattern::radial_gradient_pattern::RadialGradientPattern;
 use crate::pattern::blended_pattern::BlendedPattern;
 use crate::pattern::perturbed_pattern::PerturbedPattern;
 use crate::shape::cube::Cube;
 use crate::pattern::checker_pattern::CheckerPattern;
+use crate::pattern::voronoi_pattern::VoronoiPattern;
 use crate::shape::cylinder::Cylinder;
 use crate::shape::cone::Cone;
 use crate::shape::group::Group;
@@ -35,6 +37,7 @@ use crate::shape::triangle::Triangle;
 use crate::file::obj_loader::Parser;
 use crate::shape::shape_list::ShapeList;
 use crate::shape::csg::CSG;
+use crate::shape::torus::Torus;
 use rand::Rng;
 use crate::matrix::Matrix4;
 use noise::Perlin;
@@ -89,7 +92,7 @@ pub fn draw_bounds_scene() {
     camera.transform = view_transform(point(0.0, 1.5, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0));
 
     let canvas = camera.multithead_render(world, 8, shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("bounds_scene.ppm"))
+    file::write_canvas(&canvas, "bounds_scene")
 }
 
 //--------------------------------------------------
@@ -181,8 +184,7 @@ pub fn draw_combined_scene() {
     shape.material = material.clone();
     world.objects.push(Box::new(shape));
 
-    let mut shape = Cylinder::new_bounded(-1.0, 0.0, shape_list);
-    shape.closed = true;
+    let mut shape = Cylinder::new_closed(-1.0, 0.0, shape_list);
     shape.transform = translation(0.3, 0.4, 0.08) * scaling(0.1, 0.4, 0.1);
     shape.material = material.clone();
     world.objects.push(Box::new(shape));
@@ -202,7 +204,7 @@ pub fn draw_combined_scene() {
     camera.transform = view_transform(point(0.0, 1.5, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0));
 
     let canvas = camera.render(world, shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("combined_scene.ppm"))
+    file::write_canvas(&canvas, "combined_scene")
 }
 
 //--------------------------------------------------
@@ -336,7 +338,7 @@ pub fn draw_fractal_scene() {
 //    camera.transform = view_transform(point(0.0, 2.0, -2.0), point(0.0, 1.0, 0.0), vector(0.0, 2.0, 0.0));
 
     let canvas = camera.render(world, shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("fractal.ppm"))
+    file::write_canvas(&canvas, "fractal")
 }
 
 
@@ -422,7 +424,7 @@ pub fn draw_perturbed_normal_scene() {
     camera.transform = view_transform(point(0.0, 1.5, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0));
 
     let canvas = camera.render(world, shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("perturbed_normal_scene.ppm"))
+    file::write_canvas(&canvas, "perturbed_normal_scene")
 }
 
 //--------------------------------------------------
@@ -490,7 +492,7 @@ pub fn draw_soft_shadow_scene() {
     camera.transform = view_transform(point(0.4, 2.0, -3.0), point(0.4, 1.0, -0.7), vector(0.0, 1.0, 0.0));
 
     let canvas = camera.multithead_render(world, 4, shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("soft_shadows_scene.ppm"))
+    file::write_canvas(&canvas, "soft_shadows_scene")
 }
 
 
@@ -557,13 +559,14 @@ pub fn draw_csg_scene() {
     camera.transform = view_transform(point(-1.0, 2.5, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0));
 
     let canvas = camera.render(world, shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("csg_scene.ppm"))
+    file::write_canvas(&canvas, "csg_scene")
 }
 
 
 //--------------------------------------------------
 
-pub fn draw_obj_scene() {
+/// Builds the obj scene without rendering it, so `main` can report on it first
+pub fn obj_scene() -> (World, ShapeList, Camera) {
     // Options
     let canvas_width = 100;
     let canvas_height = 100;
@@ -596,26 +599,35 @@ pub fn draw_obj_scene() {
     tri.material = material;
     world.objects.push(Box::new(tri));
 
-    let parser = Parser::parse_obj_file("Obj/cat.obj", &mut shape_list);
+    let pb = indicatif::ProgressBar::new(0);
+    pb.set_style(indicatif::ProgressStyle::default_bar()
+        .template("[{elapsed_precise}] {bar:50} {pos:>7}/{len:7} {msg}"));
+    let parser = Parser::parse_obj_file_with_progress("Obj/cat.obj", &mut shape_list, |processed, total| {
+        pb.set_length(total as u64);
+        pb.set_position(processed as u64);
+    });
+    pb.finish_with_message("Finished parsing object");
     let mut tri_group = parser.unwrap().default_group;
     tri_group.transform = translation(0.0, 1.0, -2.0) * scaling(1.0, 1.0, 1.0) * rotation_y(PI/6.0) * rotation_x(PI/6.0);
     let mut material = Material::glass();
     material.color = Color::from_hex("FF8800");
     tri_group.material = material;
 
-    println!("Total shapes list {}\n{:#?}", &shape_list.len(), &shape_list);
-
     world.objects.push(Box::new(tri_group));
 
     let light = Light::point_light(&point(-10.0, 16.0, -10.0), &Color::new(1.0, 1.0, 1.0));
     world.lights.push(light);
 
-    // Create camera and render scene
+    // Create camera
     let mut camera = Camera::new(canvas_width, canvas_height, fov);
     camera.transform = view_transform(point(0.0, 1.5, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0));
 
+    (world, shape_list, camera)
+}
+
+pub fn draw_obj_scene(world: World, mut shape_list: ShapeList, camera: Camera) {
     let canvas = camera.render(world, &mut shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("obj_scene.ppm"))
+    file::write_canvas(&canvas, "obj_scene")
 }
 
 //--------------------------------------------------
@@ -726,7 +738,7 @@ pub fn draw_hexagon_scene() {
     camera.transform = view_transform(point(0.0, 1.5, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0));
 
     let canvas = camera.render(world, &mut shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("hexagon_scene.ppm"))
+    file::write_canvas(&canvas, "hexagon_scene")
 }
 
 //--------------------------------------------------
@@ -741,17 +753,13 @@ pub fn draw_cone_scene() {
     let mut world = World::new();
     let mut shape_list = ShapeList::new();
 
-    let mut floor = Plane::new(&mut shape_list);
-    floor.transform = scaling(10.0, 0.01, 10.0);
-    let mut material = Material::new();
-    material.reflective = Float(0.4);
     let pattern_b = RingPattern::new(Color::from_hex("FFE4C6"), Color::from_hex("B5BD89"));
-    let mut pattern = PerturbedPattern::new(Box::new(pattern_b), 0.15);
-    pattern.set_transform(transformation::scaling(0.1, 0.1, 0.1));
-    material.set_pattern(Box::new(pattern));
-    material.ambient = Float(0.15);
-    material.specular = Float(0.0);
-    floor.material = material;
+    let pattern = PerturbedPattern::new(Box::new(pattern_b), 0.15);
+    let mut floor = Plane::with_pattern_scale(Box::new(pattern), 0.1, &mut shape_list);
+    floor.transform = scaling(10.0, 0.01, 10.0);
+    floor.material.reflective = Float(0.4);
+    floor.material.ambient = Float(0.15);
+    floor.material.specular = Float(0.0);
     world.objects.push(Box::new(floor));
 
     let mut middle_cone = Cone::new_bounded(-1.0, 1.0, &mut shape_list);
@@ -780,8 +788,7 @@ pub fn draw_cone_scene() {
     ];
     for i in 0..colors.len() {
         let rotation = PI/6.0 + PI/6.0 * i as f64;
-        let mut cylinder = Cylinder::new_bounded(0.0, 2.0, &mut shape_list);
-        cylinder.closed = true;
+        let mut cylinder = Cylinder::new_closed(0.0, 2.0, &mut shape_list);
         cylinder.transform = rotation_y(rotation) * translation(0.0, 1.0, -3.0) * scaling(0.4, 1.0, 0.4);
 //        let material = Material::mirror();
         let mut material = Material::new();
@@ -810,7 +817,230 @@ pub fn draw_cone_scene() {
     camera.transform = view_transform(point(0.0, 3.5, -6.5), point(0.0, 2.0, 0.0), vector(0.0, 1.0, 0.0));
 
     let canvas = camera.render(world, &mut shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("cone_scene.ppm"))
+    file::write_canvas(&canvas, "cone_scene")
+}
+
+//--------------------------------------------------
+
+
+pub fn draw_voronoi_scene() {
+    // Options
+    let canvas_width = 500;
+    let canvas_height = 500;
+    let fov = PI/3.0;
+
+    // Construct world
+    let mut world = World::new();
+    let mut shape_list = ShapeList::new();
+
+    let flagstones = VoronoiPattern::new(Color::from_hex("4A4238"), Color::from_hex("B8AFA0"), 1.0, 3);
+    let mut floor = Plane::with_pattern_scale(Box::new(flagstones), 0.8, &mut shape_list);
+    floor.material.specular = Float(0.0);
+    world.objects.push(Box::new(floor));
+
+    let mut stone = Sphere::new(&mut shape_list);
+    stone.transform = translation(-1.2, 0.8, 0.0) * scaling(0.8, 0.8, 0.8);
+    let mut material = Material::new();
+    let mut pattern = VoronoiPattern::new(Color::from_hex("5C6B73"), Color::from_hex("C2DFE3"), 3.0, 11);
+    pattern.set_transform(rotation_x(PI/5.0));
+    material.set_pattern(Box::new(pattern));
+    material.specular = Float(0.1);
+    stone.material = material;
+    world.objects.push(Box::new(stone));
+
+    let mut cells = Sphere::new(&mut shape_list);
+    cells.transform = translation(1.0, 1.0, 0.5);
+    let mut material = Material::new();
+    material.set_pattern(Box::new(VoronoiPattern::new(Color::from_hex("7A1F2B"), Color::from_hex("F2A65A"), 4.0, 5)));
+    material.reflective = Float(0.1);
+    cells.material = material;
+    world.objects.push(Box::new(cells));
+
+    let light = Light::point_light(&point(-10.0, 12.0, -10.0), &Color::new(1.0, 1.0, 1.0));
+    world.lights.push(light);
+
+    // Create camera and render scene
+    let mut camera = Camera::new(canvas_width, canvas_height, fov);
+    camera.transform = view_transform(point(0.0, 2.5, -5.5), point(0.0, 0.8, 0.0), vector(0.0, 1.0, 0.0));
+
+    let canvas = camera.render(world, &mut shape_list);
+    file::write_canvas(&canvas, "voronoi_scene")
+}
+
+//--------------------------------------------------
+
+
+pub fn draw_dof_scene() {
+    // Options
+    let canvas_width = 500;
+    let canvas_height = 300;
+    let fov = PI/3.0;
+
+    // Construct world
+    let mut world = World::new();
+    let mut shape_list = ShapeList::new();
+
+    let mut floor = Plane::new(&mut shape_list);
+    let mut material = Material::new();
+    material.set_pattern(Box::new(CheckerPattern::new(Color::from_hex("FFE4C6"), Color::from_hex("B5BD89"))));
+    material.specular = Float(0.0);
+    floor.material = material;
+    world.objects.push(Box::new(floor));
+
+    // A diagonal row of spheres going away from the camera, the middle one is in focus
+    let colors = ["DD614A", "F48668", "F4A698", "C5C392", "73A580"];
+    for (i, hex) in colors.iter().enumerate() {
+        let mut sphere = Sphere::new(&mut shape_list);
+        sphere.transform = translation(-2.0 + i as f64, 0.5, -2.0 + 2.0 * i as f64) * scaling(0.5, 0.5, 0.5);
+        let mut material = Material::new();
+        material.color = Color::from_hex(hex);
+        sphere.material = material;
+        world.objects.push(Box::new(sphere));
+    }
+
+    let light = Light::point_light(&point(-10.0, 10.0, -10.0), &Color::new(1.0, 1.0, 1.0));
+    world.lights.push(light);
+
+    // Create camera and render scene
+    let from = point(0.0, 1.5, -6.0);
+    let in_focus = point(0.0, 0.5, 2.0);
+    let mut camera = Camera::new_with_dof(canvas_width, canvas_height, fov, 0.3, (in_focus - from).magnitude());
+    camera.transform = view_transform(from, in_focus, vector(0.0, 1.0, 0.0));
+
+    let canvas = camera.render(world, &mut shape_list);
+    file::write_canvas(&canvas, "dof_scene")
+}
+
+//--------------------------------------------------
+
+
+pub fn draw_spotlight_scene() {
+    // Options
+    let canvas_width = 500;
+    let canvas_height = 300;
+    let fov = PI/3.0;
+
+    // Construct world
+    let mut world = World::new();
+    let mut shape_list = ShapeList::new();
+
+    let mut floor = Plane::new(&mut shape_list);
+    let mut material = Material::new();
+    material.set_pattern(Box::new(CheckerPattern::new(Color::from_hex("FFE4C6"), Color::from_hex("B5BD89"))));
+    material.specular = Float(0.0);
+    floor.material = material;
+    world.objects.push(Box::new(floor));
+
+    let mut sphere = Sphere::new(&mut shape_list);
+    sphere.transform = translation(0.0, 1.0, 0.0);
+    let mut material = Material::new();
+    material.color = Color::from_hex("DD614A");
+    sphere.material = material;
+    world.objects.push(Box::new(sphere));
+
+    // Shining down onto the sphere, the edge of the beam fades out across the floor
+    let light = Light::spot_light(&point(-2.0, 8.0, -2.0), &vector(2.0, -7.0, 2.0), &Color::new(1.0, 1.0, 1.0), PI/12.0, PI/6.0);
+    world.lights.push(light);
+
+    // Create camera and render scene
+    let mut camera = Camera::new(canvas_width, canvas_height, fov);
+    camera.transform = view_transform(point(0.0, 3.0, -7.0), point(0.0, 0.5, 0.0), vector(0.0, 1.0, 0.0));
+
+    let canvas = camera.render(world, &mut shape_list);
+    file::write_canvas(&canvas, "spotlight_scene")
+}
+
+//--------------------------------------------------
+
+
+pub fn draw_directional_light_scene() {
+    // Options
+    let canvas_width = 500;
+    let canvas_height = 300;
+    let fov = PI/3.0;
+
+    // Construct world
+    let mut world = World::new();
+    world.set_background(Color::from_hex("A7D3F2"));
+    let mut shape_list = ShapeList::new();
+
+    let mut floor = Plane::new(&mut shape_list);
+    let mut material = Material::new();
+    material.color = Color::from_hex("B5BD89");
+    material.specular = Float(0.0);
+    floor.material = material;
+    world.objects.push(Box::new(floor));
+
+    // Rows of columns going off into the distance all cast parallel shadows
+    for i in 0..6 {
+        for side in [-1.5, 1.5].iter() {
+            let mut column = Cylinder::new_closed(0.0, 2.0, &mut shape_list);
+            column.transform = translation(*side, 0.0, 3.0 * i as f64) * scaling(0.3, 1.0, 0.3);
+            let mut material = Material::new();
+            material.color = Color::from_hex("FFE4C6");
+            column.material = material;
+            world.objects.push(Box::new(column));
+        }
+    }
+
+    // Low sun off to the side
+    let light = Light::directional_light(&vector(-1.0, -0.6, 0.4), &Color::new(1.0, 0.95, 0.85));
+    world.lights.push(light);
+
+    // Create camera and render scene
+    let mut camera = Camera::new(canvas_width, canvas_height, fov);
+    camera.transform = view_transform(point(0.0, 2.5, -6.0), point(0.0, 1.0, 4.0), vector(0.0, 1.0, 0.0));
+
+    let canvas = camera.render(world, &mut shape_list);
+    file::write_canvas(&canvas, "directional_light_scene")
+}
+
+//--------------------------------------------------
+
+
+pub fn draw_torus_scene() {
+    // Options
+    let canvas_width = 500;
+    let canvas_height = 500;
+    let fov = PI/3.0;
+
+    // Construct world
+    let mut world = World::new();
+    let mut shape_list = ShapeList::new();
+
+    let mut floor = Plane::new(&mut shape_list);
+    let mut material = Material::new();
+    material.reflective = Float(0.3);
+    material.set_pattern(Box::new(CheckerPattern::new(Color::from_hex("FFE4C6"), Color::from_hex("B5BD89"))));
+    material.specular = Float(0.0);
+    floor.material = material;
+    world.objects.push(Box::new(floor));
+
+    let mut lying_torus = Torus::new_with_radii(1.5, 0.4, &mut shape_list);
+    lying_torus.transform = translation(0.0, 0.4, 0.0);
+    let mut material = Material::new();
+    material.color = Color::from_hex("729EA1");
+    lying_torus.material = material;
+    world.objects.push(Box::new(lying_torus));
+
+    let mut standing_torus = Torus::new_with_radii(1.0, 0.3, &mut shape_list);
+    standing_torus.transform = translation(0.0, 1.3, 0.0) * rotation_y(PI/4.0) * rotation_x(PI/2.0);
+    standing_torus.material = Material::mirror();
+    world.objects.push(Box::new(standing_torus));
+
+    let mut glass_torus = Torus::new_with_material(Material::glass(), &mut shape_list);
+    glass_torus.transform = translation(0.0, 1.3, 0.0) * rotation_y(-PI/4.0) * rotation_x(PI/2.0) * scaling(0.6, 0.6, 0.6);
+    world.objects.push(Box::new(glass_torus));
+
+    let light = Light::point_light(&point(-10.0, 16.0, -10.0), &Color::new(1.0, 1.0, 1.0));
+    world.lights.push(light);
+
+    // Create camera and render scene
+    let mut camera = Camera::new(canvas_width, canvas_height, fov);
+    camera.transform = view_transform(point(0.0, 3.5, -6.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0));
+
+    let canvas = camera.render(world, &mut shape_list);
+    file::write_canvas(&canvas, "torus_scene")
 }
 
 //--------------------------------------------------
@@ -838,8 +1068,7 @@ pub fn draw_cylinder_refracted_scene() {
     floor.material = material;
     world.objects.push(Box::new(floor));
 
-    let mut middle_cylinder = Cylinder::new_bounded(0.0, 3.0, &mut shape_list);
-    middle_cylinder.closed = true;
+    let mut middle_cylinder = Cylinder::new_closed(0.0, 3.0, &mut shape_list);
 //    middle_cylinder.transform = scaling(0.7, 1.0, 0.7);
     let material = Material::glass();
 //    let mut material = Material::new();
@@ -854,8 +1083,7 @@ pub fn draw_cylinder_refracted_scene() {
         Color::from_hex("00cc00"),
     ];
     for i in 0..colors.len() {
-        let mut cylinder = Cylinder::new_bounded(0.0, 2.0, &mut shape_list);
-        cylinder.closed = true;
+        let mut cylinder = Cylinder::new_closed(0.0, 2.0, &mut shape_list);
         cylinder.transform = rotation_y(PI - PI/6.0 * i as f64) * translation(0.0, 0.0, -3.0) * scaling(0.4, 1.0, 0.4);
 //        let material = Material::mirror();
         let mut material = Material::new();
@@ -905,7 +1133,7 @@ pub fn draw_cylinder_refracted_scene() {
     camera.transform = view_transform(point(0.0, 5.0, -10.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0));
 
     let canvas = camera.render(world, &mut shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("cylinder_refracted_scene.ppm"))
+    file::write_canvas(&canvas, "cylinder_refracted_scene")
 }
 
 //--------------------------------------------------
@@ -932,8 +1160,7 @@ pub fn draw_cylinder_scene() {
     floor.material = material;
     world.objects.push(Box::new(floor));
 
-    let mut middle_cylinder = Cylinder::new_bounded(0.0, 3.0, &mut shape_list);
-    middle_cylinder.closed = true;
+    let mut middle_cylinder = Cylinder::new_closed(0.0, 3.0, &mut shape_list);
 //    middle_cylinder.transform = scaling(0.7, 1.0, 0.7);
     let material = Material::mirror();
 //    let mut material = Material::new();
@@ -948,8 +1175,7 @@ pub fn draw_cylinder_scene() {
         Color::from_hex("00cc00"),
     ];
     for i in 0..colors.len() {
-        let mut cylinder = Cylinder::new_bounded(0.0, 2.0, &mut shape_list);
-        cylinder.closed = true;
+        let mut cylinder = Cylinder::new_closed(0.0, 2.0, &mut shape_list);
         cylinder.transform = rotation_y(PI/6.0 * i as f64) * translation(0.0, 0.0, -3.0) * scaling(0.4, 1.0, 0.4);
 //        let material = Material::mirror();
         let mut material = Material::new();
@@ -973,7 +1199,7 @@ pub fn draw_cylinder_scene() {
     camera.transform = view_transform(point(0.0, 4.5, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0));
 
     let canvas = camera.render(world, &mut shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("cylinder_scene.ppm"))
+    file::write_canvas(&canvas, "cylinder_scene")
 }
 
 //--------------------------------------------------
@@ -1060,7 +1286,7 @@ pub fn draw_refracted_scene() {
 
     let canvas = camera.multithead_render(world, 4, &mut shape_list);
 //    let canvas = camera.render(world, &mut shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("refracted_scene.ppm"))
+    file::write_canvas(&canvas, "refracted_scene")
 }
 
 //--------------------------------------------------
@@ -1132,7 +1358,7 @@ pub fn draw_reflected_scene() {
     camera.transform = view_transform(point(0.0, 1.5, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0));
 
     let canvas = camera.render(world, &mut shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("refracted_scene.ppm"))
+    file::write_canvas(&canvas, "refracted_scene")
 }
 
 //--------------------------------------------------
@@ -1203,7 +1429,7 @@ pub fn draw_perturbed_patterned_scene() {
     camera.transform = view_transform(point(0.0, 1.5, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0));
 
     let canvas = camera.render(world, &mut shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("patterned_scene_perturbed.ppm"))
+    file::write_canvas(&canvas, "patterned_scene_perturbed")
 }
 
 //--------------------------------------------------
@@ -1223,7 +1449,10 @@ pub fn draw_blended_patterned_scene() {
     let mut material = Material::new();
     let pattern_a = RingPattern::new(Color::from_hex("FF0000"), Color::black());
     let pattern_b = CheckerPattern::new(Color::from_hex("0000FF"), Color::black());
-    let mut pattern = BlendedPattern::new(Box::new(pattern_a), Box::new(pattern_b));
+    let mut pattern_c = RadialGradientPattern::new(Color::from_hex("FFE2BA"), Color::black());
+    pattern_c.set_transform(transformation::scaling(20.0, 20.0, 20.0));
+    let pattern_ab = BlendedPattern::new(Box::new(pattern_a), Box::new(pattern_b));
+    let mut pattern = BlendedPattern::new(Box::new(pattern_ab), Box::new(pattern_c));
     pattern.set_transform(transformation::scaling(0.1, 0.1, 0.1));
     material.set_pattern(Box::new(pattern));
     material.color = Color::from_hex("FFE2BA");
@@ -1272,7 +1501,7 @@ pub fn draw_blended_patterned_scene() {
     camera.transform = view_transform(point(0.0, 1.5, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0));
 
     let canvas = camera.render(world, &mut shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("patterned_scene_blended.ppm"))
+    file::write_canvas(&canvas, "patterned_scene_blended")
 }
 
 //--------------------------------------------------
@@ -1339,7 +1568,7 @@ pub fn draw_patterned_scene() {
     camera.transform = view_transform(point(0.0, 1.5, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0));
 
     let canvas = camera.render(world, &mut shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("patterned_scene.ppm"))
+    file::write_canvas(&canvas, "patterned_scene")
 }
 
 //--------------------------------------------------
@@ -1397,7 +1626,7 @@ pub fn draw_scene_on_a_plane() {
     camera.transform = view_transform(point(0.0, 1.5, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0));
 
     let canvas = camera.render(world, &mut shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("scene_on_a_plane.ppm"))
+    file::write_canvas(&canvas, "scene_on_a_plane")
 }
 
 //--------------------------------------------------
@@ -1473,7 +1702,7 @@ pub fn draw_first_scene() {
     camera.transform = view_transform(point(0.0, 1.5, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0));
 
     let canvas = camera.render(world, &mut shape_list);
-    file::write_to_file(canvas.to_ppm(), String::from("first_scene.ppm"))
+    file::write_canvas(&canvas, "first_scene")
 }
 
 //--------------------------------------------------
@@ -1520,12 +1749,12 @@ pub fn draw_shaded_circle() {
                 let eye = -&ray.direction;
                 let object = hit.as_ref().unwrap().object.clone();
 
-                let color = Light::lighting(&object.material(), Some(object), None, &light, point, None, &eye, &normal, false, None);
+                let color = Light::lighting(&object.material(), Some(object), None, &light, point, None, &eye, &normal, Color::white(), None);
                 canvas.write_pixel(x, y, &color);
             }
         }
     }
-    file::write_to_file(canvas.to_ppm(), String::from("shaded_circle.ppm"))
+    file::write_canvas(canvas, "shaded_circle")
 }
 //--------------------------------------------------
 
@@ -1584,7 +1813,7 @@ pub fn draw_uniform_rand_circle() {
             canvas.write_pixel(x_pix as i32, y_pix as i32, &color);
         }
     }
-    file::write_to_file(canvas.to_ppm(), String::from("circle_rand.ppm"))
+    file::write_canvas(canvas, "circle_rand")
 }
 
 //--------------------------------------------------
@@ -1626,7 +1855,7 @@ pub fn draw_circle() {
             }
         }
     }
-    file::write_to_file(canvas.to_ppm(), String::from("circle.ppm"))
+    file::write_canvas(canvas, "circle")
 }
 
 //--------------------------------------------------
@@ -1653,7 +1882,7 @@ pub fn draw_clock() {
         canvas.write_pixel(x, z, color);
     }
 
-    file::write_to_file(canvas.to_ppm(), String::from("clock.ppm"));
+    file::write_canvas(canvas, "clock");
 }
 
 //--------------------------------------------------
@@ -1664,7 +1893,7 @@ pub fn draw_arch() {
 
     let canvas = &mut Canvas::new(900, 550);
     tick_loop(initial_projectile, environment, canvas);
-    file::write_to_file(canvas.to_ppm(), String::from("projectile.ppm"));
+    file::write_canvas(canvas, "projectile");
 }
 
 struct Projectile {
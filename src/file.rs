@@ -2,7 +2,44 @@
 /// `file` is a module for I/O
 
 use std::fs::File;
-use std::io::{prelude::*};
+use std::io::{self, prelude::*, Error, ErrorKind};
+use crate::canvas::Canvas;
+use crate::color::Color;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Rows of pixels shared between every pattern using the same image
+pub type Texture = Arc<Vec<Vec<Color>>>;
+
+/// Image file formats a canvas can be written as
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+}
+
+impl ImageFormat {
+    /// Returns the format with the name "ppm" or "png"
+    pub fn from_name(name: &str) -> Option<ImageFormat> {
+        match name.to_lowercase().as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Png => "png",
+        }
+    }
+}
+
+lazy_static! {
+    static ref TEXTURE_CACHE: Mutex<HashMap<String, Texture>> = Mutex::new(HashMap::new());
+    static ref OUTPUT_FORMAT: Mutex<ImageFormat> = Mutex::new(ImageFormat::Ppm);
+}
 
 pub fn write_to_file(str: String, path: String) {
     let mut f = File::create(path).expect("Unable to create file");
@@ -10,6 +47,191 @@ pub fn write_to_file(str: String, path: String) {
     f.sync_all().expect("Unable to sync file");
 }
 
+/// Writes the canvas as a PNG file, colors are clamped to [0.0, 1.0]
+pub fn write_to_png(canvas: &Canvas, path: &str) {
+    let mut f = File::create(path).expect("Unable to create file");
+    f.write_all(&canvas.to_png_bytes()).expect("Unable to write to file");
+    f.sync_all().expect("Unable to sync file");
+}
+
+/// Sets the format `write_canvas` writes images as, PPM by default
+pub fn set_output_format(format: ImageFormat) {
+    *OUTPUT_FORMAT.lock().unwrap() = format;
+}
+
+pub fn output_format() -> ImageFormat {
+    *OUTPUT_FORMAT.lock().unwrap()
+}
+
+/// Writes the canvas to `name` with the extension of the output format,
+/// e.g. "scene" is written to "scene.png" when the output format is PNG
+pub fn write_canvas(canvas: &Canvas, name: &str) {
+    let format = output_format();
+    let path = format!("{}.{}", name, format.extension());
+    match format {
+        ImageFormat::Ppm => write_to_file(canvas.to_ppm(), path),
+        ImageFormat::Png => write_to_png(canvas, &path),
+    }
+}
+
+/// Reads a plain (P3) PPM file into a canvas
+pub fn read_ppm(path: &str) -> io::Result<Canvas> {
+    let mut contents = String::new();
+    File::open(path)?.read_to_string(&mut contents)?;
+    parse_ppm(&contents)
+}
+
+/// Reads a PPM file into a texture, each path is only read and decoded once
+/// and later calls share the same pixels
+pub fn read_texture(path: &str) -> io::Result<Texture> {
+    if let Some(texture) = TEXTURE_CACHE.lock().unwrap().get(path) {
+        return Ok(texture.clone());
+    }
+
+    // Decode without holding the lock so other textures can load in parallel
+    let texture = Arc::new(read_ppm(path)?.pixels);
+    let mut cache = TEXTURE_CACHE.lock().unwrap();
+    Ok(cache.entry(String::from(path)).or_insert(texture).clone())
+}
+
+/// Parses the contents of a plain (P3) PPM file into a canvas
+///
+/// Values may be separated by any whitespace and
+/// anything after a `#` on a line is a comment
+pub fn parse_ppm(contents: &str) -> io::Result<Canvas> {
+    let invalid = |msg: String| Error::new(ErrorKind::InvalidData, msg);
+
+    let mut tokens = contents.lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(|line| line.split_whitespace());
+
+    match tokens.next() {
+        Some("P3") => {},
+        Some(magic) => return Err(invalid(format!("Unsupported PPM format: {}", magic))),
+        None => return Err(invalid(String::from("Empty PPM file"))),
+    }
+
+    let mut next_number = |name: &str| -> io::Result<f64> {
+        let token = tokens.next().ok_or_else(|| invalid(format!("Missing {}", name)))?;
+        token.parse::<f64>().map_err(|_| invalid(format!("Expected a number for {}: {}", name, token)))
+    };
+
+    let width = next_number("width")? as i32;
+    let height = next_number("height")? as i32;
+    let max_color_val = next_number("max color value")?;
+    if width < 0 || height < 0 || max_color_val <= 0.0 {
+        return Err(invalid(format!("Invalid PPM header: {} {} {}", width, height, max_color_val)));
+    }
+
+    let mut canvas = Canvas::new(width, height);
+    for row in 0..height {
+        for col in 0..width {
+            let red = next_number("red")? / max_color_val;
+            let green = next_number("green")? / max_color_val;
+            let blue = next_number("blue")? / max_color_val;
+            canvas.write_pixel(row, col, &Color::new(red, green, blue));
+        }
+    }
+    Ok(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_parse_ppm() {
+        let ppm = "P3\n# A comment\n2  2\n255\n255 0 0   0 255 0 # trailing comment\n\n0 0 255\n\t255 255 255\n";
+        let canvas = parse_ppm(ppm).unwrap();
+        assert_eq!(canvas.width, 2);
+        assert_eq!(canvas.height, 2);
+        assert_eq!(canvas.pixel_at(0, 0), &Color::new(1.0, 0.0, 0.0));
+        assert_eq!(canvas.pixel_at(0, 1), &Color::new(0.0, 1.0, 0.0));
+        assert_eq!(canvas.pixel_at(1, 0), &Color::new(0.0, 0.0, 1.0));
+        assert_eq!(canvas.pixel_at(1, 1), &Color::white());
+
+        // Other max color values are scaled
+        let canvas = parse_ppm("P3 1 1 100 50 0 100").unwrap();
+        assert_eq!(canvas.pixel_at(0, 0), &Color::new(0.5, 0.0, 1.0));
+
+        assert!(parse_ppm("P6 1 1 255").is_err());
+        assert!(parse_ppm("P3 2 1 255 0 0 0").is_err());
+        assert!(parse_ppm("P3 1 1 255 0 a 0").is_err());
+    }
+
+    #[test]
+    fn file_read_ppm() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas.write_pixel(1, 2, &Color::new(1.0, 0.8, 0.6));
+        let path = std::env::temp_dir().join("raytracer_file_read_ppm.ppm");
+        write_to_file(canvas.to_ppm(), path.to_string_lossy().to_string());
+
+        let read = read_ppm(&path.to_string_lossy()).unwrap();
+        assert_eq!(read.width, 3);
+        assert_eq!(read.height, 2);
+        assert_eq!(read.pixel_at(1, 2), &Color::new(1.0, 0.8, 0.6));
+        assert_eq!(read.pixel_at(0, 0), &Color::black());
+
+        assert!(read_ppm("does/not/exist.ppm").is_err());
+    }
+
+    #[test]
+    fn file_read_texture() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.write_pixel(0, 1, &Color::new(0.2, 0.4, 0.6));
+        let path = std::env::temp_dir().join("raytracer_file_read_texture.ppm");
+        let path = path.to_string_lossy().to_string();
+        write_to_file(canvas.to_ppm(), path.clone());
+
+        let texture = read_texture(&path).unwrap();
+        assert_eq!(texture[0][1], Color::new(0.2, 0.4, 0.6));
+
+        // The second load shares the cached pixels instead of reading the file again
+        std::fs::remove_file(&path).unwrap();
+        let cached = read_texture(&path).unwrap();
+        assert!(Arc::ptr_eq(&texture, &cached));
+
+        assert!(read_texture("does/not/exist.ppm").is_err());
+    }
+
+    #[test]
+    fn file_write_to_png() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas.write_pixel(0, 0, &Color::new(1.0, 0.5, 0.25));
+        canvas.write_pixel(1, 2, &Color::new(0.1, 0.2, 0.3));
+        // Out of range colors are clamped
+        canvas.write_pixel(1, 0, &Color::new(1.5, -0.5, 0.0));
+        let path = std::env::temp_dir().join("raytracer_file_write_to_png.png");
+        let path = path.to_string_lossy().to_string();
+        write_to_png(&canvas, &path);
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((info.width, info.height), (3, 2));
+        assert_eq!(info.color_type, png::ColorType::Rgb);
+
+        for row in 0..2 {
+            for col in 0..3 {
+                let i = ((row * 3 + col) * 3) as usize;
+                let read = Color::new(data[i] as f64 / 255.0, data[i + 1] as f64 / 255.0, data[i + 2] as f64 / 255.0);
+                assert!(read.approx_eq(canvas.clamp().pixel_at(row, col), 0.5 / 255.0));
+            }
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), canvas.to_png_bytes());
+    }
+
+    #[test]
+    fn file_image_format() {
+        assert_eq!(ImageFormat::from_name("PNG"), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_name("ppm"), Some(ImageFormat::Ppm));
+        assert_eq!(ImageFormat::from_name("jpg"), None);
+        assert_eq!(ImageFormat::Png.extension(), "png");
+        assert_eq!(output_format(), ImageFormat::Ppm);
+    }
+}
+
 
 
 /// # obj_loader
@@ -17,14 +239,15 @@ pub fn write_to_file(str: String, path: String) {
 pub mod obj_loader {
     use std::fs::File;
     use std::io::{self, prelude::*, BufReader};
-    use crate::tuple::{Tuple, point};
+    use crate::tuple::{Tuple, point, vector};
     use std::ops::{IndexMut, Index};
     use crate::shape::group::Group;
     use crate::shape::Shape;
     use crate::shape::triangle::Triangle;
-    use indicatif::ProgressStyle;
+    use crate::shape::smooth_triangle::SmoothTriangle;
     use crate::shape::shape_list::ShapeList;
     use crate::material::Material;
+    use std::collections::HashMap;
 
     /// A one based array
     #[derive(Debug)]
@@ -61,45 +284,94 @@ pub mod obj_loader {
 
 
     pub struct Parser {
-        pub ignored_lines: i32,
+        /// Count of each unsupported directive (first token of a line) that was skipped
+        pub skipped_directives: HashMap<String, usize>,
         pub vertices: OneVec<Tuple>,
+        /// Vertex normals from `vn` directives
+        pub normals: OneVec<Tuple>,
         pub default_group: Group,
+        /// Faces are smooth shaded, set by the `s` directive
+        smoothing: bool,
+        /// Triangles waiting to be added to the default group once parsing is done
+        pending_triangles: Vec<Box<dyn Shape + Send>>,
     }
 
     impl Parser {
         pub fn parse_obj_file(path: &str, shape_list: &mut ShapeList) -> io::Result<(Parser)> {
+            Parser::parse_obj_file_with_progress(path, shape_list, |_, _| {})
+        }
+
+        /// Parses an OBJ file reporting progress after each line
+        /// # Arguments
+        /// * `progress_fn` Called with the lines processed so far and the total amount of lines
+        pub fn parse_obj_file_with_progress(path: &str, shape_list: &mut ShapeList,
+                                            progress_fn: impl Fn(usize, usize)) -> io::Result<Parser> {
             let file = File::open(path)?;
             let reader = BufReader::new(file);
             let lines: Vec<String> = reader.lines()
                 .map(|l| l.expect("Could not parse line"))
                 .collect();
             let mut parser = Parser {
-                ignored_lines: 0,
+                skipped_directives: HashMap::new(),
                 vertices: OneVec::new(vec![]),
+                normals: OneVec::new(vec![]),
                 default_group: Group::new(shape_list),
+                smoothing: false,
+                pending_triangles: vec![],
             };
 
-            let pb = indicatif::ProgressBar::new(lines.len() as u64);
-            pb.set_style(ProgressStyle::default_bar()
-                .template("[{elapsed_precise}] {bar:50} {pos:>7}/{len:7} {msg}"));
+            let total_lines = lines.len();
+            for (i, line) in lines.iter().enumerate() {
+                progress_fn(i + 1, total_lines);
+
+                let directive = match line.split_whitespace().next() {
+                    Some(directive) => directive,
+                    None => continue,
+                };
+                match directive {
+                    "v" => parser.parse_vertex(line),
+                    "vn" => parser.parse_vertex_normal(line),
+                    "f" => parser.parse_face(line, shape_list),
+                    "s" => parser.parse_smoothing(line),
+                    _ => *parser.skipped_directives.entry(String::from(directive)).or_insert(0) += 1
+                }
+            }
+            let mut triangles = std::mem::take(&mut parser.pending_triangles);
+            parser.default_group.add_children(&mut triangles, shape_list);
+            parser.compute_vertex_normals(shape_list);
+            Ok(parser)
+        }
 
-            for line in lines {
-                pb.inc(1);
+        fn parse_smoothing(&mut self, line: &str) {
+            self.smoothing = !matches!(line.split_whitespace().nth(1), None | Some("off") | Some("0"));
+        }
 
-                let char_res = line.chars().next();
-                if char_res.is_none() {
-                    continue;
-                }
-                match char_res.unwrap() {
-                    'v' => parser.parse_vertex(&line),
-                    'f' => parser.parse_face(&line, shape_list),
-                    _ => parser.ignored_lines += 1
+        /// Averages the normals of the smooth triangles touching each vertex
+        /// for the default group to shade them with
+        fn compute_vertex_normals(&mut self, shape_list: &mut ShapeList) {
+            let mut normals = vec![vector(0.0, 0.0, 0.0); self.vertices.len()];
+            let mut any_smooth = false;
+            for id in self.default_group.children_ids.iter() {
+                if let Some(triangle) = shape_list[*id as usize].as_any().downcast_ref::<Triangle>() {
+                    if let (true, Some(indices)) = (triangle.smooth, triangle.vertex_indices) {
+                        any_smooth = true;
+                        for index in indices.iter() {
+                            normals[index - 1] = normals[index - 1] + triangle.normal;
+                        }
+                    }
                 }
             }
-            pb.finish_with_message("Finished parsing object");
-            Ok(parser)
+            if !any_smooth {
+                return;
+            }
+
+            let normals = normals.into_iter()
+                .map(|normal| if normal.magnitude() > 0.0 { normal.normalize() } else { normal })
+                .collect();
+            self.default_group.set_vertex_normals(normals, shape_list);
         }
 
+        /// Parses a `v x y z` line, the last coordinate may end the line without trailing whitespace
         fn parse_vertex(&mut self, line: &String) {
             let mut vertex = [0.0f64; 3];
             let mut num_counter = 0;
@@ -127,52 +399,53 @@ pub mod obj_loader {
                 num_counter += 1;
             }
 
+            // Try to parse on end of line
+            if num_counter < 3 {
+                if let Some(value) = Parser::parse_float(&str_builder) {
+                    vertex[num_counter] = value;
+                }
+            }
+
             self.vertices.push(point(vertex[0], vertex[1], vertex[2]))
         }
 
+        fn parse_vertex_normal(&mut self, line: &str) {
+            let values: Vec<f64> = line.split_whitespace().skip(1)
+                .filter_map(|value| value.parse().ok())
+                .collect();
+            if values.len() >= 3 {
+                self.normals.push(vector(values[0], values[1], values[2]));
+            }
+        }
+
         fn parse_face(&mut self, line: &String, shape_list: &mut ShapeList) {
+            // Each vertex is written as `v`, `v/vt`, `v/vt/vn` or `v//vn`
             let mut verts: Vec<usize> = vec![];
-            let mut str_builder = String::from("");
-            let mut should_skip_number: bool = false;
-
-
-            for character in line.chars() {
-                match character {
-                    'f' => continue,
-                    ' '|'\t'|'\n'|'\r'|'\u{2029}' => {
-                        if should_skip_number {
-                            str_builder.clear();
-                            continue;
-                        }
-                        let result = Parser::parse_int(&str_builder);
-                        if result.is_none() {
-                            continue;
-                        } else {
-                            verts.push(result.unwrap() as usize);
-                            str_builder.clear();
-                        }
-                    },
-                    '/' => {
-                        should_skip_number = true;
-                        let result = Parser::parse_int(&str_builder);
-                        if result.is_none() {
-                            continue;
-                        } else {
-                            verts.push(result.unwrap() as usize);
-                            str_builder.clear();
-                        }
-                    }
-                    _ => {
-                        str_builder.push(character);
-                        continue;
-                    },
+            let mut normals: Vec<usize> = vec![];
+            for token in line.split_whitespace().skip(1) {
+                let mut indices = token.split('/');
+                match indices.next().and_then(Parser::parse_int) {
+                    Some(index) => verts.push(index as usize),
+                    None => continue,
+                }
+                if let Some(index) = indices.nth(1).and_then(Parser::parse_int) {
+                    normals.push(index as usize);
                 }
             }
 
-            // Try to parse on end of line
-            let result = Parser::parse_int(&str_builder);
-            if !result.is_none() {
-                verts.push(result.unwrap() as usize);
+            // Use the file's normals when every vertex has one
+            let has_normals = verts.len() >= 3 && normals.len() == verts.len()
+                && normals.iter().all(|index| *index >= 1 && *index <= self.normals.len());
+            if has_normals {
+                let material = Material::glass();
+                for i in 1..verts.len() - 1 {
+                    let triangle = SmoothTriangle::new_with_material(
+                        self.vertices[verts[0]], self.vertices[verts[i]], self.vertices[verts[i + 1]],
+                        self.normals[normals[0]], self.normals[normals[i]], self.normals[normals[i + 1]],
+                        material.clone(), shape_list);
+                    self.pending_triangles.push(Box::new(triangle));
+                }
+                return;
             }
 
             if verts.len() >= 3 {
@@ -181,8 +454,13 @@ pub mod obj_loader {
                     polygon.push(self.vertices[verts[i]])
                 }
                 let triangles = Parser::fan_triangulations(polygon, shape_list);
-                for tri in triangles {
-                    self.default_group.add_child(&mut tri.clone(), shape_list);
+                for (i, mut tri) in triangles.into_iter().enumerate() {
+                    if self.smoothing {
+                        if let Some(triangle) = tri.as_any_mut().downcast_mut::<Triangle>() {
+                            triangle.set_smooth([verts[0], verts[i + 1], verts[i + 2]], shape_list);
+                        }
+                    }
+                    self.pending_triangles.push(tri);
                 }
             }
         }
@@ -196,7 +474,7 @@ pub mod obj_loader {
             }
         }
 
-        fn parse_int(num_str: &String) -> Option<i32>{
+        fn parse_int(num_str: &str) -> Option<i32>{
             let result = num_str.parse::<i32>();
             if result.is_err() {
                 return None
@@ -226,15 +504,55 @@ pub mod obj_loader {
         fn file_obj_parse_ignore() {
             let mut shape_list = ShapeList::new();
             let parser = Parser::parse_obj_file("Obj/gibberish.obj", &mut shape_list);
-            assert_eq!(parser.unwrap().ignored_lines, 5);
+            let uparser = parser.unwrap();
+            assert_eq!(uparser.skipped_directives.values().sum::<usize>(), 5);
+            assert_eq!(uparser.skipped_directives["There"], 1);
+        }
+
+        #[test]
+        fn file_obj_parse_skipped_directives() {
+            let mut shape_list = ShapeList::new();
+            let parser = Parser::parse_obj_file("Obj/directives.obj", &mut shape_list);
+            let uparser = parser.unwrap();
+            assert_eq!(uparser.skipped_directives["vt"], 3);
+            assert!(!uparser.skipped_directives.contains_key("vn"));
+            assert!(!uparser.skipped_directives.contains_key("s"));
+            assert_eq!(uparser.skipped_directives["o"], 1);
+            assert_eq!(uparser.skipped_directives["mtllib"], 1);
+            assert!(!uparser.skipped_directives.contains_key("v"));
+
+            // Texture coordinates are not mistaken for vertices
+            assert_eq!(uparser.vertices.len(), 3);
+            assert_eq!(uparser.normals.len(), 1);
+            assert_eq!(uparser.default_group.children_ids.len(), 1);
         }
 
         #[test]
         fn file_obj_parse_vertex() {
             let mut shape_list = ShapeList::new();
             let parser = Parser::parse_obj_file("Obj/vertex.obj", &mut shape_list);
-            let uparser = parser.unwrap();
-            assert_eq!(uparser.vertices[1], point(-1.0, 1.0, 0.0))
+            let mut uparser = parser.unwrap();
+            assert_eq!(uparser.vertices[1], point(-1.0, 1.0, 0.0));
+
+            // The last coordinate is read without any whitespace after it
+            uparser.parse_vertex(&String::from("v 1.5 -2 3.25"));
+            assert_eq!(uparser.vertices[uparser.vertices.len()], point(1.5, -2.0, 3.25));
+        }
+
+        #[test]
+        fn file_obj_parse_with_progress() {
+            let mut shape_list = ShapeList::new();
+            let calls = std::cell::RefCell::new(vec![]);
+            let parser = Parser::parse_obj_file_with_progress("Obj/vertex.obj", &mut shape_list, |processed, total| {
+                calls.borrow_mut().push((processed, total));
+            });
+            assert!(parser.is_ok());
+
+            let calls = calls.into_inner();
+            let total = calls[0].1;
+            assert_eq!(calls.len(), total);
+            assert_eq!(calls.last(), Some(&(total, total)));
+            assert!(calls.windows(2).all(|w| w[1].0 == w[0].0 + 1));
         }
 
         #[test]
@@ -258,6 +576,74 @@ pub mod obj_loader {
             assert_eq!(t2.p3, uparser.vertices[4]);
         }
 
+        #[test]
+        fn file_obj_parse_smooth() {
+            let mut shape_list = ShapeList::new();
+            let uparser = Parser::parse_obj_file("Obj/smooth.obj", &mut shape_list).unwrap();
+            let g = shape_list.get(uparser.default_group.id);
+            let g = g.as_any().downcast_ref::<Group>().unwrap();
+            assert_eq!(g.vertex_normal(1), vector(-1.0, 1.0, 0.0).normalize());
+            assert_eq!(g.vertex_normal(2), vector(0.0, 1.0, 0.0));
+            assert_eq!(g.vertex_normal(3), vector(0.0, 1.0, 0.0));
+            assert_eq!(g.vertex_normal(4), vector(1.0, 1.0, 0.0).normalize());
+
+            let t1 = shape_list.get(g.children_ids[0]);
+            assert!(t1.as_any().downcast_ref::<Triangle>().unwrap().smooth);
+            let t3 = shape_list.get(g.children_ids[2]);
+            assert!(!t3.as_any().downcast_ref::<Triangle>().unwrap().smooth);
+
+            // Normals are interpolated across the ridge instead of being flat
+            let n = crate::shape::normal_at(t1.clone(), point(0.0, 1.0, 0.5), &mut shape_list);
+            assert_eq!(n, vector(0.0, 1.0, 0.0));
+            let n = crate::shape::normal_at(t1, point(-1.0, 0.0, 0.0), &mut shape_list);
+            assert_eq!(n, vector(-1.0, 1.0, 0.0).normalize());
+            let n = crate::shape::normal_at(t3.clone(), point(0.0, -1.0, 1.0), &mut shape_list);
+            assert_eq!(n, t3.as_any().downcast_ref::<Triangle>().unwrap().normal);
+
+            // Files without the directive stay flat
+            let mut shape_list = ShapeList::new();
+            let mut uparser = Parser::parse_obj_file("Obj/faces.obj", &mut shape_list).unwrap();
+            assert!(uparser.default_group.vertex_normals.is_empty());
+
+            // Any numbered smoothing group turns smoothing on, `off`, `0` or no argument turn it off
+            for (line, smoothing) in [("s 1", true), ("s off", false), ("s 2", true), ("s 0", false), ("s 3", true), ("s", false)].iter() {
+                uparser.parse_smoothing(line);
+                assert_eq!(uparser.smoothing, *smoothing);
+            }
+        }
+
+        #[test]
+        fn file_obj_parse_vertex_normals() {
+            let mut shape_list = ShapeList::new();
+            let uparser = Parser::parse_obj_file("Obj/normals.obj", &mut shape_list).unwrap();
+            assert_eq!(uparser.normals.len(), 3);
+            assert_eq!(uparser.normals[1], vector(-1.0, 0.0, 0.0));
+            assert_eq!(uparser.normals[3], vector(0.0, 1.0, 0.0));
+
+            let g = uparser.default_group;
+            assert_eq!(g.children_ids.len(), 4);
+            for id in g.children_ids[0..3].iter() {
+                assert_eq!(shape_list.get(*id).shape_type(), "smooth_triangle");
+            }
+            let t1b = shape_list.get(g.children_ids[0]);
+            let t1 = t1b.as_any().downcast_ref::<SmoothTriangle>().unwrap();
+            assert_eq!(t1.p1, uparser.vertices[1]);
+            assert_eq!(t1.p2, uparser.vertices[2]);
+            assert_eq!(t1.p3, uparser.vertices[3]);
+            assert_eq!(t1.n1, uparser.normals[3]);
+            assert_eq!(t1.n2, uparser.normals[1]);
+            assert_eq!(t1.n3, uparser.normals[2]);
+
+            // The second half of the fanned quad
+            let t3b = shape_list.get(g.children_ids[2]);
+            let t3 = t3b.as_any().downcast_ref::<SmoothTriangle>().unwrap();
+            assert_eq!(t3.p3, uparser.vertices[4]);
+            assert_eq!(t3.n3, uparser.normals[2]);
+
+            // Faces without normals stay flat
+            assert_eq!(shape_list.get(g.children_ids[3]).shape_type(), "triangle");
+        }
+
         #[test]
         fn file_obj_parse_polygon() {
             let mut shape_list = ShapeList::new();
@@ -6,6 +6,8 @@ use crate::FLOAT_THRESHOLD;
 use std::cmp::Ordering;
 use std::f64::MAX;
 use std::fmt::{Display, Formatter, Error};
+use std::num::ParseFloatError;
+use std::str::FromStr;
 
 #[derive(Debug, Copy, Clone)]
 pub struct Float(pub f64);
@@ -82,9 +84,18 @@ impl PartialOrd<Float> for Float {
 }
 
 // Display formatter
+// Shows the inner value and respects options like precision, e.g. "{:.2}"
 impl Display for Float {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        write!(f, "{}F", self.value())
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl FromStr for Float {
+    type Err = ParseFloatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        f64::from_str(s).map(Float)
     }
 }
 
@@ -120,6 +131,17 @@ mod tests {
         assert_eq!((6.0 / &a).value(), -3.0);
     }
 
+    #[test]
+    fn float_display_and_parse() {
+        assert_eq!(format!("{}", Float(2.5)), "2.5");
+        assert_eq!(format!("{:.2}", Float(1.0 / 3.0)), "0.33");
+        assert_eq!(format!("{}", Float(-3.0)), "-3");
+
+        assert_eq!("2.5".parse::<Float>().unwrap().value(), 2.5);
+        assert_eq!("-1e-3".parse::<Float>().unwrap().value(), -0.001);
+        assert!("abc".parse::<Float>().is_err());
+    }
+
     #[test]
     fn float_performance() {
 
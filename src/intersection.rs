@@ -4,15 +4,18 @@
 
 use crate::float::Float;
 use crate::ray::Ray;
-use crate::tuple::Tuple;
+use crate::tuple::{Tuple, vector};
 use crate::shape::Shape;
+use crate::shape::sphere::Sphere;
 use crate::{tuple, FLOAT_THRESHOLD, shape};
 use crate::shape::shape_list::ShapeList;
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct Intersection<T> {
     pub t: Float,
-    pub object: T  // object that was intersected
+    pub object: T,  // object that was intersected
+    pub u: Float, // Barycentric coordinates of a triangle hit, zero for other shapes
+    pub v: Float,
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -28,11 +31,35 @@ pub struct PrecomputedData<T> {
     pub inside: bool,
     pub n1: Float, // Refraction data
     pub n2: Float, // Refraction data
+    pub tangentv: Tuple, // Direction u increases, zero if the shape has no uv mapping
+    pub bitangentv: Tuple, // Direction v increases, zero if the shape has no uv mapping
+}
+
+/// The hit of a ray along with every intersection, sorted ascending by t value
+#[derive(Debug, PartialEq, Clone)]
+pub struct IntersectionResult<T> {
+    pub hit: Option<Intersection<T>>,
+    pub all: Vec<Intersection<T>>,
 }
 
 impl<T> Intersection<T> {
     pub fn new(t: f64, object: T) -> Intersection<T> {
-       Intersection {t: Float(t), object}
+       Intersection {t: Float(t), object, u: Float(0.0), v: Float(0.0)}
+    }
+
+    /// Creates an intersection that remembers where on a triangle it hit
+    pub fn new_with_uv(t: f64, object: T, u: f64, v: f64) -> Intersection<T> {
+        Intersection {t: Float(t), object, u: Float(u), v: Float(v)}
+    }
+
+    /// Alias of `new`
+    pub fn from_t_and_shape(t: f64, shape: T) -> Intersection<T> {
+        Intersection::new(t, shape)
+    }
+
+    /// The t value as an f64, same as `self.t.value()`
+    pub fn hit_t(&self) -> f64 {
+        self.t.value()
     }
 }
 
@@ -63,6 +90,16 @@ pub fn hit<T>(intersections: Vec<Intersection<T>>) -> Option<Intersection<T>> {
     }
 }
 
+/// Returns every intersection with a positive t value sorted ascending by t,
+/// the surfaces a ray passes through in order
+pub fn all_hits<T>(intersections: Vec<Intersection<T>>) -> Vec<Intersection<T>> {
+    let mut hits: Vec<Intersection<T>> = intersections.into_iter()
+        .filter(|intersect| intersect.t > Float(0.0))
+        .collect();
+    hits.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+    hits
+}
+
 pub fn prepare_computations_single_intersection(intersection: Intersection<Box<dyn Shape + Send>>,
                                                 ray: &Ray, shape_list: &mut ShapeList) -> PrecomputedData<Box<dyn Shape + Send>> {
     prepare_computations(intersection.clone(), ray, vec![intersection], shape_list)
@@ -72,7 +109,7 @@ pub fn prepare_computations(intersection: Intersection<Box<dyn Shape + Send>>, r
                             intersections: Vec<Intersection<Box<dyn Shape + Send>>>, shape_list: &mut ShapeList) -> PrecomputedData<Box<dyn Shape + Send>> {
 
     let point = ray.position(intersection.t.value());
-    let mut normalv =  shape::normal_at(intersection.object.clone(), point, shape_list);
+    let mut normalv =  shape::normal_at_uv(intersection.object.clone(), point, intersection.u.value(), intersection.v.value(), shape_list);
     let eyev = -ray.direction;
     let inside = Float(tuple::dot(&normalv, &eyev)) < Float(0.0);
 
@@ -84,6 +121,8 @@ pub fn prepare_computations(intersection: Intersection<Box<dyn Shape + Send>>, r
 
     let reflectv = ray.direction.reflect(&normalv);
 
+    let (tangentv, bitangentv) = surface_tangents(intersection.object.clone(), point, shape_list);
+
     // Calculate n1 and n2 for refractions
     let mut n1 = Float(1.0);
     let mut n2 = Float(1.0);
@@ -139,6 +178,23 @@ pub fn prepare_computations(intersection: Intersection<Box<dyn Shape + Send>>, r
         inside,
         n1,
         n2,
+        tangentv,
+        bitangentv,
+    }
+}
+
+/// Returns the world space (tangent, bitangent) of a shape's uv mapping at a point
+/// or zero vectors if the shape has no uv derivatives
+fn surface_tangents(object: Box<dyn Shape + Send>, world_point: Tuple, shape_list: &mut ShapeList) -> (Tuple, Tuple) {
+    match object.shape_type().as_ref() {
+        "sphere" => {
+            let local_point = shape::world_to_object(object.clone(), world_point, shape_list);
+            let (du, dv) = Sphere::uv_derivatives(&local_point);
+            // Derivatives are gradients, so they transform like normals
+            (shape::normal_to_world(object.clone(), du, shape_list),
+             shape::normal_to_world(object, dv, shape_list))
+        }
+        _ => (vector(0.0, 0.0, 0.0), vector(0.0, 0.0, 0.0))
     }
 }
 
@@ -168,8 +224,7 @@ pub fn schlick(comps: PrecomputedData<Box<dyn Shape + Send>>) -> Float {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::shape::sphere::Sphere;
-    use crate::tuple::{point, vector};
+    use crate::tuple::point;
     use crate::{FLOAT_THRESHOLD, transformation};
     use crate::shape::plane::Plane;
     use crate::material::Material;
@@ -183,6 +238,16 @@ mod tests {
         let i = Intersection::new(3.5, &s);
         assert_eq!(i.t, 3.5);
         assert_eq!(i.object, &s);
+        assert_eq!(i.u, 0.0);
+        assert_eq!(i.v, 0.0);
+
+        let i = Intersection::new_with_uv(3.5, &s, 0.2, 0.4);
+        assert_eq!(i.u, 0.2);
+        assert_eq!(i.v, 0.4);
+
+        let i = Intersection::from_t_and_shape(-1.25, &s);
+        assert_eq!(i, Intersection::new(-1.25, &s));
+        assert_eq!(i.hit_t(), -1.25);
     }
 
     #[test]
@@ -235,6 +300,20 @@ mod tests {
         assert_eq!(i, Some(i4));
     }
 
+    #[test]
+    fn intersection_all_hits() {
+        let mut shape_list = ShapeList::new();
+        let s = Sphere::new(&mut shape_list);
+        let i1 = Intersection::new(5.0, &s);
+        let i2 = Intersection::new(-3.0, &s);
+        let i3 = Intersection::new(2.0, &s);
+        let i4 = Intersection::new(0.0, &s);
+        let hits = all_hits(vec![i1, i2, i3, i4]);
+        assert_eq!(hits, vec![i3, i1]);
+
+        assert!(all_hits(vec![i2, i4]).is_empty());
+    }
+
     #[test]
     fn intersection_prep() {
         let mut shape_list = ShapeList::new();
@@ -265,6 +344,25 @@ mod tests {
         assert_eq!(comps.normalv, vector(0.0, 0.0, -1.0)); // inverted from (0, 0, 1)
     }
 
+    #[test]
+    fn intersection_prep_tangents() {
+        let mut shape_list = ShapeList::new();
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let mut s = Sphere::new(&mut shape_list);
+        s.set_transform(scaling(2.0, 2.0, 2.0), &mut shape_list);
+        let i = Intersection::new(3.0, Box::new(s) as Box<dyn Shape + Send>);
+        let comps = prepare_computations_single_intersection(i, &r, &mut shape_list);
+        assert_eq!(comps.tangentv, vector(1.0, 0.0, 0.0));
+        assert_eq!(comps.bitangentv, vector(0.0, 1.0, 0.0));
+
+        // Shapes without a uv mapping have no tangents
+        let shape: Box<dyn Shape + Send> = Box::new(Plane::new(&mut shape_list));
+        let r = Ray::new(point(0.0, 1.0, 0.0), vector(0.0, -1.0, 0.0));
+        let comps = prepare_computations_single_intersection(Intersection::new(1.0, shape), &r, &mut shape_list);
+        assert_eq!(comps.tangentv, vector(0.0, 0.0, 0.0));
+        assert_eq!(comps.bitangentv, vector(0.0, 0.0, 0.0));
+    }
+
     #[test]
     fn intersection_over_point() {
         let mut shape_list = ShapeList::new();
@@ -4,22 +4,108 @@
 use super::tuple::Tuple;
 use super::color::Color;
 use crate::material::Material;
-use crate::{tuple, intersection};
+use crate::tuple;
 use crate::float::Float;
 use crate::shape::Shape;
 use rand::{Rng};
 use crate::world::World;
 use crate::shape::shape_list::ShapeList;
 use crate::ray::Ray;
+use crate::FLOAT_THRESHOLD;
+use serde::{Serialize, Deserialize};
 
 const DEFAULT_RAY_COUNT: usize = 100;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(into = "LightDef", from = "LightDef")]
 pub struct Light {
     pub position: Tuple,
     pub intensity: Color,
     pub radius: Option<f64>,
     pub ray_count: usize,
+    /// Axis of a spotlight's beam, `None` for lights shining in every direction
+    pub direction: Option<Tuple>,
+    /// Angle from the axis in radians where a spotlight starts to fade out
+    pub inner_angle: Option<f64>,
+    /// Angle from the axis in radians where a spotlight gives no light
+    pub outer_angle: Option<f64>,
+    /// Shines parallel rays down `direction` from infinitely far away, ignoring `position`
+    pub directional: bool,
+}
+
+/// How a light is stored in scene files, the `type` field tells the kinds of lights apart
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum LightDef {
+    Point {
+        position: [f64; 3],
+        intensity: [f64; 3],
+        #[serde(default = "default_ray_count")]
+        ray_count: usize,
+    },
+    Area {
+        position: [f64; 3],
+        intensity: [f64; 3],
+        radius: f64,
+        #[serde(default = "default_ray_count")]
+        ray_count: usize,
+    },
+    Spot {
+        position: [f64; 3],
+        direction: [f64; 3],
+        intensity: [f64; 3],
+        inner_angle: f64,
+        outer_angle: f64,
+    },
+    Directional {
+        direction: [f64; 3],
+        intensity: [f64; 3],
+    },
+}
+
+fn default_ray_count() -> usize {
+    DEFAULT_RAY_COUNT
+}
+
+impl From<Light> for LightDef {
+    fn from(light: Light) -> LightDef {
+        let position = [light.position.x.value(), light.position.y.value(), light.position.z.value()];
+        let intensity = [light.intensity.red.value(), light.intensity.green.value(), light.intensity.blue.value()];
+        if let (true, Some(direction)) = (light.directional, light.direction) {
+            return LightDef::Directional { direction: [direction.x.value(), direction.y.value(), direction.z.value()], intensity };
+        }
+        if let (Some(direction), Some(inner_angle), Some(outer_angle)) = (light.direction, light.inner_angle, light.outer_angle) {
+            let direction = [direction.x.value(), direction.y.value(), direction.z.value()];
+            return LightDef::Spot { position, direction, intensity, inner_angle, outer_angle };
+        }
+        match light.radius {
+            None => LightDef::Point { position, intensity, ray_count: light.ray_count },
+            Some(radius) => LightDef::Area { position, intensity, radius, ray_count: light.ray_count },
+        }
+    }
+}
+
+impl From<LightDef> for Light {
+    fn from(def: LightDef) -> Light {
+        let (position, intensity, radius, ray_count) = match def {
+            LightDef::Point { position, intensity, ray_count } => (position, intensity, None, ray_count),
+            LightDef::Area { position, intensity, radius, ray_count } => (position, intensity, Some(radius), ray_count),
+            LightDef::Spot { position, direction, intensity, inner_angle, outer_angle } => return Light::spot_light(
+                &tuple::point(position[0], position[1], position[2]),
+                &tuple::vector(direction[0], direction[1], direction[2]),
+                &Color::new(intensity[0], intensity[1], intensity[2]),
+                inner_angle, outer_angle),
+            LightDef::Directional { direction, intensity } => return Light::directional_light(
+                &tuple::vector(direction[0], direction[1], direction[2]),
+                &Color::new(intensity[0], intensity[1], intensity[2])),
+        };
+        Light {
+            position: tuple::point(position[0], position[1], position[2]),
+            intensity: Color::new(intensity[0], intensity[1], intensity[2]),
+            radius, ray_count,
+            direction: None, inner_angle: None, outer_angle: None, directional: false,
+        }
+    }
 }
 
 impl Light {
@@ -27,49 +113,130 @@ impl Light {
         Light {
             position: *position, intensity: *intensity,
             radius: None, ray_count: DEFAULT_RAY_COUNT,
+            direction: None, inner_angle: None, outer_angle: None, directional: false,
         }
     }
     pub fn area_light(position: &Tuple, intensity: &Color, radius: f64) -> Light {
         Light {
             position: *position, intensity: *intensity,
             radius: Some(radius), ray_count: DEFAULT_RAY_COUNT,
+            direction: None, inner_angle: None, outer_angle: None, directional: false,
+        }
+    }
+
+    /// Creates a point light shining a cone of light down `direction`, at full intensity
+    /// up to `inner_angle` from the axis and fading out to nothing at `outer_angle`
+    pub fn spot_light(position: &Tuple, direction: &Tuple, intensity: &Color, inner_angle: f64, outer_angle: f64) -> Light {
+        Light {
+            position: *position, intensity: *intensity,
+            radius: None, ray_count: DEFAULT_RAY_COUNT,
+            direction: Some(direction.normalize()), inner_angle: Some(inner_angle), outer_angle: Some(outer_angle),
+            directional: false,
+        }
+    }
+
+    /// Creates a sun-like light shining parallel rays down `direction` from infinitely far away
+    pub fn directional_light(direction: &Tuple, intensity: &Color) -> Light {
+        Light {
+            position: tuple::point(0.0, 0.0, 0.0), intensity: *intensity,
+            radius: None, ray_count: DEFAULT_RAY_COUNT,
+            direction: Some(direction.normalize()), inner_angle: None, outer_angle: None,
+            directional: true,
         }
     }
 
+    /// Returns the direction from a point towards `target` on the light and the distance to it,
+    /// directional lights are infinitely far away against their direction whatever the target
+    pub fn direction_from(&self, point: &Tuple, target: &Tuple) -> (Tuple, f64) {
+        if let (true, Some(direction)) = (self.directional, self.direction) {
+            return (-direction, f64::INFINITY);
+        }
+        let mut vector = target - point;
+        vector.w = Float(0.0);
+        (vector.normalize(), vector.magnitude())
+    }
+
+    /// Returns how much of the light reaches a point in [0, 1] based on the spotlight's cone,
+    /// always 1.0 for lights that aren't spotlights
+    pub fn spot_factor(&self, point: &Tuple) -> f64 {
+        let (direction, inner_angle, outer_angle) = match (self.direction, self.inner_angle, self.outer_angle) {
+            (Some(direction), Some(inner_angle), Some(outer_angle)) => (direction, inner_angle, outer_angle),
+            _ => return 1.0,
+        };
+        let cos_angle = tuple::dot(&(point - self.position).normalize(), &direction);
+        let (cos_inner, cos_outer) = (inner_angle.cos(), outer_angle.cos());
+        if cos_inner - cos_outer <= FLOAT_THRESHOLD {
+            // No falloff, a hard edged beam
+            return if cos_angle >= cos_outer { 1.0 } else { 0.0 };
+        }
+        ((cos_angle - cos_outer) / (cos_inner - cos_outer)).clamp(0.0, 1.0)
+    }
+
+    /// Returns a (position, direction) sample of light being emitted from the light source
+    ///
+    /// Point lights emit in every direction from their position. Area lights emit
+    /// from a random point on their surface into the hemisphere facing outwards.
+    pub fn sample_point(&self, rng: &mut impl Rng) -> (Tuple, Tuple) {
+        let direction = random_unit_vector(rng);
+        match self.radius {
+            None => (self.position, direction),
+            Some(radius) => {
+                let surface_normal = random_unit_vector(rng);
+                let position = self.position + surface_normal * radius;
+                // Flip the direction into the surface normal's hemisphere
+                if tuple::dot(&direction, &surface_normal) < 0.0 {
+                    (position, -direction)
+                } else {
+                    (position, direction)
+                }
+            }
+        }
+    }
+
+    /// Returns a random point a shadow ray can be aimed at,
+    /// anywhere inside of an area light's sphere or the position of a point light
+    pub fn random_point(&self, rng: &mut impl Rng) -> Tuple {
+        let radius = match self.radius {
+            Some(radius) => radius,
+            None => return self.position,
+        };
+        let mut x = rng.gen::<f64>() - 0.5;
+        let mut y = rng.gen::<f64>() - 0.5;
+        let mut z = rng.gen::<f64>() - 0.5;
+        let magnitude = (x*x + y*y + z*z).sqrt();
+        x /= magnitude;
+        y /= magnitude;
+        z /= magnitude;
+
+        let distance = rng.gen::<f64>().cbrt() * radius;
+        self.position + tuple::point(x * distance, y * distance, z * distance)
+    }
+
+    /// Returns the surface area of an area light's sphere, 0.0 for point lights
+    pub fn surface_area(&self) -> f64 {
+        self.radius.map_or(0.0, |radius| 4.0 * std::f64::consts::PI * radius * radius)
+    }
+
+    /// Amount of shadow rays used to find how much of the light reaches a point
+    pub fn shadow_ray_count(&self) -> usize {
+        if self.radius.is_some() { self.ray_count } else { 1 }
+    }
+
     fn compute_average_rays_to(&self, point: &Tuple, world: &World, shape_list: &mut ShapeList) -> Color {
         let mut rng = rand::thread_rng();
-        let mut ray_hits: i32 = 0;
+        let mut transmitted = Color::black();
         for _ in 0..self.ray_count {
-            let mut x = rng.gen::<f64>() - 0.5;
-            let mut y = rng.gen::<f64>() - 0.5;
-            let mut z = rng.gen::<f64>() - 0.5;
-            let magnitude = (x*x + y*y + z*z).sqrt();
-            x /= magnitude;
-            y /= magnitude;
-            z /= magnitude;
-
-            let distance = rng.gen::<f64>().cbrt() * self.radius.unwrap();
-            let random_point = self.position + tuple::point(x * distance, y * distance, z * distance);
+            let random_point = self.random_point(&mut rng);
             let mut vector = random_point - point;
             vector.w = Float(0.0);
             let to_light_distance = vector.magnitude();
             let direction = vector.normalize();
 
+            // Transparent objects only tint the light, the same as for point lights
             let ray = Ray::new(*point, direction);
-            let intersections = world.intersects(&ray, shape_list);
-            let hit = intersection::hit(intersections);
-
-            // If there is a hit and the t value is less than the distance to the light,
-            // add a hit counter
-            if hit.is_some() {
-                if hit.unwrap().t < Float(to_light_distance) {
-                    ray_hits += 1;
-                }
-            }
+            transmitted = transmitted + world.transmittance(&ray, to_light_distance, shape_list);
         }
-        let average_ray_hits = (self.ray_count as i32 - ray_hits) as f64 / self.ray_count as f64;
-        let ret = Color::new(average_ray_hits, average_ray_hits, average_ray_hits);
-        ret
+        transmitted * (1.0 / self.ray_count as f64)
     }
 
 
@@ -81,7 +248,7 @@ impl Light {
                     over_point: Option<&Tuple>,
                     eye_v: &Tuple,
                     normal_v: &Tuple,
-                    in_shadow: bool,
+                    shadow_color: Color,
                     shape_list: Option<&mut ShapeList>) -> Color {
 
         let color: Color;
@@ -95,7 +262,7 @@ impl Light {
         let effective_color = color * light_source.intensity;
 
         // Find the direction to the light source
-        let light_v = (light_source.position - point).normalize();
+        let light_v = light_source.direction_from(point, &light_source.position).0;
 
         // Compute ambient
         let ambient = effective_color * material.ambient.value();
@@ -112,17 +279,24 @@ impl Light {
         // If the light does not have soft shadows
         if light_source.radius == None {
 
-            // If light misses the surface or the surface is in shadow,
-            // ignore diffuse and specular components
-            if light_dot_normal < Float(0.0) || in_shadow {
+            // If light misses the surface, ignore diffuse and specular components
+            if light_dot_normal < Float(0.0) {
                 diffuse = Color::new(0.0, 0.0, 0.0); // black
                 specular = Color::new(0.0, 0.0, 0.0); // black
                 return ambient + diffuse + specular
             }
-            light_intensity = light_source.intensity;
+            // The shadow color filters the light, black when it is fully blocked
+            light_intensity = light_source.intensity * shadow_color * light_source.spot_factor(point);
+            if light_intensity.is_black() {
+                return ambient
+            }
         } else {
             // Compute light intensity for soft shadows by averaging ray misses
             light_intensity = light_source.compute_average_rays_to(over_point.unwrap(), world.unwrap(), shape_list.unwrap());
+            // Fully shadowed from the area light
+            if light_intensity.is_black() {
+                return ambient
+            }
         }
 
         // Compute diffuse
@@ -146,6 +320,19 @@ impl Light {
 }
 
 
+/// Returns a uniformly distributed random unit vector
+pub(crate) fn random_unit_vector(rng: &mut impl Rng) -> Tuple {
+    loop {
+        // Rejection sample the unit ball so directions are not biased towards the corners
+        let x = rng.gen::<f64>() * 2.0 - 1.0;
+        let y = rng.gen::<f64>() * 2.0 - 1.0;
+        let z = rng.gen::<f64>() * 2.0 - 1.0;
+        let magnitude_squared = x*x + y*y + z*z;
+        if magnitude_squared > FLOAT_THRESHOLD && magnitude_squared <= 1.0 {
+            return tuple::vector(x, y, z).normalize();
+        }
+    }
+}
 
 
 #[cfg(test)]
@@ -162,12 +349,42 @@ mod tests {
         assert_eq!(light.intensity, i);
     }
 
+    #[test]
+    fn light_yaml() {
+        let light = Light::point_light(&point(-10.0, 10.0, -10.0), &Color::new(1.0, 0.9, 0.8));
+        let yaml = serde_yaml::to_string(&light).unwrap();
+        assert!(yaml.contains("type: point"));
+        assert!(!yaml.contains("radius"));
+        assert_eq!(serde_yaml::from_str::<Light>(&yaml).unwrap(), light);
+
+        let mut light = Light::area_light(&point(0.0, 5.0, 0.0), &Color::new(1.0, 1.0, 1.0), 0.5);
+        light.ray_count = 16;
+        let yaml = serde_yaml::to_string(&light).unwrap();
+        assert!(yaml.contains("type: area"));
+        assert_eq!(serde_yaml::from_str::<Light>(&yaml).unwrap(), light);
+
+        // The ray count can be left out
+        let light: Light = serde_yaml::from_str("type: area\nposition: [1, 2, 3]\nintensity: [1, 1, 1]\nradius: 2\n").unwrap();
+        assert_eq!(light, Light::area_light(&point(1.0, 2.0, 3.0), &Color::new(1.0, 1.0, 1.0), 2.0));
+        assert!(serde_yaml::from_str::<Light>("type: laser\nposition: [0, 0, 0]\nintensity: [1, 1, 1]\n").is_err());
+
+        let light = Light::spot_light(&point(0.0, 5.0, 0.0), &vector(0.0, -1.0, 0.0), &Color::new(1.0, 1.0, 1.0), 0.2, 0.4);
+        let yaml = serde_yaml::to_string(&light).unwrap();
+        assert!(yaml.contains("type: spot"));
+        assert_eq!(serde_yaml::from_str::<Light>(&yaml).unwrap(), light);
+
+        let light = Light::directional_light(&vector(1.0, -1.0, 0.0), &Color::new(1.0, 1.0, 0.9));
+        let yaml = serde_yaml::to_string(&light).unwrap();
+        assert!(yaml.contains("type: directional"));
+        assert_eq!(serde_yaml::from_str::<Light>(&yaml).unwrap(), light);
+    }
+
     #[test]
     fn light_lighting() {
         let m = Material::new();
         let position = point(0.0, 0.0, 0.0);
 
-        let in_shadow = false;
+        let in_shadow = Color::white();
 
         // Lighting with the eye between the light and the surface
         let eye_v = vector(0.0, 0.0, -1.0);
@@ -212,8 +429,66 @@ mod tests {
         let eye_v = vector(0.0, 0.0, -1.0);
         let normal_v = vector(0.0, 0.0, -1.0);
         let light = Light::point_light(&point(0.0, 0.0, -10.0), &Color::new(1.0, 1.0, 1.0));
-        let in_shadow = true;
+        let in_shadow = Color::black();
         let result = Light::lighting(&m, None, None, &light, &position, None, &eye_v, &normal_v, in_shadow, None);
         assert_eq!(result, Color::new(0.1, 0.1, 0.1));
     }
+
+    #[test]
+    fn light_spot_light() {
+        let m = Material::new();
+        let eye_v = vector(0.0, 0.0, -1.0);
+        let normal_v = vector(0.0, 0.0, -1.0);
+        let light = Light::spot_light(&point(0.0, 0.0, -10.0), &vector(0.0, 0.0, 2.0), &Color::new(1.0, 1.0, 1.0), 0.1, 0.2);
+        assert_eq!(light.direction, Some(vector(0.0, 0.0, 1.0)));
+        let lighting = |p: Tuple| Light::lighting(&m, None, None, &light, &p, None, &eye_v, &normal_v, Color::white(), None);
+
+        // On the axis gets the full light, the same as a point light
+        let on_axis = point(0.0, 0.0, 0.0);
+        assert_eq!(light.spot_factor(&on_axis), 1.0);
+        let point_light = Light::point_light(&point(0.0, 0.0, -10.0), &Color::new(1.0, 1.0, 1.0));
+        assert_eq!(lighting(on_axis), Light::lighting(&m, None, None, &point_light, &on_axis, None, &eye_v, &normal_v, Color::white(), None));
+
+        // At the outer angle only ambient light is left
+        let at_outer = point(10.0 * 0.2_f64.tan(), 0.0, 0.0);
+        assert_eq!(Float(light.spot_factor(&at_outer)), Float(0.0));
+        assert_eq!(lighting(at_outer), Color::new(0.1, 0.1, 0.1));
+
+        // Between the angles fades out
+        let between = point(10.0 * 0.15_f64.tan(), 0.0, 0.0);
+        let factor = light.spot_factor(&between);
+        assert!(factor > 0.0 && factor < 1.0);
+        let expected = (0.15_f64.cos() - 0.2_f64.cos()) / (0.1_f64.cos() - 0.2_f64.cos());
+        assert_eq!(Float(factor), Float(expected));
+        let color = lighting(between);
+        assert!(color.red > Float(0.1) && color.red < lighting(on_axis).red);
+
+        // Other lights are unaffected
+        assert_eq!(point_light.spot_factor(&at_outer), 1.0);
+    }
+
+    #[test]
+    fn light_sample_point() {
+        let mut rng = rand::thread_rng();
+
+        // Point lights emit from their position
+        let light = Light::point_light(&point(1.0, 2.0, 3.0), &Color::white());
+        for _ in 0..10 {
+            let (position, direction) = light.sample_point(&mut rng);
+            assert_eq!(position, point(1.0, 2.0, 3.0));
+            assert!(direction.is_vector());
+            assert_eq!(Float(direction.magnitude()), Float(1.0));
+        }
+
+        // Area lights emit outwards from their surface
+        let light = Light::area_light(&point(1.0, 2.0, 3.0), &Color::white(), 0.5);
+        for _ in 0..10 {
+            let (position, direction) = light.sample_point(&mut rng);
+            let surface_normal = position - light.position;
+            assert!(position.is_point());
+            assert_eq!(Float(surface_normal.magnitude()), Float(0.5));
+            assert_eq!(Float(direction.magnitude()), Float(1.0));
+            assert!(tuple::dot(&direction, &surface_normal) >= 0.0);
+        }
+    }
 }
@@ -27,6 +27,7 @@ pub mod camera;
 pub mod canvas;
 pub mod examples;
 pub mod file;
+pub mod scene;
 
 
 fn main() {
@@ -38,6 +39,17 @@ fn main() {
         example = &args[0]; // set to invalid example
     }
 
+    // Examples are written as PPM unless given e.g. "--format png"
+    if let Some(i) = args.iter().position(|arg| arg == "--format") {
+        match args.get(i + 1).and_then(|name| file::ImageFormat::from_name(name)) {
+            Some(format) => file::set_output_format(format),
+            None => {
+                println!("Unknown format, expected \"ppm\" or \"png\"");
+                return;
+            }
+        }
+    }
+
     match example.as_str() {
         "draw-arch" => {
             println!("Running Example \"{}\"", example);
@@ -99,13 +111,54 @@ fn main() {
             println!("Running Example \"{}\"", example);
             examples::draw_cone_scene();
         },
+        "draw-torus-scene" => {
+            println!("Running Example \"{}\"", example);
+            examples::draw_torus_scene();
+        },
+        "draw-voronoi-scene" => {
+            println!("Running Example \"{}\"", example);
+            examples::draw_voronoi_scene();
+        },
+        "draw-dof-scene" => {
+            println!("Running Example \"{}\"", example);
+            examples::draw_dof_scene();
+        },
+        "draw-spotlight-scene" => {
+            println!("Running Example \"{}\"", example);
+            examples::draw_spotlight_scene();
+        },
+        "draw-directional-light-scene" => {
+            println!("Running Example \"{}\"", example);
+            examples::draw_directional_light_scene();
+        },
+        "render-scene" => {
+            let path = match args.get(2) {
+                Some(path) => path,
+                None => {
+                    println!("Expected a scene file, e.g. \"render-scene scene.yaml\"");
+                    return;
+                }
+            };
+            println!("Rendering Scene \"{}\"", path);
+            match scene::WorldBuilder::from_file(path) {
+                Ok((world, mut shape_list, camera)) => {
+                    println!("{}", world.complexity_report(&shape_list));
+                    let canvas = camera.render(world, &mut shape_list);
+                    let name = std::path::Path::new(path).file_stem().map_or(String::from("scene"), |stem| stem.to_string_lossy().to_string());
+                    file::write_canvas(&canvas, &name);
+                },
+                Err(e) => println!("Unable to load scene: {}", e),
+            }
+        },
         "draw-hexagon-scene" => {
             println!("Running Example \"{}\"", example);
             examples::draw_hexagon_scene();
         },
         "draw-obj-scene" => {
             println!("Running Example \"{}\"", example);
-            examples::draw_obj_scene();
+            let (world, shape_list, camera) = examples::obj_scene();
+            println!("{}", world.complexity_report(&shape_list));
+            examples::draw_obj_scene(world, shape_list, camera);
         },
         "draw-csg-scene" => {
             println!("Running Example \"{}\"", example);
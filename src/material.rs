@@ -20,6 +20,9 @@ pub struct Material {
     pub normal_perturb: Option<String>,
     pub normal_perturb_factor: Option<f64>,
     pub normal_perturb_perlin: Option<CmpPerlin>,
+    /// Strength of the light given off by the surface
+    pub emission: Float,
+    pub emission_color: Color,
 }
 
 impl Material {
@@ -33,13 +36,38 @@ impl Material {
                   transparency: Float(0.0),
                   refractive_index: Float(1.0),
                   pattern: None, normal_perturb: None,
-                  normal_perturb_factor: None, normal_perturb_perlin: None}
+                  normal_perturb_factor: None, normal_perturb_perlin: None,
+                  emission: Float(0.0), emission_color: Color::black()}
+    }
+
+    /// Returns a default material that glows with `color` scaled by `strength`
+    pub fn emissive(color: Color, strength: f64) -> Material {
+        let mut material = Material::new();
+        material.emission = Float(strength);
+        material.emission_color = color;
+        material
     }
 
     pub fn set_pattern(&mut self, pattern: Box<dyn Pattern + Send>) {
         self.pattern = Some(pattern)
     }
 
+    /// Returns the light given off by the surface, black if it is not emissive
+    pub fn emitted(&self) -> Color {
+        self.emission_color * self.emission.value()
+    }
+
+    /// Returns a one line description of the material for debugging
+    pub fn summary(&self) -> String {
+        let pattern = match &self.pattern {
+            Some(pattern) => format!("Some({})", pattern.pattern_name()),
+            None => String::from("None"),
+        };
+        format!("color=#{} ambient={:?} diffuse={:?} specular={:?} reflective={:?} transparent={:?} ior={:?} pattern={}",
+                self.color.to_hex(), self.ambient.value(), self.diffuse.value(), self.specular.value(),
+                self.reflective.value(), self.transparency.value(), self.refractive_index.value(), pattern)
+    }
+
     // Common materials
 
     pub fn glass() -> Material {
@@ -52,7 +80,8 @@ impl Material {
             transparency: Float(1.0),
             refractive_index: Float(1.5),
             pattern: None, normal_perturb: None,
-            normal_perturb_factor: None, normal_perturb_perlin: None}
+            normal_perturb_factor: None, normal_perturb_perlin: None,
+            emission: Float(0.0), emission_color: Color::black()}
 }
 
 pub fn mirror() -> Material {
@@ -65,9 +94,53 @@ pub fn mirror() -> Material {
             transparency: Float(0.0),
             refractive_index: Float(1.0),
             pattern: None, normal_perturb: None,
-            normal_perturb_factor: None, normal_perturb_perlin: None}
+            normal_perturb_factor: None, normal_perturb_perlin: None,
+            emission: Float(0.0), emission_color: Color::black()}
 }
 
+    /// Glass with a rough surface, the roughness comes from
+    /// perturbing its normals with perlin noise
+    pub fn frosted_glass() -> Material {
+        let mut material = Material::glass();
+        material.diffuse = Float(0.3);
+        material.specular = Float(0.4);
+        material.shininess = Float(20.0);
+        material.reflective = Float(0.1);
+        material.transparency = Float(0.9);
+        material.normal_perturb = Some(String::from("perlin"));
+        material.normal_perturb_factor = Some(0.3);
+        material.normal_perturb_perlin = Some(CmpPerlin {perlin: Perlin::new()});
+        material
+    }
+
+    pub fn tinted_glass(color: Color) -> Material {
+        let mut material = Material::glass();
+        material.color = color;
+        material
+    }
+
+    /// A polished metal, mostly reflective with a tight highlight
+    pub fn chrome() -> Material {
+        let mut material = Material::mirror();
+        material.color = Color::new(0.8, 0.8, 0.85);
+        material.ambient = Float(0.05);
+        material.diffuse = Float(0.05);
+        material.specular = Float(1.0);
+        material.shininess = Float(500.0);
+        material.reflective = Float(0.9);
+        material
+    }
+
+    /// A flat diffuse material with no highlights
+    pub fn matte(color: Color) -> Material {
+        let mut material = Material::new();
+        material.color = color;
+        material.ambient = Float(0.1);
+        material.diffuse = Float(0.9);
+        material.specular = Float(0.0);
+        material
+    }
+
 
 // Common material values
 
@@ -117,12 +190,48 @@ mod tests {
         let eyev = vector(0.0, 0.0, -1.0);
         let normalv = vector(0.0, 0.0, -1.0);
         let light = Light::point_light(&point(0.0, 0.0, -10.0), &Color::white());
-        let c1 = Light::lighting(&m, Some(Box::new(object.clone())), None, &light, &point(0.9, 0.0, 0.0), None, &eyev, &normalv, false, None);
-        let c2 = Light::lighting(&m, Some(Box::new(object.clone())), None, &light, &point(1.1, 0.0, 0.0), None, &eyev, &normalv, false, None);
+        let c1 = Light::lighting(&m, Some(Box::new(object.clone())), None, &light, &point(0.9, 0.0, 0.0), None, &eyev, &normalv, Color::white(), None);
+        let c2 = Light::lighting(&m, Some(Box::new(object.clone())), None, &light, &point(1.1, 0.0, 0.0), None, &eyev, &normalv, Color::white(), None);
         assert_eq!(c1, Color::white());
         assert_eq!(c2, Color::black());
     }
 
+    #[test]
+    fn material_presets() {
+        let m = Material::frosted_glass();
+        assert!(m.transparency > Float(0.0));
+        assert_eq!(m.refractive_index, Material::glass().refractive_index);
+        assert_eq!(m.normal_perturb, Some(String::from("perlin")));
+
+        let red = Color::new(1.0, 0.0, 0.0);
+        let m = Material::tinted_glass(red);
+        assert_eq!(m.color, red);
+        assert_eq!(m.transparency, 1.0);
+
+        let m = Material::chrome();
+        assert!(m.reflective > Float(0.5));
+        assert_eq!(m.transparency, 0.0);
+
+        let m = Material::matte(red);
+        assert_eq!(m.color, red);
+        assert_eq!(m.ambient, 0.1);
+        assert_eq!(m.diffuse, 0.9);
+        assert_eq!(m.specular, 0.0);
+        assert_eq!(m.reflective, 0.0);
+    }
+
+    #[test]
+    fn material_summary() {
+        let mut m = Material::new();
+        m.color = Color::from_hex("FF0000");
+        m.specular = Float(0.3);
+        assert_eq!(m.summary(), "color=#FF0000 ambient=0.1 diffuse=0.9 specular=0.3 reflective=0.0 transparent=0.0 ior=1.0 pattern=None");
+
+        m.set_pattern(Box::new(StripePattern::new(Color::white(), Color::black())));
+        assert!(m.summary().ends_with(" pattern=Some(StripePattern)"));
+        assert!(Material::glass().summary().contains(" transparent=1.0 ior=1.5 "));
+    }
+
     #[test]
     fn material_reflective() {
         let m = Material::new();
@@ -116,6 +116,41 @@ impl Matrix4 {
         }
         Matrix4(new_mat)
     }
+
+    /// Returns the transpose of the inverse of the matrix
+    ///
+    /// This is the transform to use on surface normals, since a normal
+    /// transformed by the matrix itself would no longer be perpendicular
+    /// to its surface. The transpose is free here as it is the inverse
+    /// without swapping the rows and columns of the cofactors.
+    pub fn inverse_transpose(&self) -> Matrix4 {
+        assert!(self.is_invertible());
+
+        let mut new_mat= [[Float(0.0); 4]; 4];
+        let determinant = self.determinant();
+
+        for (i, row) in new_mat.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.cofactor(i, j) / determinant;
+            }
+        }
+        Matrix4(new_mat)
+    }
+
+    /// Returns the element-wise blend from `a` at t = 0.0 to `b` at t = 1.0
+    ///
+    /// This does not keep rotations rigid, halfway between two rotations is
+    /// squashed and no longer a rotation. It is only close enough for blending
+    /// transforms like view matrices that differ by small angles.
+    pub fn lerp(a: Matrix4, b: Matrix4, t: f64) -> Matrix4 {
+        let mut new_mat= [[Float(0.0); 4]; 4];
+        for (i, row) in new_mat.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = Float(a[i][j].value() + (b[i][j].value() - a[i][j].value()) * t);
+            }
+        }
+        Matrix4(new_mat)
+    }
 }
 
 impl Index<usize> for Matrix4 {
@@ -498,6 +533,10 @@ mod tests {
              [6.0, -2.0, 0.0, 5.0]]);
         let c = &a * &b;
         assert_eq!(c * b.inverse(), a);
+
+        // Inverse transpose
+        assert_eq!(a.inverse_transpose(), a.inverse().transpose());
+        assert_eq!(b.inverse_transpose(), b.inverse().transpose());
     }
 
     #[test]
@@ -537,4 +576,22 @@ mod tests {
 
         assert_eq!(a.submatrix(2, 1), b);
     }
+
+    #[test]
+    fn matrix_lerp() {
+        let a = Matrix4::identity();
+        let b = Matrix4::new(
+            [[3.0, 0.0, 0.0, 2.0],
+             [0.0, 5.0, 0.0, -4.0],
+             [0.0, 0.0, 1.0, 0.0],
+             [0.0, 0.0, 0.0, 1.0]]);
+
+        assert_eq!(Matrix4::lerp(a, b, 0.0), a);
+        assert_eq!(Matrix4::lerp(a, b, 1.0), b);
+        assert_eq!(Matrix4::lerp(a, b, 0.5), Matrix4::new(
+            [[2.0, 0.0, 0.0, 1.0],
+             [0.0, 3.0, 0.0, -2.0],
+             [0.0, 0.0, 1.0, 0.0],
+             [0.0, 0.0, 0.0, 1.0]]));
+    }
 }
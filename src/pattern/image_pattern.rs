@@ -0,0 +1,185 @@
+/// # Image Patterns
+/// `image_pattern` is a module to represent an image wrapped around a shape with its uv mapping
+use crate::color::Color;
+use crate::tuple::{Tuple, point};
+use crate::matrix::Matrix4;
+use crate::pattern::Pattern;
+use crate::shape::Shape;
+use crate::shape::shape_list::ShapeList;
+use crate::file::{self, Texture};
+use crate::float::Float;
+use std::fmt::{self, Formatter, Error};
+use std::any::Any;
+use std::io;
+use std::sync::Arc;
+
+#[derive(PartialEq, Clone)]
+pub struct ImagePattern {
+    /// Rows of pixels from the top of the image down, shared between copies of the pattern
+    pub pixels: Texture,
+    /// Applied to the (u, v, 0) point before sampling, e.g. a scaling to tile the image
+    pub transform: Matrix4,
+}
+
+impl ImagePattern {
+    pub fn new(pixels: Vec<Vec<Color>>) -> ImagePattern {
+        assert!(!pixels.is_empty() && !pixels[0].is_empty(), "Image pattern needs at least one pixel");
+        ImagePattern { pixels: Arc::new(pixels), transform: Matrix4::identity() }
+    }
+
+    /// Loads the image from a PPM file, reusing the pixels if the file was already loaded
+    pub fn from_ppm(path: &str) -> io::Result<ImagePattern> {
+        Ok(ImagePattern { pixels: file::read_texture(path)?, transform: Matrix4::identity() })
+    }
+
+    pub fn width(&self) -> usize {
+        self.pixels[0].len()
+    }
+
+    pub fn height(&self) -> usize {
+        self.pixels.len()
+    }
+
+    /// Returns the bilinearly filtered color at (u, v), (0, 0) is the bottom left
+    /// pixel and (1, 1) is the top right pixel
+    pub fn sample(&self, u: f64, v: f64) -> Color {
+        let x = Float(u).clamp(0.0, 1.0) * (self.width() - 1) as f64;
+        let y = (1.0 - Float(v).clamp(0.0, 1.0)) * (self.height() - 1) as f64;
+
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width() - 1), (y0 + 1).min(self.height() - 1));
+        let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+
+        let top = Color::lerp(self.pixels[y0][x0], self.pixels[y0][x1], fx);
+        let bottom = Color::lerp(self.pixels[y1][x0], self.pixels[y1][x1], fx);
+        Color::lerp(top, bottom, fy)
+    }
+
+    fn pattern_at_uv(&self, object: &dyn Shape, world_point: &Tuple) -> Color {
+        let object_point = object.transform().inverse() * world_point;
+        let (u, v) = object.uv_at(&object_point);
+        let pattern_point = self.transform.inverse() * point(u, v, 0.0);
+        self.pattern_at(&pattern_point)
+    }
+}
+
+impl fmt::Debug for ImagePattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.debug_fmt(f)
+    }
+}
+
+impl Pattern for ImagePattern {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| self == a)
+    }
+
+    fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "Box ImagePattern {{ {}x{}, transform: {:?} }}", self.width(), self.height(), self.transform)
+    }
+
+    fn pattern_clone(&self) -> Box<dyn Pattern + Send> {
+        Box::new(self.clone())
+    }
+
+    fn transform(&self) -> Matrix4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Matrix4) {
+        self.transform = transform;
+    }
+
+    /// Samples the image with the point's x and y as (u, v), repeating every unit
+    fn pattern_at(&self, point: &Tuple) -> Color {
+        // Keep 1.0 as the image's far edge instead of wrapping it back to 0.0
+        let wrap = |x: f64| if (0.0..=1.0).contains(&x) { x } else { x.rem_euclid(1.0) };
+        self.sample(wrap(point.x.value()), wrap(point.y.value()))
+    }
+
+    fn pattern_at_object(&self, object: Box<dyn Shape + Send>, world_point: &Tuple) -> Color {
+        self.pattern_at_uv(&*object, world_point)
+    }
+
+    fn pattern_at_shape_id(&self, id: i32, world_point: &Tuple, shape_list: &ShapeList) -> Color {
+        self.pattern_at_uv(&*shape_list[id as usize], world_point)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::Canvas;
+    use crate::shape::sphere::Sphere;
+    use crate::transformation::scaling;
+
+    fn test_image() -> Vec<Vec<Color>> {
+        // Top row red, green and bottom row blue, white
+        vec![
+            vec![Color::new(1.0, 0.0, 0.0), Color::new(0.0, 1.0, 0.0)],
+            vec![Color::new(0.0, 0.0, 1.0), Color::new(1.0, 1.0, 1.0)],
+        ]
+    }
+
+    #[test]
+    fn image_pattern_sample() {
+        let pattern = ImagePattern::new(test_image());
+        assert_eq!(pattern.width(), 2);
+        assert_eq!(pattern.height(), 2);
+
+        // Corners
+        assert_eq!(pattern.sample(0.0, 1.0), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(pattern.sample(1.0, 1.0), Color::new(0.0, 1.0, 0.0));
+        assert_eq!(pattern.sample(0.0, 0.0), Color::new(0.0, 0.0, 1.0));
+        assert_eq!(pattern.sample(1.0, 0.0), Color::new(1.0, 1.0, 1.0));
+
+        // Center averages every pixel, edges average two
+        assert_eq!(pattern.sample(0.5, 0.5), Color::new(0.5, 0.5, 0.5));
+        assert_eq!(pattern.sample(0.5, 1.0), Color::new(0.5, 0.5, 0.0));
+        assert_eq!(pattern.sample(0.0, 0.25), Color::new(0.25, 0.0, 0.75));
+
+        // Out of range coordinates clamp to the border
+        assert_eq!(pattern.sample(-1.0, 2.0), Color::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn image_pattern_at_object() {
+        let mut shape_list = ShapeList::new();
+        let pattern = ImagePattern::new(test_image());
+        let sphere = Sphere::new(&mut shape_list);
+
+        // The poles are the top and bottom rows of the image
+        let (u, v) = sphere.uv_at(&point(0.0, 1.0, 0.0));
+        assert_eq!(pattern.pattern_at_object(Box::new(sphere.clone()), &point(0.0, 1.0, 0.0)), pattern.sample(u, v));
+        assert_eq!(pattern.pattern_at_shape_id(sphere.id, &point(0.0, -1.0, 0.0), &shape_list), pattern.sample(0.5, 0.0));
+
+        // The pattern transform scales uv space
+        let mut tiled = pattern.clone();
+        tiled.set_transform(scaling(0.5, 0.5, 1.0));
+        assert_eq!(tiled.pattern_at_object(Box::new(sphere), &point(0.0, -1.0, 0.0)), pattern.sample(1.0, 0.0));
+        assert_eq!(tiled.pattern_at(&point(1.25, 0.5, 0.0)), pattern.sample(0.25, 0.5));
+    }
+
+    #[test]
+    fn image_pattern_from_ppm() {
+        let mut canvas = Canvas::new(2, 2);
+        for (y, row) in test_image().iter().enumerate() {
+            for (x, color) in row.iter().enumerate() {
+                canvas.write_pixel(y as i32, x as i32, color);
+            }
+        }
+        let path = std::env::temp_dir().join("raytracer_image_pattern.ppm");
+        let path = path.to_string_lossy().to_string();
+        file::write_to_file(canvas.to_ppm(), path.clone());
+
+        let pattern = ImagePattern::from_ppm(&path).unwrap();
+        assert_eq!(pattern, ImagePattern::new(test_image()));
+        assert_eq!(pattern.sample(0.5, 0.5), Color::new(0.5, 0.5, 0.5));
+        assert!(ImagePattern::from_ppm("does/not/exist.ppm").is_err());
+    }
+}
@@ -0,0 +1,147 @@
+/// # Layered Patterns
+/// `layered_pattern` is a module to represent a stack of patterns composited on top of each other
+use crate::color::Color;
+use crate::tuple::Tuple;
+use crate::matrix::Matrix4;
+use crate::pattern::Pattern;
+use std::fmt::{Formatter, Error};
+use std::any::Any;
+use serde::{Serialize, Deserialize};
+
+/// How a layer's color is combined with the layers below it
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlendMode {
+    Normal,
+    Average,
+    Add,
+    Multiply,
+    Screen,
+}
+
+impl BlendMode {
+    /// Returns the color of `top` blended onto `base` at full opacity
+    pub fn blend(&self, base: Color, top: Color) -> Color {
+        match self {
+            BlendMode::Normal => top,
+            BlendMode::Average => (base + top) * 0.5,
+            BlendMode::Add => base + top,
+            BlendMode::Multiply => base * top,
+            BlendMode::Screen => base + top - base * top,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LayeredPattern {
+    pub layers: Vec<(Box<dyn Pattern + Send>, BlendMode, f64)>, // Pattern, blend mode and opacity, bottom layer first
+    pub transform: Matrix4,
+}
+
+impl LayeredPattern {
+    pub fn new() -> LayeredPattern {
+        LayeredPattern { layers: vec![], transform: Matrix4::identity() }
+    }
+
+    /// Adds a layer on top of the stack
+    /// # Arguments
+    /// * `pattern` Pattern of the new layer
+    /// * `mode` How the layer is blended with the layers below it
+    /// * `opacity` 0.0 hides the layer and 1.0 fully applies it
+    pub fn with_layer(mut self, pattern: Box<dyn Pattern + Send>, mode: BlendMode, opacity: f64) -> LayeredPattern {
+        self.layers.push((pattern, mode, opacity));
+        self
+    }
+}
+
+impl Default for LayeredPattern {
+    fn default() -> Self {
+        LayeredPattern::new()
+    }
+}
+
+impl Pattern for LayeredPattern {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| self == a)
+    }
+
+    fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "Box {:?}", self)
+    }
+
+    fn pattern_clone(&self) -> Box<dyn Pattern + Send> {
+        Box::new(self.clone())
+    }
+
+    fn transform(&self) -> Matrix4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Matrix4) {
+        self.transform = transform;
+    }
+
+    fn pattern_at(&self, point: &Tuple) -> Color {
+        // Each layer is composited onto the ones below it, starting from black
+        self.layers.iter().fold(Color::black(), |base, (pattern, mode, opacity)| {
+            let blended = mode.blend(base, pattern.pattern_at(point));
+            Color::lerp(base, blended, *opacity)
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::ring_pattern::RingPattern;
+    use crate::pattern::stripe_pattern::StripePattern;
+    use crate::tuple::point;
+
+    #[test]
+    fn layered_patterns() {
+        let red = RingPattern::new(Color::from_hex("FF0000"), Color::black());
+        let blue = RingPattern::new(Color::from_hex("0000FF"), Color::black());
+
+        // Two averaged layers match a blended pattern
+        let pattern = LayeredPattern::new()
+            .with_layer(Box::new(red), BlendMode::Normal, 1.0)
+            .with_layer(Box::new(blue), BlendMode::Average, 1.0);
+        assert_eq!(pattern.pattern_at(&point(0.0, 0.0, 0.0)), Color::new(0.5, 0.0, 0.5));
+        assert_eq!(pattern.pattern_at(&point(1.0, 0.0, 0.0)), Color::black());
+
+        // Opacity mixes a layer with what is below it
+        let pattern = LayeredPattern::new()
+            .with_layer(Box::new(red), BlendMode::Normal, 1.0)
+            .with_layer(Box::new(blue), BlendMode::Normal, 0.25);
+        assert_eq!(pattern.pattern_at(&point(0.0, 0.0, 0.0)), Color::new(0.75, 0.0, 0.25));
+
+        // Any number of layers fold bottom to top
+        let stripes = StripePattern::new(Color::white(), Color::new(0.5, 0.5, 0.5));
+        let pattern = LayeredPattern::new()
+            .with_layer(Box::new(red), BlendMode::Normal, 1.0)
+            .with_layer(Box::new(blue), BlendMode::Add, 1.0)
+            .with_layer(Box::new(stripes), BlendMode::Multiply, 1.0);
+        assert_eq!(pattern.pattern_at(&point(0.0, 0.0, 0.0)), Color::new(1.0, 0.0, 1.0));
+        assert_eq!(pattern.pattern_at(&point(1.5, 0.0, 0.0)), Color::black());
+        assert_eq!(pattern.pattern_at(&point(0.0, 0.0, 1.5)), Color::black());
+
+        // An empty stack is black
+        assert_eq!(LayeredPattern::new().pattern_at(&point(0.0, 0.0, 0.0)), Color::black());
+    }
+
+    #[test]
+    fn layered_pattern_blend_modes() {
+        let base = Color::new(0.5, 0.2, 1.0);
+        let top = Color::new(0.5, 0.5, 0.0);
+        assert_eq!(BlendMode::Normal.blend(base, top), top);
+        assert_eq!(BlendMode::Average.blend(base, top), Color::new(0.5, 0.35, 0.5));
+        assert_eq!(BlendMode::Add.blend(base, top), Color::new(1.0, 0.7, 1.0));
+        assert_eq!(BlendMode::Multiply.blend(base, top), Color::new(0.25, 0.1, 0.0));
+        assert_eq!(BlendMode::Screen.blend(base, top), Color::new(0.75, 0.6, 1.0));
+    }
+}
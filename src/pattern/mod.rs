@@ -5,16 +5,32 @@ use crate::color::Color;
 use crate::tuple::Tuple;
 use crate::matrix::Matrix4;
 use crate::shape::Shape;
+use crate::shape::shape_list::ShapeList;
 use std::any::Any;
 use std::fmt::{Formatter, Error, Debug};
+use std::f64::consts::PI;
+use crate::canvas::Canvas;
+use crate::camera::Camera;
+use crate::light::Light;
+use crate::material::Material;
+use crate::shape::sphere::Sphere;
+use crate::transformation::view_transform;
+use crate::tuple::{point, vector};
+use crate::world::World;
+use crate::float::Float;
 
 pub mod test_pattern;
 pub mod stripe_pattern;
 pub mod gradient_pattern;
+pub mod radial_gradient_pattern;
 pub mod ring_pattern;
 pub mod checker_pattern;
 pub mod blended_pattern;
 pub mod perturbed_pattern;
+pub mod noise_pattern;
+pub mod layered_pattern;
+pub mod image_pattern;
+pub mod voronoi_pattern;
 
 
 pub trait Pattern: Any {
@@ -26,6 +42,11 @@ pub trait Pattern: Any {
 
     fn pattern_clone(&self) -> Box<dyn Pattern + Send>;
 
+    /// Returns the name of the pattern's type, e.g. "StripePattern"
+    fn pattern_name(&self) -> String {
+        std::any::type_name::<Self>().rsplit("::").next().unwrap_or_default().to_string()
+    }
+
     fn transform(&self) -> Matrix4;
 
     fn set_transform(&mut self, transform: Matrix4);
@@ -33,10 +54,47 @@ pub trait Pattern: Any {
     fn pattern_at(&self, point: &Tuple) -> Color;
 
     fn pattern_at_object(&self, object: Box<dyn Shape + Send>, world_point: &Tuple) -> Color {
-        let object_point = object.transform().inverse() * world_point;
+        self.pattern_at_object_transform(&object.transform(), world_point)
+    }
+
+    /// Looks up the shape in the shape list by reference instead of
+    /// requiring a cloned shape like `pattern_at_object`
+    fn pattern_at_shape_id(&self, id: i32, world_point: &Tuple, shape_list: &ShapeList) -> Color {
+        self.pattern_at_object_transform(&shape_list[id as usize].transform(), world_point)
+    }
+
+    fn pattern_at_object_transform(&self, object_transform: &Matrix4, world_point: &Tuple) -> Color {
+        let object_point = object_transform.inverse() * world_point;
         let pattern_point = self.transform().inverse() * object_point;
         self.pattern_at(&pattern_point)
     }
+
+    /// Renders a unit sphere with the pattern applied under a single point light
+    /// for previewing how the pattern looks on a shape
+    fn preview_lit(&self, width: usize, height: usize) -> Canvas {
+        let mut shape_list = ShapeList::new();
+        let mut material = Material::new();
+        material.specular = Float(0.3);
+        material.set_pattern(self.pattern_clone());
+        let sphere = Sphere::new_with_material(material, &mut shape_list);
+
+        let mut world = World::new();
+        world.objects.push(Box::new(sphere));
+        world.lights.push(Light::point_light(&point(-10.0, 10.0, -10.0), &Color::white()));
+
+        let mut camera = Camera::new(width as i32, height as i32, PI / 3.0);
+        camera.transform = view_transform(point(0.0, 0.0, -3.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0));
+
+        // Rendered directly instead of with `Camera::render` to skip its progress bar
+        let mut image = Canvas::new(width as i32, height as i32);
+        for y in 0..camera.v_size {
+            for x in 0..camera.h_size {
+                let color = camera.color_at_pixel(x, y, &world, &mut shape_list);
+                image.write_pixel(y, x, &color);
+            }
+        }
+        image
+    }
 }
 
 impl PartialEq for Box<dyn Pattern + Send> {
@@ -69,6 +127,8 @@ mod tests {
     use crate::tuple::point;
     use crate::shape::Shape;
     use crate::shape::shape_list::ShapeList;
+    use crate::pattern::stripe_pattern::StripePattern;
+    use crate::float::Float;
 
     #[test]
     fn pattern_creation() {
@@ -108,4 +168,30 @@ mod tests {
         let c = pattern.pattern_at_object(Box::new(object), &point(2.5, 3.0, 3.5));
         assert_eq!(c, Color::new(0.75, 0.5, 0.25));
     }
+
+    #[test]
+    fn pattern_at_shape_id() {
+        let mut shape_list = ShapeList::new();
+        let mut object = Sphere::new(&mut shape_list);
+        object.set_transform(scaling(2.0, 2.0, 2.0), &mut shape_list);
+        let mut pattern = TestPattern::new();
+        pattern.set_transform(translation(0.5, 1.0, 1.5));
+        let c = pattern.pattern_at_shape_id(object.id(), &point(2.5, 3.0, 3.5), &shape_list);
+        assert_eq!(c, Color::new(0.75, 0.5, 0.25));
+        assert_eq!(c, pattern.pattern_at_object(Box::new(object), &point(2.5, 3.0, 3.5)));
+    }
+
+    #[test]
+    fn pattern_preview_lit() {
+        let pattern = StripePattern::new(Color::new(1.0, 0.0, 0.0), Color::new(0.0, 0.0, 1.0));
+        let preview = pattern.preview_lit(21, 11);
+        assert_eq!(preview.width, 21);
+        assert_eq!(preview.height, 11);
+
+        // The background stays black and the lit side of the sphere shows the pattern
+        assert_eq!(preview.pixel_at(0, 0), &Color::black());
+        let center = preview.pixel_at(5, 10);
+        assert!(center.red > Float(0.5));
+        assert_eq!(center.green, 0.0);
+    }
 }
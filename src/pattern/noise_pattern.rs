@@ -0,0 +1,84 @@
+/// # Noise Patterns
+/// `noise_pattern` is a module to represent solid textures colored by perlin noise,
+/// unlike perturbed patterns the noise is the color itself
+use crate::color::Color;
+use crate::tuple::Tuple;
+use crate::matrix::Matrix4;
+use crate::pattern::Pattern;
+use crate::material::CmpPerlin;
+use std::fmt::{Formatter, Error};
+use std::any::Any;
+use noise::{Perlin, NoiseFn};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct NoisePattern {
+    pub color_a: Color, // Color where the noise is lowest
+    pub color_b: Color, // Color where the noise is highest
+    pub perlin: CmpPerlin,
+    /// Frequency of the noise, larger values give smaller features
+    pub scale: f64,
+    pub transform: Matrix4,
+}
+
+impl NoisePattern {
+    pub fn new(color_a: Color, color_b: Color, scale: f64) -> NoisePattern {
+        NoisePattern { color_a, color_b, perlin: CmpPerlin {perlin: Perlin::new()}, scale, transform: Matrix4::identity() }
+    }
+}
+
+impl Pattern for NoisePattern {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| self == a)
+    }
+
+    fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "Box {:?}", self)
+    }
+
+    fn pattern_clone(&self) -> Box<dyn Pattern + Send> {
+        Box::new(self.clone())
+    }
+
+    fn transform(&self) -> Matrix4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Matrix4) {
+        self.transform = transform;
+    }
+
+    fn pattern_at(&self, point: &Tuple) -> Color {
+        let noise = self.perlin.perlin.get([point.x.value() * self.scale, point.y.value() * self.scale, point.z.value() * self.scale]);
+
+        // Noise is in [-1, 1]
+        Color::lerp(self.color_a, self.color_b, ((noise + 1.0) / 2.0).clamp(0.0, 1.0))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tuple::point;
+
+    #[test]
+    fn noise_pattern() {
+        let pattern = NoisePattern::new(Color::black(), Color::white(), 2.0);
+
+        // Perlin noise is zero on the integer lattice
+        assert_eq!(pattern.pattern_at(&point(0.0, 0.0, 0.0)), Color::new(0.5, 0.5, 0.5));
+        assert_eq!(pattern.pattern_at(&point(1.5, -0.5, 2.0)), Color::new(0.5, 0.5, 0.5));
+
+        // The color varies smoothly between both colors
+        let a = pattern.pattern_at(&point(0.3, 0.7, 0.1));
+        let b = pattern.pattern_at(&point(0.31, 0.7, 0.1));
+        assert!(a.red.value() >= 0.0 && a.red.value() <= 1.0);
+        assert_ne!(a, Color::new(0.5, 0.5, 0.5));
+        assert!((a.red.value() - b.red.value()).abs() < 0.1);
+        assert_eq!(a.red, a.green);
+    }
+}
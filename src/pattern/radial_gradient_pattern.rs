@@ -0,0 +1,71 @@
+/// # Radial Gradient Patterns
+/// `radial_gradient_pattern` is a module to represent gradients spreading out from the y axis
+use crate::color::Color;
+use crate::tuple::Tuple;
+use crate::matrix::Matrix4;
+use crate::pattern::Pattern;
+use std::fmt::{Formatter, Error};
+use std::any::Any;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct RadialGradientPattern {
+    pub a: Color, // First color used in the pattern
+    pub b: Color, // Second color used in the pattern, reached 1 unit from the y axis
+    pub transform: Matrix4,
+}
+
+impl RadialGradientPattern {
+    pub fn new(color_a: Color, color_b: Color) -> RadialGradientPattern {
+        RadialGradientPattern { a: color_a, b: color_b, transform: Matrix4::identity() }
+    }
+}
+
+impl Pattern for RadialGradientPattern {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| self == a)
+    }
+
+    fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "Box {:?}", self)
+    }
+
+    fn pattern_clone(&self) -> Box<dyn Pattern + Send> {
+        Box::new(*self)
+    }
+
+    fn transform(&self) -> Matrix4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Matrix4) {
+        self.transform = transform;
+    }
+
+    fn pattern_at(&self, point: &Tuple) -> Color {
+        // Interpolate color by the distance from the y axis, staying b past 1
+        let r = (point.x * point.x + point.z * point.z).value().sqrt();
+        Color::lerp(self.a, self.b, r.clamp(0.0, 1.0))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tuple::point;
+
+    #[test]
+    fn radial_gradient_pattern() {
+        let pattern = RadialGradientPattern::new(Color::white(), Color::black());
+        assert_eq!(pattern.pattern_at(&point(0.0, 0.0, 0.0)), Color::white());
+        assert_eq!(pattern.pattern_at(&point(1.0, 0.0, 0.0)), Color::black());
+        assert_eq!(pattern.pattern_at(&point(0.0, 0.0, -0.25)), Color::new(0.75, 0.75, 0.75));
+        assert_eq!(pattern.pattern_at(&point(0.3, 5.0, 0.4)), Color::new(0.5, 0.5, 0.5));
+        // Past 1 unit the pattern stays the second color
+        assert_eq!(pattern.pattern_at(&point(2.0, 0.0, 2.0)), Color::black());
+    }
+}
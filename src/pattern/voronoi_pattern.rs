@@ -0,0 +1,156 @@
+/// # Voronoi Patterns
+/// `voronoi_pattern` is a module to represent cellular noise, with colors following
+/// the edges between randomly placed feature points
+use crate::color::Color;
+use crate::tuple::Tuple;
+use crate::matrix::Matrix4;
+use crate::pattern::Pattern;
+use std::fmt::{Formatter, Error};
+use std::any::Any;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct VoronoiPattern {
+    pub color_a: Color, // Color on the edges between cells
+    pub color_b: Color, // Color towards the feature point of each cell
+    /// Frequency of the cells, larger values give smaller cells
+    pub scale: f64,
+    /// Picks the feature point placed in every cell
+    pub seed: u64,
+    pub transform: Matrix4,
+}
+
+impl VoronoiPattern {
+    pub fn new(color_a: Color, color_b: Color, scale: f64, seed: u64) -> VoronoiPattern {
+        VoronoiPattern { color_a, color_b, scale, seed, transform: Matrix4::identity() }
+    }
+
+    /// Returns the feature point of a cell, always the same for the same cell and seed
+    fn feature_point(&self, cell: [i64; 3]) -> [f64; 3] {
+        let mut hash = self.seed;
+        for c in cell.iter() {
+            hash = mix(hash ^ *c as u64);
+        }
+        let mut offset = [0.0; 3];
+        for o in offset.iter_mut() {
+            hash = mix(hash);
+            // Top 53 bits as a fraction in [0, 1)
+            *o = (hash >> 11) as f64 / (1u64 << 53) as f64;
+        }
+        [cell[0] as f64 + offset[0], cell[1] as f64 + offset[1], cell[2] as f64 + offset[2]]
+    }
+
+    /// Returns the distances to the nearest (F1) and second nearest (F2) feature points
+    fn nearest_distances(&self, p: [f64; 3]) -> (f64, f64) {
+        let cell = [p[0].floor() as i64, p[1].floor() as i64, p[2].floor() as i64];
+        let (mut f1, mut f2) = (f64::INFINITY, f64::INFINITY);
+
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let feature = self.feature_point([cell[0] + dx, cell[1] + dy, cell[2] + dz]);
+                    let distance = ((feature[0] - p[0]).powi(2) + (feature[1] - p[1]).powi(2) + (feature[2] - p[2]).powi(2)).sqrt();
+                    if distance < f1 {
+                        f2 = f1;
+                        f1 = distance;
+                    } else if distance < f2 {
+                        f2 = distance;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+}
+
+/// SplitMix64 finalizer, scrambles the bits of a value
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E3779B97F4A7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+    x ^ (x >> 31)
+}
+
+impl Pattern for VoronoiPattern {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| self == a)
+    }
+
+    fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "Box {:?}", self)
+    }
+
+    fn pattern_clone(&self) -> Box<dyn Pattern + Send> {
+        Box::new(*self)
+    }
+
+    fn transform(&self) -> Matrix4 {
+        self.transform
+    }
+
+    fn set_transform(&mut self, transform: Matrix4) {
+        self.transform = transform;
+    }
+
+    fn pattern_at(&self, point: &Tuple) -> Color {
+        let (f1, f2) = self.nearest_distances([point.x.value() * self.scale, point.y.value() * self.scale, point.z.value() * self.scale]);
+
+        // F2 - F1 is 0 on the edges between cells and rarely goes past 1
+        Color::lerp(self.color_a, self.color_b, (f2 - f1).clamp(0.0, 1.0))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tuple::point;
+    use crate::float::Float;
+
+    fn sample_points() -> Vec<Tuple> {
+        let mut points = vec![];
+        for i in -20..20 {
+            for j in -5..5 {
+                points.push(point(i as f64 * 0.37, j as f64 * 0.53, (i * j) as f64 * 0.11));
+            }
+        }
+        points
+    }
+
+    #[test]
+    fn voronoi_pattern_in_color_range() {
+        let color_a = Color::new(0.2, 0.9, 0.1);
+        let color_b = Color::new(0.8, 0.3, 0.1);
+        let pattern = VoronoiPattern::new(color_a, color_b, 2.0, 7);
+
+        let within = |c: Float, a: Float, b: Float| c.value() >= a.value().min(b.value()) && c.value() <= a.value().max(b.value());
+        for p in sample_points() {
+            let c = pattern.pattern_at(&p);
+            assert!(within(c.red, color_a.red, color_b.red));
+            assert!(within(c.green, color_a.green, color_b.green));
+            assert_eq!(c.blue, Float(0.1));
+        }
+    }
+
+    #[test]
+    fn voronoi_pattern_is_deterministic() {
+        let pattern = VoronoiPattern::new(Color::white(), Color::black(), 1.0, 42);
+        let same = VoronoiPattern::new(Color::white(), Color::black(), 1.0, 42);
+        let other = VoronoiPattern::new(Color::white(), Color::black(), 1.0, 43);
+
+        let points = sample_points();
+        assert!(points.iter().all(|p| pattern.pattern_at(p) == same.pattern_at(p)));
+        assert!(points.iter().any(|p| pattern.pattern_at(p) != other.pattern_at(p)));
+
+        // A cell's feature point lies inside the cell
+        let feature = pattern.feature_point([-3, 0, 5]);
+        assert!(feature[0] >= -3.0 && feature[0] < -2.0);
+        assert!(feature[1] >= 0.0 && feature[1] < 1.0);
+        assert!(feature[2] >= 5.0 && feature[2] < 6.0);
+        // Right on a feature point F1 is 0
+        assert_eq!(pattern.nearest_distances(feature).0, 0.0);
+    }
+}
@@ -1,8 +1,11 @@
 /// # ray
 /// `ray` is a module to represent a ray tracer's ray
 
-use super::tuple::Tuple;
+use super::tuple::{self, Tuple, point};
 use super::matrix::Matrix4;
+use crate::light::random_unit_vector;
+use crate::FLOAT_THRESHOLD;
+use rand::Rng;
 
 #[derive(Debug)]
 pub struct Ray {
@@ -20,10 +23,55 @@ impl Ray {
         Ray {origin, direction}
     }
 
+    /// Returns a ray from `origin` in a random direction above the surface with `normal`,
+    /// cosine weighted so directions near the normal are more likely, as diffuse surfaces scatter light
+    pub fn random_in_hemisphere(origin: Tuple, normal: Tuple, rng: &mut impl Rng) -> Ray {
+        let normal = normal.normalize();
+        // A point on the unit sphere resting on the surface gives a cosine weighted direction
+        let direction = normal + random_unit_vector(rng);
+        if direction.magnitude() < FLOAT_THRESHOLD {
+            return Ray::new(origin, normal);
+        }
+        Ray::new(origin, direction.normalize())
+    }
+
     pub fn position(&self, t: f64) -> Tuple {
         &self.origin + &self.direction * t
     }
 
+    /// Alias of `position`, the point t along the ray
+    pub fn at(&self, t: f64) -> Tuple {
+        self.position(t)
+    }
+
+    /// Returns whether the ray passes through a sphere in front of its origin,
+    /// a cheap test that doesn't create any intersections
+    pub fn intersects_sphere_simple(&self, center: Tuple, radius: f64) -> bool {
+        let center_to_origin = self.origin - center;
+        let a = tuple::dot(&self.direction, &self.direction);
+        let b = tuple::dot(&self.direction, &center_to_origin);
+        let c = tuple::dot(&center_to_origin, &center_to_origin) - radius * radius;
+
+        // Origin is inside the sphere
+        if c <= 0.0 {
+            return true;
+        }
+        // Otherwise the sphere has to be ahead of the ray and the discriminant positive
+        b < 0.0 && b * b - a * c >= 0.0
+    }
+
+    /// Returns the t where the ray meets the infinite plane of points p with
+    /// `dot(normal, p) = d`, None if the ray runs parallel to the plane
+    pub fn hits_plane(&self, normal: Tuple, d: f64) -> Option<f64> {
+        let denominator = tuple::dot(&normal, &self.direction);
+        // Also rejects the NaN normal of a degenerate triangle
+        if denominator.abs() < FLOAT_THRESHOLD || denominator.is_nan() {
+            return None;
+        }
+        let origin_distance = tuple::dot(&normal, &(self.origin - point(0.0, 0.0, 0.0)));
+        Some((d - origin_distance) / denominator)
+    }
+
     pub fn transform(&self, matrix: &Matrix4) -> Ray{
         Ray::new(matrix * self.origin, matrix * self.direction)
     }
@@ -51,6 +99,36 @@ mod tests {
         assert_eq!(r.position(1.0), point(3.0, 3.0, 4.0));
         assert_eq!(r.position(-1.0), point(1.0, 3.0, 4.0));
         assert_eq!(r.position(2.5), point(4.5, 3.0, 4.0));
+
+        assert_eq!(r.at(2.5), r.position(2.5));
+        assert_eq!(r.at(-1.0), point(1.0, 3.0, 4.0));
+    }
+
+    #[test]
+    fn ray_intersects_sphere_simple() {
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        assert!(r.intersects_sphere_simple(point(0.0, 0.0, 0.0), 1.0));
+        assert!(r.intersects_sphere_simple(point(0.0, 1.0, 0.0), 1.0)); // tangent
+        assert!(!r.intersects_sphere_simple(point(0.0, 1.5, 0.0), 1.0));
+        assert!(!r.intersects_sphere_simple(point(0.0, 0.0, -10.0), 1.0)); // behind
+        assert!(r.intersects_sphere_simple(point(1.0, 0.0, -4.0), 2.0)); // inside
+    }
+
+    #[test]
+    fn ray_random_in_hemisphere() {
+        let mut rng = rand::thread_rng();
+        let normal = vector(0.0, 2.0, 0.0);
+        let mut cos_sum = 0.0;
+        for _ in 0..1000 {
+            let r = Ray::random_in_hemisphere(point(1.0, 2.0, 3.0), normal, &mut rng);
+            assert_eq!(r.origin, point(1.0, 2.0, 3.0));
+            assert!((r.direction.magnitude() - 1.0).abs() < FLOAT_THRESHOLD);
+            assert!(r.direction.y.value() >= 0.0);
+            cos_sum += r.direction.y.value();
+        }
+        // Cosine weighted samples average a cosine of 2/3, uniform ones 1/2
+        let mean = cos_sum / 1000.0;
+        assert!(mean > 0.6 && mean < 0.73, "mean cosine {}", mean);
     }
 
     #[test]
@@ -69,4 +147,22 @@ mod tests {
         assert_eq!(r2.origin, point(2.0, 6.0, 12.0));
         assert_eq!(r2.direction, vector(0.0, 3.0, 0.0));
     }
+
+    #[test]
+    fn ray_hits_plane() {
+        let up = vector(0.0, 1.0, 0.0);
+        let r = Ray::new(point(0.0, 1.0, 0.0), vector(0.0, -1.0, 0.0));
+        assert_eq!(r.hits_plane(up, 0.0), Some(1.0));
+        assert_eq!(r.hits_plane(up, -2.0), Some(3.0));
+        // Planes behind the ray give a negative t
+        assert_eq!(r.hits_plane(up, 3.0), Some(-2.0));
+
+        let r = Ray::new(point(1.0, 0.0, 0.0), vector(1.0, 1.0, 0.0));
+        assert_eq!(r.hits_plane(vector(1.0, 0.0, 0.0), 2.0), Some(1.0));
+
+        // Parallel and coplanar rays
+        let r = Ray::new(point(0.0, 1.0, 0.0), vector(0.0, 0.0, 1.0));
+        assert_eq!(r.hits_plane(up, 0.0), None);
+        assert_eq!(r.hits_plane(up, 1.0), None);
+    }
 }
@@ -0,0 +1,725 @@
+/// # scene
+/// `scene` is a module to load and save whole scenes, the camera, lights and objects, as YAML files
+///
+/// ```yaml
+/// camera:
+///   width: 400
+///   height: 200
+///   field_of_view: 1.0472
+///   transform:
+///     - {type: view_transform, from: [0, 1.5, -5], to: [0, 1, 0], up: [0, 1, 0]}
+/// lights:
+///   - {type: point, position: [-10, 10, -10], intensity: [1, 1, 1]}
+/// objects:
+///   - type: plane
+///     material:
+///       pattern: {type: checker, a: [1, 1, 1], b: [0, 0, 0]}
+///   - type: sphere
+///     transform:
+///       - {type: translation, x: 0, y: 1, z: 0}
+///     material: {color: [0.1, 1, 0.5], diffuse: 0.7, specular: 0.3}
+/// ```
+///
+/// A list of transforms is multiplied together as written, like `TransformBuilder`,
+/// so the last one is applied to the object first
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::float::Float;
+use crate::light::Light;
+use crate::material::{Material, CmpPerlin};
+use crate::matrix::Matrix4;
+use crate::pattern::Pattern;
+use crate::pattern::stripe_pattern::StripePattern;
+use crate::pattern::gradient_pattern::GradientPattern;
+use crate::pattern::radial_gradient_pattern::RadialGradientPattern;
+use crate::pattern::ring_pattern::RingPattern;
+use crate::pattern::checker_pattern::CheckerPattern;
+use crate::pattern::blended_pattern::BlendedPattern;
+use crate::pattern::perturbed_pattern::PerturbedPattern;
+use crate::pattern::noise_pattern::NoisePattern;
+use crate::pattern::layered_pattern::{LayeredPattern, BlendMode};
+use crate::pattern::voronoi_pattern::VoronoiPattern;
+use crate::pattern::image_pattern::ImagePattern;
+use crate::shape::Shape;
+use crate::shape::shape_list::ShapeList;
+use crate::shape::sphere::Sphere;
+use crate::shape::plane::Plane;
+use crate::shape::cube::Cube;
+use crate::shape::cylinder::Cylinder;
+use crate::shape::cone::Cone;
+use crate::shape::paraboloid::Paraboloid;
+use crate::shape::torus::Torus;
+use crate::shape::disk::Disk;
+use crate::shape::triangle::Triangle;
+use crate::shape::smooth_triangle::SmoothTriangle;
+use crate::shape::group::Group;
+use crate::shape::csg::CSG;
+use crate::transformation::{self, TransformBuilder};
+use crate::tuple::{Tuple, point, vector};
+use crate::world::World;
+use noise::Perlin;
+use serde::{Serialize, Deserialize};
+use std::fs::File;
+use std::io::{self, Read, Error, ErrorKind};
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn rgb(color: Color) -> [f64; 3] {
+    [color.red.value(), color.green.value(), color.blue.value()]
+}
+
+fn color(c: [f64; 3]) -> Color {
+    Color::new(c[0], c[1], c[2])
+}
+
+fn xyz(t: Tuple) -> [f64; 3] {
+    [t.x.value(), t.y.value(), t.z.value()]
+}
+
+/// A single transformation, written the same way as `transformation::to_yaml`
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TransformDef {
+    Identity,
+    Translation { x: f64, y: f64, z: f64 },
+    Scaling { x: f64, y: f64, z: f64 },
+    RotationX { angle: f64 },
+    RotationY { angle: f64 },
+    RotationZ { angle: f64 },
+    Shearing { x_y: f64, x_z: f64, y_x: f64, y_z: f64, z_x: f64, z_y: f64 },
+    ViewTransform { from: [f64; 3], to: [f64; 3], up: [f64; 3] },
+    /// Rows of the matrix one after another
+    Matrix { values: [f64; 16] },
+}
+
+impl TransformDef {
+    fn matrix(&self) -> Matrix4 {
+        match *self {
+            TransformDef::Identity => Matrix4::identity(),
+            TransformDef::Translation { x, y, z } => transformation::translation(x, y, z),
+            TransformDef::Scaling { x, y, z } => transformation::scaling(x, y, z),
+            TransformDef::RotationX { angle } => transformation::rotation_x(angle),
+            TransformDef::RotationY { angle } => transformation::rotation_y(angle),
+            TransformDef::RotationZ { angle } => transformation::rotation_z(angle),
+            TransformDef::Shearing { x_y, x_z, y_x, y_z, z_x, z_y } => transformation::shearing(x_y, x_z, y_x, y_z, z_x, z_y),
+            TransformDef::ViewTransform { from, to, up } => transformation::view_transform(
+                point(from[0], from[1], from[2]), point(to[0], to[1], to[2]), vector(up[0], up[1], up[2])),
+            TransformDef::Matrix { values } => {
+                let mut rows = [[0.0; 4]; 4];
+                for (i, value) in values.iter().enumerate() {
+                    rows[i / 4][i % 4] = *value;
+                }
+                Matrix4::new(rows)
+            },
+        }
+    }
+}
+
+/// Returns the product of the transforms as written
+fn to_matrix(transform: &[TransformDef]) -> Matrix4 {
+    transform.iter().fold(TransformBuilder::new(), |builder, t| builder.then(t.matrix())).build()
+}
+
+/// Returns the matrix as a single transform, or no transforms for the identity
+fn to_transform_defs(m: Matrix4) -> Vec<TransformDef> {
+    if m == Matrix4::identity() {
+        return vec![];
+    }
+    let def = serde_yaml::from_str(&transformation::to_yaml(m)).expect("Unable to read back a written transform");
+    vec![def]
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+struct CameraDef {
+    width: i32,
+    height: i32,
+    field_of_view: f64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    transform: Vec<TransformDef>,
+    /// Turns on depth of field when larger than 0.0
+    #[serde(default, skip_serializing_if = "is_zero")]
+    aperture: f64,
+    #[serde(default = "default_focus_distance")]
+    focus_distance: f64,
+}
+
+fn is_zero(x: &f64) -> bool {
+    *x == 0.0
+}
+
+fn default_focus_distance() -> f64 {
+    1.0
+}
+
+impl CameraDef {
+    fn from_camera(camera: &Camera) -> CameraDef {
+        CameraDef {
+            width: camera.h_size,
+            height: camera.v_size,
+            field_of_view: camera.field_of_view.value(),
+            transform: to_transform_defs(camera.transform),
+            aperture: camera.aperture,
+            focus_distance: camera.focus_distance,
+        }
+    }
+
+    fn build(&self) -> Camera {
+        let mut camera = if self.aperture > 0.0 {
+            Camera::new_with_dof(self.width, self.height, self.field_of_view, self.aperture, self.focus_distance)
+        } else {
+            Camera::new(self.width, self.height, self.field_of_view)
+        };
+        camera.transform = to_matrix(&self.transform);
+        camera
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+struct LayerDef {
+    pattern: PatternDef,
+    mode: BlendMode,
+    opacity: f64,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum PatternDef {
+    Stripe {
+        a: [f64; 3], b: [f64; 3],
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        transform: Vec<TransformDef>,
+    },
+    Gradient {
+        a: [f64; 3], b: [f64; 3],
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        transform: Vec<TransformDef>,
+    },
+    RadialGradient {
+        a: [f64; 3], b: [f64; 3],
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        transform: Vec<TransformDef>,
+    },
+    Ring {
+        a: [f64; 3], b: [f64; 3],
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        transform: Vec<TransformDef>,
+    },
+    Checker {
+        a: [f64; 3], b: [f64; 3],
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        transform: Vec<TransformDef>,
+    },
+    Blended {
+        a: Box<PatternDef>, b: Box<PatternDef>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        transform: Vec<TransformDef>,
+    },
+    Perturbed {
+        pattern: Box<PatternDef>,
+        perlin_factor: f64,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        transform: Vec<TransformDef>,
+    },
+    Noise {
+        a: [f64; 3], b: [f64; 3],
+        scale: f64,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        transform: Vec<TransformDef>,
+    },
+    Voronoi {
+        a: [f64; 3], b: [f64; 3],
+        scale: f64,
+        seed: u64,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        transform: Vec<TransformDef>,
+    },
+    /// Bottom layer first
+    Layered {
+        layers: Vec<LayerDef>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        transform: Vec<TransformDef>,
+    },
+    /// A PPM file wrapped around the shape
+    Image {
+        path: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        transform: Vec<TransformDef>,
+    },
+}
+
+impl PatternDef {
+    fn from_pattern(pattern: &dyn Pattern) -> io::Result<PatternDef> {
+        let any = pattern.as_any();
+        let transform = to_transform_defs(pattern.transform());
+        let nested = |p: &Option<Box<dyn Pattern + Send>>| match p {
+            Some(p) => PatternDef::from_pattern(&**p).map(Box::new),
+            None => Err(invalid(format!("{} is missing a pattern", pattern.pattern_name()))),
+        };
+
+        if let Some(p) = any.downcast_ref::<StripePattern>() {
+            Ok(PatternDef::Stripe { a: rgb(p.a), b: rgb(p.b), transform })
+        } else if let Some(p) = any.downcast_ref::<GradientPattern>() {
+            Ok(PatternDef::Gradient { a: rgb(p.a), b: rgb(p.b), transform })
+        } else if let Some(p) = any.downcast_ref::<RadialGradientPattern>() {
+            Ok(PatternDef::RadialGradient { a: rgb(p.a), b: rgb(p.b), transform })
+        } else if let Some(p) = any.downcast_ref::<RingPattern>() {
+            Ok(PatternDef::Ring { a: rgb(p.a), b: rgb(p.b), transform })
+        } else if let Some(p) = any.downcast_ref::<CheckerPattern>() {
+            Ok(PatternDef::Checker { a: rgb(p.a), b: rgb(p.b), transform })
+        } else if let Some(p) = any.downcast_ref::<BlendedPattern>() {
+            Ok(PatternDef::Blended { a: nested(&p.a)?, b: nested(&p.b)?, transform })
+        } else if let Some(p) = any.downcast_ref::<PerturbedPattern>() {
+            Ok(PatternDef::Perturbed { pattern: nested(&p.pattern)?, perlin_factor: p.perlin_factor, transform })
+        } else if let Some(p) = any.downcast_ref::<NoisePattern>() {
+            Ok(PatternDef::Noise { a: rgb(p.color_a), b: rgb(p.color_b), scale: p.scale, transform })
+        } else if let Some(p) = any.downcast_ref::<VoronoiPattern>() {
+            Ok(PatternDef::Voronoi { a: rgb(p.color_a), b: rgb(p.color_b), scale: p.scale, seed: p.seed, transform })
+        } else if let Some(p) = any.downcast_ref::<LayeredPattern>() {
+            let layers = p.layers.iter()
+                .map(|(pattern, mode, opacity)| Ok(LayerDef { pattern: PatternDef::from_pattern(&**pattern)?, mode: *mode, opacity: *opacity }))
+                .collect::<io::Result<Vec<LayerDef>>>()?;
+            Ok(PatternDef::Layered { layers, transform })
+        } else {
+            // Image patterns don't remember the file they were loaded from
+            Err(invalid(format!("{} can't be written to a scene file", pattern.pattern_name())))
+        }
+    }
+
+    fn build(&self) -> io::Result<Box<dyn Pattern + Send>> {
+        let (mut pattern, transform): (Box<dyn Pattern + Send>, _) = match self {
+            PatternDef::Stripe { a, b, transform } => (Box::new(StripePattern::new(color(*a), color(*b))), transform),
+            PatternDef::Gradient { a, b, transform } => (Box::new(GradientPattern::new(color(*a), color(*b))), transform),
+            PatternDef::RadialGradient { a, b, transform } => (Box::new(RadialGradientPattern::new(color(*a), color(*b))), transform),
+            PatternDef::Ring { a, b, transform } => (Box::new(RingPattern::new(color(*a), color(*b))), transform),
+            PatternDef::Checker { a, b, transform } => (Box::new(CheckerPattern::new(color(*a), color(*b))), transform),
+            PatternDef::Blended { a, b, transform } => (Box::new(BlendedPattern::new(a.build()?, b.build()?)), transform),
+            PatternDef::Perturbed { pattern, perlin_factor, transform } => (Box::new(PerturbedPattern::new(pattern.build()?, *perlin_factor)), transform),
+            PatternDef::Noise { a, b, scale, transform } => (Box::new(NoisePattern::new(color(*a), color(*b), *scale)), transform),
+            PatternDef::Voronoi { a, b, scale, seed, transform } => (Box::new(VoronoiPattern::new(color(*a), color(*b), *scale, *seed)), transform),
+            PatternDef::Layered { layers, transform } => {
+                let mut layered = LayeredPattern::new();
+                for layer in layers.iter() {
+                    layered = layered.with_layer(layer.pattern.build()?, layer.mode, layer.opacity);
+                }
+                (Box::new(layered), transform)
+            },
+            PatternDef::Image { path, transform } => (Box::new(ImagePattern::from_ppm(path)?), transform),
+        };
+        pattern.set_transform(to_matrix(transform));
+        Ok(pattern)
+    }
+}
+
+/// Every field is optional and defaults to `Material::new()`
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct MaterialDef {
+    color: [f64; 3],
+    ambient: f64,
+    diffuse: f64,
+    specular: f64,
+    shininess: f64,
+    reflective: f64,
+    transparency: f64,
+    refractive_index: f64,
+    emission: f64,
+    emission_color: [f64; 3],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pattern: Option<PatternDef>,
+    /// "sin_y" or "perlin"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    normal_perturb: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    normal_perturb_factor: Option<f64>,
+}
+
+impl Default for MaterialDef {
+    fn default() -> MaterialDef {
+        MaterialDef::new(&Material::new(), None)
+    }
+}
+
+impl MaterialDef {
+    fn new(material: &Material, pattern: Option<PatternDef>) -> MaterialDef {
+        MaterialDef {
+            color: rgb(material.color),
+            ambient: material.ambient.value(),
+            diffuse: material.diffuse.value(),
+            specular: material.specular.value(),
+            shininess: material.shininess.value(),
+            reflective: material.reflective.value(),
+            transparency: material.transparency.value(),
+            refractive_index: material.refractive_index.value(),
+            emission: material.emission.value(),
+            emission_color: rgb(material.emission_color),
+            pattern,
+            normal_perturb: material.normal_perturb.clone(),
+            normal_perturb_factor: material.normal_perturb_factor,
+        }
+    }
+
+    fn from_material(material: &Material) -> io::Result<MaterialDef> {
+        let pattern = match &material.pattern {
+            Some(pattern) => Some(PatternDef::from_pattern(&**pattern)?),
+            None => None,
+        };
+        Ok(MaterialDef::new(material, pattern))
+    }
+
+    fn build(&self) -> io::Result<Material> {
+        let mut material = Material::new();
+        material.color = color(self.color);
+        material.ambient = Float(self.ambient);
+        material.diffuse = Float(self.diffuse);
+        material.specular = Float(self.specular);
+        material.shininess = Float(self.shininess);
+        material.reflective = Float(self.reflective);
+        material.transparency = Float(self.transparency);
+        material.refractive_index = Float(self.refractive_index);
+        material.emission = Float(self.emission);
+        material.emission_color = color(self.emission_color);
+        if let Some(pattern) = &self.pattern {
+            material.set_pattern(pattern.build()?);
+        }
+
+        match self.normal_perturb.as_deref() {
+            None => {},
+            Some("sin_y") | Some("perlin") if self.normal_perturb_factor.is_none() =>
+                return Err(invalid(String::from("normal_perturb needs a normal_perturb_factor"))),
+            Some("sin_y") => {},
+            Some("perlin") => material.normal_perturb_perlin = Some(CmpPerlin {perlin: Perlin::new()}),
+            Some(other) => return Err(invalid(format!("Unknown normal_perturb: {}", other))),
+        }
+        material.normal_perturb = self.normal_perturb.clone();
+        material.normal_perturb_factor = self.normal_perturb_factor;
+        Ok(material)
+    }
+}
+
+fn neg_infinity() -> f64 {
+    f64::NEG_INFINITY
+}
+
+fn infinity() -> f64 {
+    f64::INFINITY
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ShapeDef {
+    Sphere,
+    Plane,
+    Cube,
+    Disk,
+    Cylinder {
+        #[serde(default = "neg_infinity")]
+        minimum: f64,
+        #[serde(default = "infinity")]
+        maximum: f64,
+        #[serde(default)]
+        closed: bool,
+    },
+    Cone {
+        #[serde(default = "neg_infinity")]
+        minimum: f64,
+        #[serde(default = "infinity")]
+        maximum: f64,
+        #[serde(default)]
+        closed: bool,
+    },
+    Paraboloid {
+        #[serde(default = "neg_infinity")]
+        minimum: f64,
+        #[serde(default = "infinity")]
+        maximum: f64,
+        #[serde(default)]
+        closed: bool,
+    },
+    Torus { major_radius: f64, minor_radius: f64 },
+    Triangle { p1: [f64; 3], p2: [f64; 3], p3: [f64; 3] },
+    SmoothTriangle { p1: [f64; 3], p2: [f64; 3], p3: [f64; 3], n1: [f64; 3], n2: [f64; 3], n3: [f64; 3] },
+    Group { children: Vec<ObjectDef> },
+    /// `operation` is "union", "intersection" or "difference"
+    Csg { operation: String, left: Box<ObjectDef>, right: Box<ObjectDef> },
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+struct ObjectDef {
+    #[serde(flatten)]
+    shape: ShapeDef,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    transform: Vec<TransformDef>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    material: Option<MaterialDef>,
+}
+
+impl ObjectDef {
+    fn from_shape(shape: &dyn Shape, shape_list: &ShapeList) -> io::Result<ObjectDef> {
+        let any = shape.as_any();
+        let child = |id: Option<i32>| match id {
+            Some(id) => ObjectDef::from_shape(&*shape_list.get(id), shape_list).map(Box::new),
+            None => Err(invalid(format!("{} is missing a child", shape.shape_name()))),
+        };
+
+        let kind = if any.is::<Sphere>() {
+            ShapeDef::Sphere
+        } else if any.is::<Plane>() {
+            ShapeDef::Plane
+        } else if any.is::<Cube>() {
+            ShapeDef::Cube
+        } else if any.is::<Disk>() {
+            ShapeDef::Disk
+        } else if let Some(s) = any.downcast_ref::<Cylinder>() {
+            ShapeDef::Cylinder { minimum: s.minimum, maximum: s.maximum, closed: s.closed }
+        } else if let Some(s) = any.downcast_ref::<Cone>() {
+            ShapeDef::Cone { minimum: s.minimum, maximum: s.maximum, closed: s.closed }
+        } else if let Some(s) = any.downcast_ref::<Paraboloid>() {
+            ShapeDef::Paraboloid { minimum: s.minimum, maximum: s.maximum, closed: s.closed }
+        } else if let Some(s) = any.downcast_ref::<Torus>() {
+            ShapeDef::Torus { major_radius: s.major_radius, minor_radius: s.minor_radius }
+        } else if let Some(s) = any.downcast_ref::<Triangle>() {
+            ShapeDef::Triangle { p1: xyz(s.p1), p2: xyz(s.p2), p3: xyz(s.p3) }
+        } else if let Some(s) = any.downcast_ref::<SmoothTriangle>() {
+            ShapeDef::SmoothTriangle { p1: xyz(s.p1), p2: xyz(s.p2), p3: xyz(s.p3), n1: xyz(s.n1), n2: xyz(s.n2), n3: xyz(s.n3) }
+        } else if let Some(s) = any.downcast_ref::<Group>() {
+            let children = s.children_ids.iter()
+                .map(|id| ObjectDef::from_shape(&*shape_list.get(*id), shape_list))
+                .collect::<io::Result<Vec<ObjectDef>>>()?;
+            ShapeDef::Group { children }
+        } else if let Some(s) = any.downcast_ref::<CSG>() {
+            let operation = s.operation.clone().ok_or_else(|| invalid(format!("{} is missing an operation", shape.shape_name())))?;
+            ShapeDef::Csg { operation, left: child(s.left_id)?, right: child(s.right_id)? }
+        } else {
+            return Err(invalid(format!("{} can't be written to a scene file", shape.shape_name())));
+        };
+
+        let material = shape.material();
+        Ok(ObjectDef {
+            shape: kind,
+            transform: to_transform_defs(shape.transform()),
+            material: if material == Material::new() { None } else { Some(MaterialDef::from_material(&material)?) },
+        })
+    }
+
+    /// Adds the shape and any of its children to the shape list
+    fn build(&self, shape_list: &mut ShapeList) -> io::Result<Box<dyn Shape + Send>> {
+        let p = |c: &[f64; 3]| point(c[0], c[1], c[2]);
+        let v = |c: &[f64; 3]| vector(c[0], c[1], c[2]);
+
+        let mut shape: Box<dyn Shape + Send> = match &self.shape {
+            ShapeDef::Sphere => Box::new(Sphere::new(shape_list)),
+            ShapeDef::Plane => Box::new(Plane::new(shape_list)),
+            ShapeDef::Cube => Box::new(Cube::new(shape_list)),
+            ShapeDef::Disk => Box::new(Disk::new(shape_list)),
+            ShapeDef::Cylinder { minimum, maximum, closed: false } => Box::new(Cylinder::new_open(*minimum, *maximum, shape_list)),
+            ShapeDef::Cylinder { minimum, maximum, closed: true } => Box::new(Cylinder::new_closed(*minimum, *maximum, shape_list)),
+            ShapeDef::Cone { minimum, maximum, closed } => {
+                let mut cone = Cone::new_bounded(*minimum, *maximum, shape_list);
+                cone.closed = *closed;
+                shape_list.update(Box::new(cone.clone()));
+                Box::new(cone)
+            },
+            ShapeDef::Paraboloid { minimum, maximum, closed: false } => Box::new(Paraboloid::new_bounded(*minimum, *maximum, shape_list)),
+            ShapeDef::Paraboloid { minimum, maximum, closed: true } => Box::new(Paraboloid::new_closed(*minimum, *maximum, shape_list)),
+            ShapeDef::Torus { major_radius, minor_radius } => Box::new(Torus::new_with_radii(*major_radius, *minor_radius, shape_list)),
+            ShapeDef::Triangle { p1, p2, p3 } => Box::new(Triangle::new(p(p1), p(p2), p(p3), shape_list)),
+            ShapeDef::SmoothTriangle { p1, p2, p3, n1, n2, n3 } =>
+                Box::new(SmoothTriangle::new(p(p1), p(p2), p(p3), v(n1), v(n2), v(n3), shape_list)),
+            ShapeDef::Group { children } => {
+                let mut children = children.iter()
+                    .map(|child| child.build(shape_list))
+                    .collect::<io::Result<Vec<Box<dyn Shape + Send>>>>()?;
+                let mut group = Group::new(shape_list);
+                group.add_children(&mut children, shape_list);
+                Box::new(group)
+            },
+            ShapeDef::Csg { operation, left, right } => {
+                if !["union", "intersection", "difference"].contains(&operation.as_str()) {
+                    return Err(invalid(format!("Unknown csg operation: {}", operation)));
+                }
+                let left = left.build(shape_list)?;
+                let right = right.build(shape_list)?;
+                Box::new(CSG::new_with_operation(operation, left.id(), right.id(), shape_list))
+            },
+        };
+
+        shape.set_transform(to_matrix(&self.transform), shape_list);
+        if let Some(material) = &self.material {
+            shape.set_material(material.build()?, shape_list);
+        }
+        Ok(shape)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+struct SceneDef {
+    camera: CameraDef,
+    #[serde(default)]
+    lights: Vec<Light>,
+    #[serde(default)]
+    objects: Vec<ObjectDef>,
+}
+
+/// Builds worlds from scene files and writes worlds made in code back out as scene files
+#[derive(Debug, PartialEq, Clone)]
+pub struct WorldBuilder {
+    scene: SceneDef,
+}
+
+impl WorldBuilder {
+    pub fn from_yaml(yaml: &str) -> io::Result<WorldBuilder> {
+        let scene = serde_yaml::from_str(yaml).map_err(|e| invalid(e.to_string()))?;
+        Ok(WorldBuilder { scene })
+    }
+
+    /// Reads a scene file and builds everything needed to render it
+    pub fn from_file(path: &str) -> io::Result<(World, ShapeList, Camera)> {
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+        WorldBuilder::from_yaml(&contents)?.build()
+    }
+
+    /// Captures the world's objects and lights along with the camera,
+    /// fails on shapes or patterns that can't be written to a scene file
+    pub fn from_world(world: &World, shape_list: &ShapeList, camera: &Camera) -> io::Result<WorldBuilder> {
+        let objects = world.objects.iter()
+            // The shape list holds the latest copy of each object
+            .map(|object| ObjectDef::from_shape(&*shape_list.get(object.id()), shape_list))
+            .collect::<io::Result<Vec<ObjectDef>>>()?;
+        let scene = SceneDef { camera: CameraDef::from_camera(camera), lights: world.lights.clone(), objects };
+        Ok(WorldBuilder { scene })
+    }
+
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(&self.scene).expect("Unable to serialize scene")
+    }
+
+    pub fn build(&self) -> io::Result<(World, ShapeList, Camera)> {
+        let mut shape_list = ShapeList::new();
+        let mut world = World::new();
+        world.lights = self.scene.lights.clone();
+        for object in self.scene.objects.iter() {
+            let shape = object.build(&mut shape_list)?;
+            world.objects.push(shape_list.get(shape.id()));
+        }
+        Ok((world, shape_list, self.scene.camera.build()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::transformation::{translation, scaling, rotation_y};
+    use std::f64::consts::PI;
+
+    #[test]
+    fn scene_round_trip() {
+        let mut shape_list = ShapeList::new();
+        let world = World::default_world(&mut shape_list);
+        let mut camera = Camera::new(100, 50, PI / 3.0);
+        camera.transform = translation(0.0, 0.0, -5.0);
+
+        let yaml = WorldBuilder::from_world(&world, &shape_list, &camera).unwrap().to_yaml();
+        let (loaded, loaded_list, loaded_camera) = WorldBuilder::from_yaml(&yaml).unwrap().build().unwrap();
+
+        assert_eq!(loaded.objects.len(), world.objects.len());
+        assert_eq!(loaded.lights[0].position, point(-10.0, 10.0, -10.0));
+        assert_eq!(loaded.lights, world.lights);
+        for (object, loaded_object) in world.objects.iter().zip(loaded.objects.iter()) {
+            let object = shape_list.get(object.id());
+            assert_eq!(loaded_object.transform(), object.transform());
+            assert_eq!(loaded_object.material(), object.material());
+        }
+        assert_eq!(loaded_camera.transform, camera.transform);
+        assert_eq!((loaded_camera.h_size, loaded_camera.v_size), (100, 50));
+
+        // Writing the loaded world gives back the same scene
+        let again = WorldBuilder::from_world(&loaded, &loaded_list, &loaded_camera).unwrap();
+        assert_eq!(again.to_yaml(), yaml);
+    }
+
+    #[test]
+    fn scene_round_trip_nested_shapes_and_patterns() {
+        let mut shape_list = ShapeList::new();
+        let mut world = World::new();
+        world.lights.push(Light::area_light(&point(0.0, 5.0, 0.0), &Color::new(0.5, 0.5, 0.5), 2.0));
+
+        let mut cylinder: Box<dyn Shape + Send> = Box::new(Cylinder::new_closed(-1.0, 1.0, &mut shape_list));
+        cylinder.set_transform(rotation_y(PI / 4.0), &mut shape_list);
+        let mut material = Material::new();
+        material.set_pattern(Box::new(BlendedPattern::new(
+            Box::new(StripePattern::new(Color::white(), Color::black())),
+            Box::new(VoronoiPattern::new(Color::white(), Color::black(), 2.0, 7)))));
+        let mut sphere: Box<dyn Shape + Send> = Box::new(Sphere::new_with_material(material.clone(), &mut shape_list));
+        sphere.set_transform(scaling(1.0, 2.0, 1.0), &mut shape_list);
+        let csg = CSG::new_with_operation("difference", cylinder.id(), sphere.id(), &mut shape_list);
+
+        let mut group = Group::new(&mut shape_list);
+        let mut children: Vec<Box<dyn Shape + Send>> = vec![Box::new(csg), Box::new(Torus::new(&mut shape_list))];
+        group.add_children(&mut children, &mut shape_list);
+        world.objects.push(Box::new(group));
+
+        let yaml = WorldBuilder::from_world(&world, &shape_list, &Camera::new(10, 10, 1.0)).unwrap().to_yaml();
+        let (loaded, loaded_list, _) = WorldBuilder::from_yaml(&yaml).unwrap().build().unwrap();
+
+        assert_eq!(loaded.lights, world.lights);
+        let group = loaded_list.get(loaded.objects[0].id());
+        let group = group.as_any().downcast_ref::<Group>().unwrap();
+        assert_eq!(group.children_ids.len(), 2);
+        let csg = loaded_list.get(group.children_ids[0]);
+        let csg = csg.as_any().downcast_ref::<CSG>().unwrap();
+        assert_eq!(csg.operation, Some(String::from("difference")));
+        assert_eq!(loaded_list.get(csg.left_id.unwrap()).transform(), rotation_y(PI / 4.0));
+        let sphere = loaded_list.get(csg.right_id.unwrap());
+        assert_eq!(sphere.material(), material);
+        assert_eq!(loaded_list.get(group.children_ids[1]).shape_type(), "torus");
+    }
+
+    #[test]
+    fn scene_from_yaml() {
+        let yaml = "
+camera:
+  width: 20
+  height: 10
+  field_of_view: 1.0
+  transform:
+    - {type: view_transform, from: [0, 1.5, -5], to: [0, 1, 0], up: [0, 1, 0]}
+lights:
+  - {type: point, position: [-10, 10, -10], intensity: [1, 1, 1]}
+objects:
+  - type: plane
+    material:
+      pattern: {type: checker, a: [1, 1, 1], b: [0, 0, 0], transform: [{type: scaling, x: 2, y: 2, z: 2}]}
+  - type: cone
+    maximum: 0
+    closed: true
+    transform:
+      - {type: translation, x: 0, y: 1, z: 0}
+      - {type: scaling, x: 2, y: 2, z: 2}
+    material: {color: [0.1, 1, 0.5], diffuse: 0.7, normal_perturb: sin_y, normal_perturb_factor: 2}
+";
+        let (world, shape_list, camera) = WorldBuilder::from_yaml(yaml).unwrap().build().unwrap();
+        assert_eq!(camera.transform, transformation::view_transform(point(0.0, 1.5, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0)));
+        assert_eq!(world.objects.len(), 2);
+
+        let plane = shape_list.get(world.objects[0].id());
+        assert_eq!(plane.material().pattern.unwrap().transform(), scaling(2.0, 2.0, 2.0));
+
+        let cone = shape_list.get(world.objects[1].id());
+        let cone = cone.as_any().downcast_ref::<Cone>().unwrap();
+        assert_eq!((cone.minimum, cone.maximum, cone.closed), (f64::NEG_INFINITY, 0.0, true));
+        assert_eq!(cone.transform, translation(0.0, 1.0, 0.0) * scaling(2.0, 2.0, 2.0));
+        assert_eq!(cone.material.color, Color::new(0.1, 1.0, 0.5));
+        assert_eq!(cone.material.specular, Material::new().specular);
+
+        let missing_camera = "objects: []";
+        assert_eq!(WorldBuilder::from_yaml(missing_camera).unwrap_err().kind(), ErrorKind::InvalidData);
+        let bad_operation = "{camera: {width: 1, height: 1, field_of_view: 1}, objects: [{type: csg, operation: xor, left: {type: cube}, right: {type: sphere}}]}";
+        assert_eq!(WorldBuilder::from_yaml(bad_operation).unwrap().build().err().map(|e| e.kind()), Some(ErrorKind::InvalidData));
+        assert!(WorldBuilder::from_file("does/not/exist.yaml").is_err());
+    }
+}
@@ -6,7 +6,7 @@ use crate::ray::Ray;
 use crate::{FLOAT_THRESHOLD};
 use crate::intersection::Intersection;
 use crate::matrix::Matrix4;
-use crate::tuple::{Tuple, vector};
+use crate::tuple::{Tuple, point, vector};
 use crate::float::Float;
 use crate::material::Material;
 use std::any::Any;
@@ -14,6 +14,7 @@ use std::fmt::{Formatter, Error};
 use num_traits::float::Float as NumFloat;
 use crate::shape::shape_list::ShapeList;
 use crate::normal_perturber::NormalPerturber;
+use std::f64::consts::PI;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Cone {
@@ -21,6 +22,8 @@ pub struct Cone {
     pub shape_type: String,
     pub parent_id: Option<i32>,
     pub transform: Matrix4,
+    /// Placement of this copy of the shape, applied after `transform`
+    pub instance_transform: Matrix4,
     pub material: Material,
     pub minimum: f64,
     pub maximum: f64,
@@ -30,21 +33,30 @@ pub struct Cone {
 impl Cone {
     pub fn new(shape_list: &mut ShapeList) -> Cone {
         let id = shape_list.get_id();
-        let shape = Cone {id, shape_type: String::from("cone"), parent_id: None, transform: Matrix4::identity(), material: Material::new(), minimum: NumFloat::neg_infinity(), maximum: NumFloat::infinity(), closed: false};
+        let shape = Cone {id, shape_type: String::from("cone"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new(), minimum: NumFloat::neg_infinity(), maximum: NumFloat::infinity(), closed: false};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
 
     pub fn new_with_material(material: Material, shape_list: &mut ShapeList) -> Cone {
         let id = shape_list.get_id();
-        let shape = Cone{id, shape_type: String::from("cone"), parent_id: None, transform: Matrix4::identity(), material, minimum: NumFloat::neg_infinity(), maximum: NumFloat::infinity(), closed: false};
+        let shape = Cone{id, shape_type: String::from("cone"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material, minimum: NumFloat::neg_infinity(), maximum: NumFloat::infinity(), closed: false};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
 
+    /// Creates an unbounded double cone made of two nappes meeting at the origin
+    ///
+    /// A ray can pass through both nappes, giving one intersection on each,
+    /// and a ray passing through the apex touches it twice at the same t.
+    /// This is the same as `Cone::new` but makes the shape explicit
+    pub fn new_double(shape_list: &mut ShapeList) -> Cone {
+        Cone::new_bounded(NumFloat::neg_infinity(), NumFloat::infinity(), shape_list)
+    }
+
     pub fn new_bounded(minimum: f64, maximum: f64, shape_list: &mut ShapeList) -> Cone {
         let id = shape_list.get_id();
-        let shape = Cone {id, shape_type: String::from("cone"), parent_id: None, transform: Matrix4::identity(), material: Material::new(), minimum, maximum, closed: false};
+        let shape = Cone {id, shape_type: String::from("cone"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new(), minimum, maximum, closed: false};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
@@ -80,6 +92,10 @@ impl Shape for Cone {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
     fn as_shape(&self) -> Box<&dyn Shape> {
         Box::new(self)
     }
@@ -89,17 +105,27 @@ impl Shape for Cone {
     }
 
     fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        write!(f, "Box {:?}", self)
+        write!(f, "{}", self.shape_name())
     }
 
     fn shape_clone(&self) -> Box<dyn Shape + Send> {
         Box::new(self.clone())
     }
 
+    fn clone_with_material(&self, material: Material) -> Box<dyn Shape + Send> {
+        let mut shape = self.clone();
+        shape.material = material;
+        Box::new(shape)
+    }
+
     fn id(&self) -> i32 {
         self.id
     }
 
+    fn parent_id(&self) -> Option<i32> {
+        self.parent_id
+    }
+
     fn shape_type(&self) -> String {
         self.shape_type.clone()
     }
@@ -118,17 +144,26 @@ impl Shape for Cone {
 
     fn set_parent(&mut self, parent_id: i32, shape_list: &mut ShapeList) {
         self.parent_id = Some(parent_id);
-        shape_list.update(Box::new(self.clone()));
+        shape_list.get_mut_as::<Self>(self.id()).parent_id = self.parent_id;
     }
 
     fn transform(&self) -> Matrix4 {
-        self.transform
+        self.instance_transform * self.transform
     }
 
 
     fn set_transform(&mut self, transform: Matrix4, shape_list: &mut ShapeList) {
         self.transform = transform;
-        shape_list.update(Box::new(self.clone()))
+        shape_list.get_mut_as::<Self>(self.id()).transform = self.transform;
+    }
+
+    fn instance_transform(&self) -> Matrix4 {
+        self.instance_transform
+    }
+
+    fn set_instance_transform(&mut self, instance_transform: Matrix4, shape_list: &mut ShapeList) {
+        self.instance_transform = instance_transform;
+        shape_list.get_mut_as::<Self>(self.id()).instance_transform = self.instance_transform;
     }
 
     fn material(&self) -> Material {
@@ -137,12 +172,54 @@ impl Shape for Cone {
 
     fn set_material(&mut self, material: Material, shape_list: &mut ShapeList) {
         self.material = material;
-        shape_list.update(Box::new(self.clone()))
+        shape_list.get_mut_as::<Self>(self.id()).material = self.material.clone();
+    }
+
+    /// Returns the (u, v) coordinates of a point on the cone
+    ///
+    /// The side uses a cylindrical mapping where v goes from 0 at the top of the
+    /// cone to 1 at the bottom, the caps are mapped as planar discs
+    fn uv_at(&self, local_point: &Tuple) -> (f64, f64) {
+        let x = local_point.x.value();
+        let y = local_point.y.value();
+        let z = local_point.z.value();
+
+        // The cap's radius is the same as its distance from the apex
+        let radius = y.abs();
+        let on_cap = radius > 0.0 && x * x + z * z < radius * radius - FLOAT_THRESHOLD &&
+            (y >= self.maximum - FLOAT_THRESHOLD || y <= self.minimum + FLOAT_THRESHOLD);
+        if on_cap {
+            return ((x / radius + 1.0) / 2.0, (z / radius + 1.0) / 2.0);
+        }
+
+        let u = (z.atan2(x) + PI) / (2.0 * PI);
+        let v = if self.minimum.is_finite() && self.maximum.is_finite() {
+            1.0 - (y - self.minimum) / (self.maximum - self.minimum)
+        } else {
+            // Unbounded cones repeat every unit instead
+            y.rem_euclid(1.0)
+        };
+        (u, v)
+    }
+
+    fn bounding_sphere_center(&self) -> Tuple {
+        if self.minimum.is_finite() && self.maximum.is_finite() {
+            point(0.0, (self.minimum + self.maximum) / 2.0, 0.0)
+        } else {
+            point(0.0, 0.0, 0.0)
+        }
+    }
+
+    /// The sphere around a cylinder as wide as the cone's widest end, infinite when unbounded
+    fn bounding_sphere_radius(&self) -> f64 {
+        let half_height = (self.maximum - self.minimum) / 2.0;
+        let radius = self.minimum.abs().max(self.maximum.abs());
+        (radius * radius + half_height * half_height).sqrt()
     }
 
     fn intersects(&self, ray: &Ray, _shape_list: &mut ShapeList) -> Vec<Intersection<Box<dyn Shape + Send>>> {
         // Transform the ray
-        let t_ray = ray.transform(&self.transform.inverse());
+        let t_ray = ray.transform(&self.transform().inverse());
 
         let a = (t_ray.direction.x * t_ray.direction.x -
             t_ray.direction.y * t_ray.direction.y +
@@ -206,6 +283,19 @@ impl Shape for Cone {
         }
     }
 
+    fn contains_point(&self, world_point: Tuple, shape_list: &mut ShapeList) -> bool {
+        let p = super::world_to_object(Box::new(self.clone()), world_point, shape_list);
+        let dist = p.x.value().powi(2) + p.z.value().powi(2);
+        dist <= p.y.value().powi(2) + FLOAT_THRESHOLD &&
+            p.y.value() >= self.minimum - FLOAT_THRESHOLD &&
+            p.y.value() <= self.maximum + FLOAT_THRESHOLD
+    }
+
+    fn is_convex(&self) -> bool {
+        // A closed cone spanning both nappes pinches together at the apex
+        self.closed && (self.minimum >= 0.0 || self.maximum <= 0.0)
+    }
+
     fn normal_at(&self, point: &Tuple) -> Tuple {
         let distance = point.x * point.x + point.z * point.z;
 
@@ -279,6 +369,34 @@ mod tests {
         assert_eq!(xs[0].t, 0.35355);
     }
 
+    #[test]
+    fn cone_double() {
+        let mut shape_list = ShapeList::new();
+        let cone = Cone::new_double(&mut shape_list);
+        assert_eq!(cone.minimum, f64::NEG_INFINITY);
+        assert_eq!(cone.maximum, f64::INFINITY);
+        assert!(!cone.closed);
+
+        // Through the apex
+        let r = Ray::new(point(-5.0, 0.0, 0.0), vector(1.0, 0.0, 0.0));
+        let xs = cone.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 5.0);
+        assert_eq!(xs[1].t, 5.0);
+
+        // Beside the apex
+        let r = Ray::new(point(-5.0, 0.0, 0.5), vector(1.0, 0.0, 0.0));
+        let xs = cone.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 0);
+
+        // Through both nappes
+        let r = Ray::new(point(0.5, -5.0, 0.0), vector(0.0, 1.0, 0.0));
+        let xs = cone.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.5);
+        assert_eq!(xs[1].t, 5.5);
+    }
+
     #[test]
     fn cone_intersects_cap() {
         let examples = vec![
@@ -317,4 +435,31 @@ mod tests {
             assert_eq!(n, examples[i].1);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn cone_uv_at() {
+        let mut shape_list = ShapeList::new();
+        let mut c = Cone::new(&mut shape_list);
+        c.minimum = -1.0;
+        c.maximum = 1.0;
+        c.closed = true;
+
+        // Side
+        let examples = [
+            (point(1.0, 1.0, 0.0), (0.5, 0.0)),
+            (point(0.0, 0.5, -0.5), (0.25, 0.25)),
+            (point(0.5, -0.5, 0.0), (0.5, 0.75)),
+            (point(0.0, 0.0, 0.0), (0.5, 0.5)),
+        ];
+        for (p, (u, v)) in examples.iter() {
+            let uv = c.uv_at(p);
+            assert_eq!(Float(uv.0), Float(*u));
+            assert_eq!(Float(uv.1), Float(*v));
+        }
+
+        // Caps
+        assert_eq!(c.uv_at(&point(0.0, 1.0, 0.0)), (0.5, 0.5));
+        assert_eq!(c.uv_at(&point(-0.5, 1.0, 0.5)), (0.25, 0.75));
+        assert_eq!(c.uv_at(&point(0.5, -1.0, -0.5)), (0.75, 0.25));
+    }
+}
@@ -21,6 +21,8 @@ pub struct CSG {
     pub right_id: Option<i32>,
     pub parent_id: Option<i32>,
     pub transform: Matrix4,
+    /// Placement of this copy of the shape, applied after `transform`
+    pub instance_transform: Matrix4,
     pub material: Material,
     pub operation: Option<String>,
 }
@@ -29,7 +31,7 @@ impl CSG {
     pub fn new(shape_list: &mut ShapeList) -> CSG {
         let id = shape_list.get_id();
         let shape = CSG { id, shape_type: String::from("csg"), parent_id: None, left_id: None, right_id: None,
-            transform: Matrix4::identity(), material: Material::new(), operation: None};
+            transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new(), operation: None};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
@@ -41,20 +43,46 @@ impl CSG {
         shape_list.get(right_id).set_parent(id, shape_list);
 
         let shape = CSG { id, shape_type: String::from("csg"), parent_id: None, left_id: Some(left_id), right_id: Some(right_id),
-            transform: Matrix4::identity(), material: Material::new(),
+            transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new(),
             operation: Some(String::from(operation))};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
 
+    /// Creates the difference of the right shape minus the left shape,
+    /// the reverse of `new_with_operation("difference", left_id, right_id, ..)`
+    pub fn new_difference_ba(left_id: i32, right_id: i32, shape_list: &mut ShapeList) -> CSG {
+        CSG::new_with_operation("difference", right_id, left_id, shape_list)
+    }
+
+    /// Left folds the shapes with a single operation,
+    /// `chain(vec![a, b, c], "union", ..)` builds `CSG(CSG(a, b), c)`
+    ///
+    /// A single shape is returned as is, panics if there are no shapes
+    pub fn chain(shapes: Vec<Box<dyn Shape + Send>>, operation: &str, shape_list: &mut ShapeList) -> Box<dyn Shape + Send> {
+        let mut shapes = shapes.into_iter();
+        let first = shapes.next().expect("chaining an empty list of shapes");
+        shapes.fold(first, |left, right| {
+            Box::new(CSG::new_with_operation(operation, left.id(), right.id(), shape_list))
+        })
+    }
+
     pub fn new_with_material(material: Material, shape_list: &mut ShapeList) -> CSG {
         let id = shape_list.get_id();
         let shape = CSG { id, shape_type: String::from("csg"), parent_id: None, left_id: None, right_id: None,
-            transform: Matrix4::identity(), material, operation: None};
+            transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material, operation: None};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
 
+    /// Swaps the left and right shapes, turning A - B into B - A for differences
+    pub fn swap_operands(&mut self, shape_list: &mut ShapeList) {
+        std::mem::swap(&mut self.left_id, &mut self.right_id);
+        let stored = shape_list.get_mut_as::<Self>(self.id());
+        stored.left_id = self.left_id;
+        stored.right_id = self.right_id;
+    }
+
     pub fn intersection_allowed(op: String, lhit: bool, inl: bool, inr: bool) -> bool {
 
         match op.as_ref() {
@@ -75,12 +103,17 @@ impl CSG {
 
         let mut result = vec![];
 
+        // Once a ray leaves a convex child it can't enter it again
+        let operation = self.operation.clone().unwrap();
+        let left_convex = shape_list[self.left_id.unwrap() as usize].is_convex();
+        let right_convex = shape_list[self.right_id.unwrap() as usize].is_convex();
+
         for intersection in xs {
             // if the intersection's object is part of the left child, then lhit is true
             let object_id = intersection.object.id();
             let lhit = shape_list.get(self.left_id.unwrap()).includes(object_id);
 
-            if CSG::intersection_allowed(self.operation.clone().unwrap(), lhit, inl, inr) {
+            if CSG::intersection_allowed(operation.clone(), lhit, inl, inr) {
                 result.push(intersection.clone())
             }
 
@@ -89,6 +122,15 @@ impl CSG {
             } else {
                 inr = !inr
             }
+
+            // Nothing more can be inside of both children, or inside of the left one for a difference
+            let left_exited = lhit && !inl && left_convex;
+            let right_exited = !lhit && !inr && right_convex;
+            match operation.as_ref() {
+                "intersection" if left_exited || right_exited => break,
+                "difference" if left_exited => break,
+                _ => (),
+            }
         }
         result
     }
@@ -100,6 +142,10 @@ impl Shape for CSG {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
     fn as_shape(&self) -> Box<&dyn Shape> {
         Box::new(self)
     }
@@ -109,21 +155,35 @@ impl Shape for CSG {
     }
 
     fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        write!(f, "Box {:?}", self)
+        write!(f, "{}", self.shape_name())
     }
 
     fn shape_clone(&self) -> Box<dyn Shape + Send> {
         Box::new(self.clone())
     }
 
+    fn clone_with_material(&self, material: Material) -> Box<dyn Shape + Send> {
+        let mut shape = self.clone();
+        shape.material = material;
+        Box::new(shape)
+    }
+
     fn id(&self) -> i32 {
         self.id
     }
 
+    fn parent_id(&self) -> Option<i32> {
+        self.parent_id
+    }
+
     fn shape_type(&self) -> String {
         self.shape_type.clone()
     }
 
+    fn shape_name(&self) -> String {
+        format!("CSG#{}[{}]", self.id, self.operation.clone().unwrap_or_default())
+    }
+
     fn parent(&self, shape_list: &mut ShapeList) -> Option<Box<dyn Shape + Send>> {
         if self.parent_id.is_some() {
             Some(shape_list[self.parent_id.unwrap() as usize].clone())
@@ -138,16 +198,25 @@ impl Shape for CSG {
 
     fn set_parent(&mut self, parent_id: i32, shape_list: &mut ShapeList) {
         self.parent_id = Some(parent_id);
-        shape_list.update(Box::new(self.clone()));
+        shape_list.get_mut_as::<Self>(self.id()).parent_id = self.parent_id;
     }
 
     fn transform(&self) -> Matrix4 {
-        self.transform
+        self.instance_transform * self.transform
     }
 
     fn set_transform(&mut self, transform: Matrix4, shape_list: &mut ShapeList) {
         self.transform = transform;
-        shape_list.update(Box::new(self.clone()))
+        shape_list.get_mut_as::<Self>(self.id()).transform = self.transform;
+    }
+
+    fn instance_transform(&self) -> Matrix4 {
+        self.instance_transform
+    }
+
+    fn set_instance_transform(&mut self, instance_transform: Matrix4, shape_list: &mut ShapeList) {
+        self.instance_transform = instance_transform;
+        shape_list.get_mut_as::<Self>(self.id()).instance_transform = self.instance_transform;
     }
 
     fn material(&self) -> Material {
@@ -156,12 +225,12 @@ impl Shape for CSG {
 
     fn set_material(&mut self, material: Material, shape_list: &mut ShapeList) {
         self.material = material;
-        shape_list.update(Box::new(self.clone()))
+        shape_list.get_mut_as::<Self>(self.id()).material = self.material.clone();
     }
 
     fn intersects(&self, ray: &Ray, shape_list: &mut ShapeList) -> Vec<Intersection<Box<dyn Shape + Send>>> {
         // Transform the ray
-        let t_ray = ray.transform(&self.transform.inverse());
+        let t_ray = ray.transform(&self.transform().inverse());
 
         let left_child = shape_list.get(self.left_id.unwrap());
         let right_child = shape_list.get(self.right_id.unwrap());
@@ -177,10 +246,26 @@ impl Shape for CSG {
         return self.filter_intersects(&xs, shape_list)
     }
 
+    fn contains_point(&self, world_point: Tuple, shape_list: &mut ShapeList) -> bool {
+        let (left_id, right_id) = match (self.left_id, self.right_id) {
+            (Some(left_id), Some(right_id)) => (left_id, right_id),
+            _ => return false,
+        };
+        let in_left = shape_list.get(left_id).contains_point(world_point, shape_list);
+        let in_right = shape_list.get(right_id).contains_point(world_point, shape_list);
+
+        match self.operation.as_deref() {
+            Some("union") => in_left || in_right,
+            Some("intersection") => in_left && in_right,
+            Some("difference") => in_left && !in_right,
+            _ => false,
+        }
+    }
+
     fn normal_at(&self, world_point: &Tuple) -> Tuple {
-        let object_point = self.transform.inverse() * world_point;
+        let object_point = self.transform().inverse() * world_point;
         let object_normal = object_point - point(0.0, 0.0, 0.0);
-        let mut world_normal = self.transform.inverse().transpose() * object_normal;
+        let mut world_normal = self.transform().inverse_transpose() * object_normal;
         world_normal.w = Float(0.0);
         world_normal.normalize()
     }
@@ -304,6 +389,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn csg_convex_early_exit() {
+        let mut shape_list = ShapeList::new();
+        let s1 = Sphere::new(&mut shape_list);
+        let mut s2 = Sphere::new(&mut shape_list);
+        s2.set_transform(translation(0.0, 0.0, 0.5), &mut shape_list);
+        let csg = CSG::new_with_operation("intersection", s1.id, s2.id, &mut shape_list);
+
+        // Hits after leaving a convex child are never looked at
+        let xs: Vec<Intersection<Box<dyn Shape + Send>>> = vec![
+            Intersection::new(1.0, Box::new(s1.clone())),
+            Intersection::new(1.5, Box::new(s2.clone())),
+            Intersection::new(3.0, Box::new(s1.clone())),
+            Intersection::new(4.0, Box::new(s1.clone())),
+            Intersection::new(4.5, Box::new(s2.clone())),
+        ];
+        let result = csg.filter_intersects(&xs, &mut shape_list);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].t, 1.5);
+        assert_eq!(result[1].t, 3.0);
+
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let xs = csg.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.5);
+        assert_eq!(xs[1].t, 6.0);
+    }
+
     #[test]
     fn csg_ray_misses() {
         let shape_list = &mut ShapeList::new();
@@ -313,6 +426,62 @@ mod tests {
         assert_eq!(xs.len(), 0);
     }
 
+    #[test]
+    fn csg_swap_operands() {
+        let shape_list = &mut ShapeList::new();
+        let s1 = Sphere::new(shape_list);
+        let mut s2 = Sphere::new(shape_list);
+        s2.set_transform(translation(0.0, 0.0, 0.5), shape_list);
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+
+        // s1 - s2 keeps the front of s1
+        let mut c = CSG::new_with_operation("difference", s1.id(), s2.id(), shape_list);
+        let xs = c.intersects(&r, shape_list);
+        assert_eq!(xs[0].t, 4.0);
+        assert_eq!(xs[1].t, 4.5);
+
+        // s2 - s1 keeps the back of s2
+        c.swap_operands(shape_list);
+        assert_eq!(c.left_id, Some(s2.id()));
+        assert_eq!(shape_list.get(c.id()).as_any().downcast_ref::<CSG>().unwrap().right_id, Some(s1.id()));
+        let xs = c.intersects(&r, shape_list);
+        assert_eq!(xs[0].t, 6.0);
+        assert_eq!(xs[1].t, 6.5);
+
+        let c = CSG::new_difference_ba(s1.id(), s2.id(), shape_list);
+        assert_eq!(c.left_id, Some(s2.id()));
+        assert_eq!(c.right_id, Some(s1.id()));
+    }
+
+    #[test]
+    fn csg_chain() {
+        let shape_list = &mut ShapeList::new();
+        let a = Sphere::new(shape_list);
+        let mut b = Sphere::new(shape_list);
+        b.set_transform(translation(0.0, 0.0, 1.5), shape_list);
+        let mut c = Sphere::new(shape_list);
+        c.set_transform(translation(0.0, 0.0, 3.0), shape_list);
+
+        let chained = CSG::chain(vec![Box::new(a.clone()), Box::new(b.clone()), Box::new(c.clone())], "union", shape_list);
+        let outer = chained.as_any().downcast_ref::<CSG>().unwrap();
+        assert_eq!(outer.right_id, Some(c.id()));
+        let inner = shape_list.get(outer.left_id.unwrap());
+        let inner = inner.as_any().downcast_ref::<CSG>().unwrap();
+        assert_eq!((inner.left_id, inner.right_id), (Some(a.id()), Some(b.id())));
+        assert_eq!(inner.parent_id, Some(outer.id()));
+        assert_eq!(shape_list.get(c.id()).parent_id(), Some(outer.id()));
+
+        // The union of the three spheres spans from the front of a to the back of c
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let xs = chained.intersects(&r, shape_list);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.0);
+        assert_eq!(xs[1].t, 9.0);
+
+        let single = CSG::chain(vec![Box::new(a.clone())], "union", shape_list);
+        assert_eq!(single.id(), a.id());
+    }
+
     #[test]
     fn csg_ray_hits() {
         let shape_list = &mut ShapeList::new();
@@ -329,6 +498,27 @@ mod tests {
         assert_eq!(xs[1].t, 6.5);
         assert_eq!(xs[1].object.id(), s2.id());
     }
-}
 
+    #[test]
+    fn csg_contains_point() {
+        let shape_list = &mut ShapeList::new();
+        let s1 = Sphere::new(shape_list);
+        let mut s2 = Sphere::new(shape_list);
+        s2.set_transform(translation(0.0, 0.0, 1.0), shape_list);
+
+        let table: Vec<(&str, bool, bool, bool)> = vec![
+            // op, only in s1, in both, only in s2
+            ("union", true, true, true),
+            ("intersection", false, true, false),
+            ("difference", true, false, false),
+        ];
 
+        for (op, only_left, both, only_right) in table {
+            let c = CSG::new_with_operation(op, s1.id(), s2.id(), shape_list);
+            assert_eq!(c.contains_point(point(0.0, 0.0, -0.5), shape_list), only_left);
+            assert_eq!(c.contains_point(point(0.0, 0.0, 0.5), shape_list), both);
+            assert_eq!(c.contains_point(point(0.0, 0.0, 1.5), shape_list), only_right);
+            assert!(!c.contains_point(point(0.0, 3.0, 0.0), shape_list));
+        }
+    }
+}
@@ -22,27 +22,29 @@ pub struct Cube {
     pub shape_type: String,
     pub parent_id: Option<i32>,
     pub transform: Matrix4,
+    /// Placement of this copy of the shape, applied after `transform`
+    pub instance_transform: Matrix4,
     pub material: Material,
 }
 
 impl Cube {
     pub fn new(shape_list: &mut ShapeList) -> Cube {
         let id = shape_list.get_id();
-        let shape = Cube {id, shape_type: String::from("cube"), parent_id: None, transform: Matrix4::identity(), material: Material::new()};
+        let shape = Cube {id, shape_type: String::from("cube"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new()};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
 
     pub fn new_with_material(material: Material, shape_list: &mut ShapeList) -> Cube {
         let id = shape_list.get_id();
-        let shape = Cube{id, shape_type: String::from("cube"), parent_id: None, transform: Matrix4::identity(), material};
+        let shape = Cube{id, shape_type: String::from("cube"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
 
     pub fn new_including_points(min_point: Tuple, max_point: Tuple, shape_list: &mut ShapeList) -> Cube {
         let id = shape_list.get_id();
-        let mut shape = Cube {id, shape_type: String::from("cube"), parent_id: None, transform: Matrix4::identity(), material: Material::new()};
+        let mut shape = Cube {id, shape_type: String::from("cube"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new()};
         shape_list.push(Box::new(shape.clone()));
         shape.transform_to_fit_points(min_point, max_point, shape_list);
         shape
@@ -66,6 +68,10 @@ impl Shape for Cube {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
     fn as_shape(&self) -> Box<&dyn Shape> {
         Box::new(self)
     }
@@ -75,17 +81,27 @@ impl Shape for Cube {
     }
 
     fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        write!(f, "Box {:?}", self)
+        write!(f, "{}", self.shape_name())
     }
 
     fn shape_clone(&self) -> Box<dyn Shape + Send> {
         Box::new(self.clone())
     }
 
+    fn clone_with_material(&self, material: Material) -> Box<dyn Shape + Send> {
+        let mut shape = self.clone();
+        shape.material = material;
+        Box::new(shape)
+    }
+
     fn id(&self) -> i32 {
         self.id
     }
 
+    fn parent_id(&self) -> Option<i32> {
+        self.parent_id
+    }
+
     fn shape_type(&self) -> String {
         self.shape_type.clone()
     }
@@ -104,17 +120,26 @@ impl Shape for Cube {
 
     fn set_parent(&mut self, parent_id: i32, shape_list: &mut ShapeList) {
         self.parent_id = Some(parent_id);
-        shape_list.update(Box::new(self.clone()));
+        shape_list.get_mut_as::<Self>(self.id()).parent_id = self.parent_id;
     }
 
     fn transform(&self) -> Matrix4 {
-        self.transform
+        self.instance_transform * self.transform
     }
 
 
     fn set_transform(&mut self, transform: Matrix4, shape_list: &mut ShapeList) {
         self.transform = transform;
-        shape_list.update(Box::new(self.clone()))
+        shape_list.get_mut_as::<Self>(self.id()).transform = self.transform;
+    }
+
+    fn instance_transform(&self) -> Matrix4 {
+        self.instance_transform
+    }
+
+    fn set_instance_transform(&mut self, instance_transform: Matrix4, shape_list: &mut ShapeList) {
+        self.instance_transform = instance_transform;
+        shape_list.get_mut_as::<Self>(self.id()).instance_transform = self.instance_transform;
     }
 
     fn material(&self) -> Material {
@@ -123,12 +148,40 @@ impl Shape for Cube {
 
     fn set_material(&mut self, material: Material, shape_list: &mut ShapeList) {
         self.material = material;
-        shape_list.update(Box::new(self.clone()))
+        shape_list.get_mut_as::<Self>(self.id()).material = self.material.clone();
+    }
+
+    /// Returns the (u, v) coordinates of a point on the cube, each face is mapped
+    /// on its own as if the cube were unfolded into a cross
+    fn uv_at(&self, object_point: &Tuple) -> (f64, f64) {
+        let x = object_point.x.value();
+        let y = object_point.y.value();
+        let z = object_point.z.value();
+        let maxc = x.abs().max(y.abs().max(z.abs()));
+
+        if Float(maxc) == Float(x) {
+            ((1.0 - z) / 2.0, (y + 1.0) / 2.0) // Right
+        } else if Float(maxc) == Float(-x) {
+            ((z + 1.0) / 2.0, (y + 1.0) / 2.0) // Left
+        } else if Float(maxc) == Float(y) {
+            ((x + 1.0) / 2.0, (1.0 - z) / 2.0) // Up
+        } else if Float(maxc) == Float(-y) {
+            ((x + 1.0) / 2.0, (z + 1.0) / 2.0) // Down
+        } else if Float(maxc) == Float(z) {
+            ((x + 1.0) / 2.0, (y + 1.0) / 2.0) // Front
+        } else {
+            ((1.0 - x) / 2.0, (y + 1.0) / 2.0) // Back
+        }
+    }
+
+    /// The sphere through the cube's corners
+    fn bounding_sphere_radius(&self) -> f64 {
+        3.0_f64.sqrt()
     }
 
     fn intersects(&self, ray: &Ray, _shape_list: &mut ShapeList) -> Vec<Intersection<Box<dyn Shape + Send>>> {
         // Transform the ray
-        let t_ray = ray.transform(&self.transform.inverse());
+        let t_ray = ray.transform(&self.transform().inverse());
 
         let xtminmax = check_axis(t_ray.origin.x.value(), t_ray.direction.x.value());
         let ytminmax = check_axis(t_ray.origin.y.value(), t_ray.direction.y.value());
@@ -147,6 +200,17 @@ impl Shape for Cube {
         ]
     }
 
+    fn contains_point(&self, world_point: Tuple, shape_list: &mut ShapeList) -> bool {
+        let object_point = super::world_to_object(Box::new(self.clone()), world_point, shape_list);
+        object_point.x.value().abs() <= 1.0 + FLOAT_THRESHOLD &&
+            object_point.y.value().abs() <= 1.0 + FLOAT_THRESHOLD &&
+            object_point.z.value().abs() <= 1.0 + FLOAT_THRESHOLD
+    }
+
+    fn is_convex(&self) -> bool {
+        true
+    }
+
     fn normal_at(&self, object_point: &Tuple) -> Tuple {
 
         let maxc = object_point.x.value().abs().max(object_point.y.value().abs().max(object_point.z.value().abs()));
@@ -296,4 +360,29 @@ mod tests {
         assert_eq!(xs[0].t, 3.0);
         assert_eq!(xs[1].t, 7.0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn cube_contains_point() {
+        let shape_list = &mut ShapeList::new();
+        let c = Cube::new_including_points(point(-2.0, -2.0, -2.0), point(1.0, 1.0, 2.0), shape_list);
+
+        assert!(c.contains_point(point(0.0, 0.0, 0.0), shape_list));
+        assert!(c.contains_point(point(-1.9, 0.9, 1.9), shape_list));
+        assert!(c.contains_point(point(1.0, 1.0, 2.0), shape_list));
+        assert!(!c.contains_point(point(1.1, 0.0, 0.0), shape_list));
+        assert!(!c.contains_point(point(0.0, 0.0, -2.1), shape_list));
+    }
+
+    #[test]
+    fn cube_uv_at() {
+        let shape_list = &mut ShapeList::new();
+        let c = Cube::new(shape_list);
+
+        assert_eq!(c.uv_at(&point(1.0, 0.0, 0.0)), (0.5, 0.5));
+        assert_eq!(c.uv_at(&point(-1.0, 0.5, -0.5)), (0.25, 0.75));
+        assert_eq!(c.uv_at(&point(0.5, 1.0, 1.0)), (0.75, 0.0));
+        assert_eq!(c.uv_at(&point(-0.5, -1.0, 0.5)), (0.25, 0.75));
+        assert_eq!(c.uv_at(&point(0.5, 0.5, 1.0)), (0.75, 0.75));
+        assert_eq!(c.uv_at(&point(0.5, -0.5, -1.0)), (0.25, 0.25));
+    }
+}
@@ -6,7 +6,7 @@ use crate::ray::Ray;
 use crate::{FLOAT_THRESHOLD};
 use crate::intersection::Intersection;
 use crate::matrix::Matrix4;
-use crate::tuple::{Tuple, vector};
+use crate::tuple::{Tuple, point, vector};
 use crate::float::Float;
 use crate::material::Material;
 use std::any::Any;
@@ -14,6 +14,7 @@ use std::fmt::{Formatter, Error};
 use num_traits::float::Float as NumFloat;
 use crate::shape::shape_list::ShapeList;
 use crate::normal_perturber::NormalPerturber;
+use std::f64::consts::PI;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Cylinder {
@@ -21,6 +22,8 @@ pub struct Cylinder {
     pub shape_type: String,
     pub parent_id: Option<i32>,
     pub transform: Matrix4,
+    /// Placement of this copy of the shape, applied after `transform`
+    pub instance_transform: Matrix4,
     pub material: Material,
     pub minimum: f64,
     pub maximum: f64,
@@ -30,21 +33,37 @@ pub struct Cylinder {
 impl Cylinder {
     pub fn new(shape_list: &mut ShapeList) -> Cylinder {
         let id = shape_list.get_id();
-        let shape = Cylinder {id, shape_type: String::from("cylinder"), parent_id: None, transform: Matrix4::identity(), material: Material::new(), minimum: NumFloat::neg_infinity(), maximum: NumFloat::infinity(), closed: false};
+        let shape = Cylinder {id, shape_type: String::from("cylinder"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new(), minimum: NumFloat::neg_infinity(), maximum: NumFloat::infinity(), closed: false};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
 
     pub fn new_with_material(material: Material, shape_list: &mut ShapeList) -> Cylinder {
         let id = shape_list.get_id();
-        let shape = Cylinder{id, shape_type: String::from("cylinder"), parent_id: None, transform: Matrix4::identity(), material, minimum: NumFloat::neg_infinity(), maximum: NumFloat::infinity(), closed: false};
+        let shape = Cylinder{id, shape_type: String::from("cylinder"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material, minimum: NumFloat::neg_infinity(), maximum: NumFloat::infinity(), closed: false};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
 
+    /// Same as `new_open`, kept for backwards compatibility
     pub fn new_bounded(minimum: f64, maximum: f64, shape_list: &mut ShapeList) -> Cylinder {
+        Cylinder::new_open(minimum, maximum, shape_list)
+    }
+
+    /// Creates a cylinder truncated at `minimum` and `maximum` along the y axis
+    /// with its ends left open
+    pub fn new_open(minimum: f64, maximum: f64, shape_list: &mut ShapeList) -> Cylinder {
+        let id = shape_list.get_id();
+        let shape = Cylinder {id, shape_type: String::from("cylinder"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new(), minimum, maximum, closed: false};
+        shape_list.push(Box::new(shape.clone()));
+        shape
+    }
+
+    /// Creates a cylinder truncated at `minimum` and `maximum` along the y axis
+    /// with both ends capped
+    pub fn new_closed(minimum: f64, maximum: f64, shape_list: &mut ShapeList) -> Cylinder {
         let id = shape_list.get_id();
-        let shape = Cylinder {id, shape_type: String::from("cylinder"), parent_id: None, transform: Matrix4::identity(), material: Material::new(), minimum, maximum, closed: false};
+        let shape = Cylinder {id, shape_type: String::from("cylinder"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new(), minimum, maximum, closed: true};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
@@ -80,6 +99,10 @@ impl Shape for Cylinder {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
     fn as_shape(&self) -> Box<&dyn Shape> {
         Box::new(self)
     }
@@ -89,17 +112,27 @@ impl Shape for Cylinder {
     }
 
     fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        write!(f, "Box {:?}", self)
+        write!(f, "{}", self.shape_name())
     }
 
     fn shape_clone(&self) -> Box<dyn Shape + Send> {
         Box::new(self.clone())
     }
 
+    fn clone_with_material(&self, material: Material) -> Box<dyn Shape + Send> {
+        let mut shape = self.clone();
+        shape.material = material;
+        Box::new(shape)
+    }
+
     fn id(&self) -> i32 {
         self.id
     }
 
+    fn parent_id(&self) -> Option<i32> {
+        self.parent_id
+    }
+
     fn shape_type(&self) -> String {
         self.shape_type.clone()
     }
@@ -118,17 +151,26 @@ impl Shape for Cylinder {
 
     fn set_parent(&mut self, parent_id: i32, shape_list: &mut ShapeList) {
         self.parent_id = Some(parent_id);
-        shape_list.update(Box::new(self.clone()));
+        shape_list.get_mut_as::<Self>(self.id()).parent_id = self.parent_id;
     }
 
     fn transform(&self) -> Matrix4 {
-        self.transform
+        self.instance_transform * self.transform
     }
 
 
     fn set_transform(&mut self, transform: Matrix4, shape_list: &mut ShapeList) {
         self.transform = transform;
-        shape_list.update(Box::new(self.clone()))
+        shape_list.get_mut_as::<Self>(self.id()).transform = self.transform;
+    }
+
+    fn instance_transform(&self) -> Matrix4 {
+        self.instance_transform
+    }
+
+    fn set_instance_transform(&mut self, instance_transform: Matrix4, shape_list: &mut ShapeList) {
+        self.instance_transform = instance_transform;
+        shape_list.get_mut_as::<Self>(self.id()).instance_transform = self.instance_transform;
     }
 
     fn material(&self) -> Material {
@@ -137,12 +179,48 @@ impl Shape for Cylinder {
 
     fn set_material(&mut self, material: Material, shape_list: &mut ShapeList) {
         self.material = material;
-        shape_list.update(Box::new(self.clone()))
+        shape_list.get_mut_as::<Self>(self.id()).material = self.material.clone();
+    }
+
+    /// Returns the (u, v) coordinates of a point on the cylinder
+    ///
+    /// The side uses a cylindrical mapping where u wraps around the y axis and
+    /// v repeats every unit along it, the caps are mapped as planar discs
+    fn uv_at(&self, local_point: &Tuple) -> (f64, f64) {
+        let x = local_point.x.value();
+        let y = local_point.y.value();
+        let z = local_point.z.value();
+
+        let on_cap = x * x + z * z < 1.0 &&
+            (y >= self.maximum - FLOAT_THRESHOLD || y <= self.minimum + FLOAT_THRESHOLD);
+        if on_cap {
+            return ((x + 1.0) / 2.0, (z + 1.0) / 2.0);
+        }
+
+        // Azimuthal angle from -pi to pi
+        let theta = x.atan2(z);
+        let raw_u = theta / (2.0 * PI);
+        let u = 1.0 - (raw_u + 0.5);
+        (u, y.rem_euclid(1.0))
+    }
+
+    fn bounding_sphere_center(&self) -> Tuple {
+        if self.minimum.is_finite() && self.maximum.is_finite() {
+            point(0.0, (self.minimum + self.maximum) / 2.0, 0.0)
+        } else {
+            point(0.0, 0.0, 0.0)
+        }
+    }
+
+    /// The sphere through the rims of both ends, infinite when unbounded
+    fn bounding_sphere_radius(&self) -> f64 {
+        let half_height = (self.maximum - self.minimum) / 2.0;
+        (1.0 + half_height * half_height).sqrt()
     }
 
     fn intersects(&self, ray: &Ray, _shape_list: &mut ShapeList) -> Vec<Intersection<Box<dyn Shape + Send>>> {
         // Transform the ray
-        let t_ray = ray.transform(&self.transform.inverse());
+        let t_ray = ray.transform(&self.transform().inverse());
 
         let a = (t_ray.direction.x * t_ray.direction.x + t_ray.direction.z * t_ray.direction.z).value();
 
@@ -190,6 +268,18 @@ impl Shape for Cylinder {
         }
     }
 
+    fn contains_point(&self, world_point: Tuple, shape_list: &mut ShapeList) -> bool {
+        let p = super::world_to_object(Box::new(self.clone()), world_point, shape_list);
+        let dist = p.x.value().powi(2) + p.z.value().powi(2);
+        dist <= 1.0 + FLOAT_THRESHOLD &&
+            p.y.value() >= self.minimum - FLOAT_THRESHOLD &&
+            p.y.value() <= self.maximum + FLOAT_THRESHOLD
+    }
+
+    fn is_convex(&self) -> bool {
+        self.closed
+    }
+
     fn normal_at(&self, point: &Tuple) -> Tuple {
         let distance = point.x * point.x + point.z * point.z;
 
@@ -342,6 +432,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn cylinder_open_and_closed() {
+        let mut shape_list = ShapeList::new();
+        let open = Cylinder::new_open(1.0, 2.0, &mut shape_list);
+        let closed = Cylinder::new_closed(1.0, 2.0, &mut shape_list);
+        assert!(!open.closed);
+        assert!(closed.closed);
+        assert!(!Cylinder::new_bounded(1.0, 2.0, &mut shape_list).closed);
+
+        // The shape list copy matches the constructed cylinder
+        let listed = shape_list.get(closed.id);
+        assert_eq!(listed.as_any().downcast_ref::<Cylinder>().unwrap(), &closed);
+
+        // A ray straight down the axis only hits the caps
+        let r = Ray::new(point(0.0, 3.0, 0.0), vector(0.0, -1.0, 0.0));
+        assert_eq!(open.intersects(&r, &mut shape_list).len(), 0);
+        assert_eq!(closed.intersects(&r, &mut shape_list).len(), 2);
+    }
+
     #[test]
     fn cylinder_normal_capped() {
         let mut shape_list = ShapeList::new();
@@ -364,4 +473,40 @@ mod tests {
             assert_eq!(n, examples[i].1);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn cylinder_contains_point() {
+        let mut shape_list = ShapeList::new();
+        let c = Cylinder::new_closed(1.0, 2.0, &mut shape_list);
+
+        assert!(c.contains_point(point(0.0, 1.5, 0.0), &mut shape_list));
+        assert!(c.contains_point(point(0.7, 1.0, -0.7), &mut shape_list));
+        assert!(!c.contains_point(point(0.0, 2.5, 0.0), &mut shape_list));
+        assert!(!c.contains_point(point(0.8, 1.5, 0.8), &mut shape_list));
+    }
+
+    #[test]
+    fn cylinder_uv_at() {
+        let mut shape_list = ShapeList::new();
+        let c = Cylinder::new_closed(-1.0, 1.0, &mut shape_list);
+
+        // Side
+        let examples = [
+            (point(0.0, 0.0, -1.0), (0.0, 0.0)),
+            (point(0.0, 0.5, -1.0), (0.0, 0.5)),
+            (point(-1.0, -0.25, 0.0), (0.75, 0.75)),
+            (point(0.0, 0.5, 1.0), (0.5, 0.5)),
+            (point(1.0, 0.25, 0.0), (0.25, 0.25)),
+        ];
+        for (p, (u, v)) in examples.iter() {
+            let uv = c.uv_at(p);
+            assert_eq!(Float(uv.0), Float(*u));
+            assert_eq!(Float(uv.1), Float(*v));
+        }
+
+        // Caps
+        assert_eq!(c.uv_at(&point(0.0, 1.0, 0.0)), (0.5, 0.5));
+        assert_eq!(c.uv_at(&point(-0.5, 1.0, 0.5)), (0.25, 0.75));
+        assert_eq!(c.uv_at(&point(0.5, -1.0, -0.5)), (0.75, 0.25));
+    }
+}
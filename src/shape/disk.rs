@@ -0,0 +1,210 @@
+/// # Disk
+/// `disk` is a module to represent a disk of radius 1 in the xz plane
+use crate::material::Material;
+use crate::matrix::Matrix4;
+use crate::shape::Shape;
+use std::any::Any;
+use std::fmt::{Formatter, Error};
+use crate::ray::Ray;
+use crate::intersection::Intersection;
+use crate::tuple::{Tuple, vector};
+use crate::float::Float;
+use crate::shape::shape_list::ShapeList;
+use crate::normal_perturber::NormalPerturber;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Disk {
+    pub id: i32,
+    pub shape_type: String,
+    pub parent_id: Option<i32>,
+    pub transform: Matrix4,
+    /// Placement of this copy of the shape, applied after `transform`
+    pub instance_transform: Matrix4,
+    pub material: Material,
+}
+
+impl Disk {
+    pub fn new(shape_list: &mut ShapeList) -> Disk {
+        let id = shape_list.get_id();
+        let shape = Disk {id, shape_type: String::from("disk"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new()};
+        shape_list.push(Box::new(shape.clone()));
+        shape
+    }
+
+    pub fn new_with_material(material: Material, shape_list: &mut ShapeList) -> Disk {
+        let id = shape_list.get_id();
+        let shape = Disk {id, shape_type: String::from("disk"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material};
+        shape_list.push(Box::new(shape.clone()));
+        shape
+    }
+}
+
+impl Shape for Disk {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_shape(&self) -> Box<&dyn Shape> {
+        Box::new(self)
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| self == a)
+    }
+
+    fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}", self.shape_name())
+    }
+
+    fn shape_clone(&self) -> Box<dyn Shape + Send> {
+        Box::new(self.clone())
+    }
+
+    fn clone_with_material(&self, material: Material) -> Box<dyn Shape + Send> {
+        let mut shape = self.clone();
+        shape.material = material;
+        Box::new(shape)
+    }
+
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn parent_id(&self) -> Option<i32> {
+        self.parent_id
+    }
+
+    fn shape_type(&self) -> String {
+        self.shape_type.clone()
+    }
+
+    fn parent(&self, shape_list: &mut ShapeList) -> Option<Box<dyn Shape + Send>> {
+        self.parent_id.map(|parent_id| shape_list[parent_id as usize].clone())
+    }
+
+    fn includes(&self, id: i32) -> bool {
+        self.id == id
+    }
+
+    fn set_parent(&mut self, parent_id: i32, shape_list: &mut ShapeList) {
+        self.parent_id = Some(parent_id);
+        shape_list.get_mut_as::<Self>(self.id()).parent_id = self.parent_id;
+    }
+
+    fn transform(&self) -> Matrix4 {
+        self.instance_transform * self.transform
+    }
+
+    fn set_transform(&mut self, transform: Matrix4, shape_list: &mut ShapeList) {
+        self.transform = transform;
+        shape_list.get_mut_as::<Self>(self.id()).transform = self.transform;
+    }
+
+    fn instance_transform(&self) -> Matrix4 {
+        self.instance_transform
+    }
+
+    fn set_instance_transform(&mut self, instance_transform: Matrix4, shape_list: &mut ShapeList) {
+        self.instance_transform = instance_transform;
+        shape_list.get_mut_as::<Self>(self.id()).instance_transform = self.instance_transform;
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, material: Material, shape_list: &mut ShapeList) {
+        self.material = material;
+        shape_list.get_mut_as::<Self>(self.id()).material = self.material.clone();
+    }
+
+    fn bounding_sphere_radius(&self) -> f64 {
+        1.0
+    }
+
+    fn intersects(&self, ray: &Ray, _shape_list: &mut ShapeList) -> Vec<Intersection<Box<dyn Shape + Send>>> {
+        // Transform the ray
+        let t_ray = ray.transform(&self.transform().inverse());
+
+        // If the ray is parallel with the plane (including coplanar)
+        // return an empty vec
+        let t = match t_ray.hits_plane(vector(0.0, 1.0, 0.0), 0.0) {
+            Some(t) => t,
+            None => return vec![],
+        };
+
+        // Discard hits on the plane outside of the disk
+        let hit = t_ray.position(t);
+        if hit.x * hit.x + hit.z * hit.z > Float(1.0) {
+            return vec![]
+        }
+        vec![Intersection::new(t, Box::new(self.clone()))]
+    }
+
+    fn normal_at(&self, point: &Tuple) -> Tuple {
+        // Constant normal of an xz disk
+        let mut normal = vector(0.0, 1.0, 0.0);
+        if self.material.normal_perturb.is_some() {
+            let perturb = NormalPerturber::perturb_normal(self.material.clone().normal_perturb.unwrap(),
+                                                          point, self.material.clone().normal_perturb_factor, self.material.clone().normal_perturb_perlin);
+            normal = normal + perturb;
+        }
+        normal
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tuple::point;
+    use crate::transformation;
+    use crate::shape;
+
+    #[test]
+    fn disk_normal() {
+        let mut shape_list = ShapeList::new();
+        let d = Disk::new(&mut shape_list);
+        assert_eq!(d.normal_at(&point(0.0, 0.0, 0.0)), vector(0.0, 1.0, 0.0));
+        assert_eq!(d.normal_at(&point(0.5, 0.0, -0.5)), vector(0.0, 1.0, 0.0));
+
+        let mut d = Disk::new(&mut shape_list);
+        d.set_transform(transformation::rotation_x(std::f64::consts::PI / 2.0), &mut shape_list);
+        let n = shape::normal_at(Box::new(d), point(0.0, 0.5, 0.0), &mut shape_list);
+        assert_eq!(n, vector(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn disk_intersects() {
+        let mut shape_list = ShapeList::new();
+        let d = Disk::new(&mut shape_list);
+
+        // Ray hits the center
+        let r = Ray::new(point(0.0, 1.0, 0.0), vector(0.0, -1.0, 0.0));
+        let xs = d.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 1.0);
+        assert!(xs[0].object.box_eq(d.as_any()));
+
+        // Ray hits near the edge at an angle
+        let r = Ray::new(point(0.0, 2.0, -2.99), vector(0.0, -1.0, 1.0));
+        let xs = d.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 2.0);
+
+        // Ray hits the plane outside of the radius
+        let r = Ray::new(point(0.8, 1.0, 0.8), vector(0.0, -1.0, 0.0));
+        assert!(d.intersects(&r, &mut shape_list).is_empty());
+        let r = Ray::new(point(0.0, 2.0, -3.01), vector(0.0, -1.0, 1.0));
+        assert!(d.intersects(&r, &mut shape_list).is_empty());
+
+        // Ray is parallel to the disk
+        let r = Ray::new(point(0.0, 1.0, -5.0), vector(0.0, 0.0, 1.0));
+        assert!(d.intersects(&r, &mut shape_list).is_empty());
+    }
+}
@@ -20,41 +20,78 @@ pub struct Group {
     pub shape_type: String,
     pub parent_id: Option<i32>,
     pub transform: Matrix4,
+    /// Placement of this copy of the shape, applied after `transform`
+    pub instance_transform: Matrix4,
     pub material: Material,
     pub children_ids: Vec<i32>,
     pub bounding_box: Bounds,
+    /// Intersections with children use the group's material instead of their own
+    pub override_material: bool,
+    /// Averaged normals of the vertices of smooth triangles in the group, in OBJ vertex order
+    pub vertex_normals: Vec<Tuple>,
 }
 
 impl Group {
     pub fn new(shape_list: &mut ShapeList) -> Group {
+        // The bounding box's cube is pushed first so the group's id matches its index
+        let bounding_box = Bounds::new(shape_list);
         let id = shape_list.get_id();
-        let shape = Group {id, shape_type: String::from("group"), parent_id: None, transform: Matrix4::identity(), material: Material::new(), children_ids: vec![], bounding_box: Bounds::new(shape_list)};
+        let shape = Group {id, shape_type: String::from("group"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new(), children_ids: vec![], bounding_box, override_material: false, vertex_normals: vec![]};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
 
     pub fn new_with_material(material: Material, shape_list: &mut ShapeList) -> Group {
+        let bounding_box = Bounds::new(shape_list);
         let id = shape_list.get_id();
-        let shape = Group{id, shape_type: String::from("group"), parent_id: None, transform: Matrix4::identity(), material, children_ids: vec![], bounding_box: Bounds::new(shape_list)};
+        let shape = Group{id, shape_type: String::from("group"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material, children_ids: vec![], bounding_box, override_material: false, vertex_normals: vec![]};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
 
+    pub fn set_override_material(&mut self, override_material: bool, shape_list: &mut ShapeList) {
+        self.override_material = override_material;
+        shape_list.get_mut_as::<Self>(self.id()).override_material = self.override_material;
+    }
+
+    pub fn set_vertex_normals(&mut self, vertex_normals: Vec<Tuple>, shape_list: &mut ShapeList) {
+        self.vertex_normals = vertex_normals;
+        shape_list.get_mut_as::<Self>(self.id()).vertex_normals = self.vertex_normals.clone();
+    }
+
+    /// Returns the averaged normal of a vertex, indexed from 1 like the vertices of an OBJ file
+    pub fn vertex_normal(&self, vertex_index: usize) -> Tuple {
+        self.vertex_normals[vertex_index - 1]
+    }
+
     pub fn is_empty(&self) -> bool {
         self.children_ids.is_empty()
     }
 
     pub fn add_child(&mut self, child: &mut Box<dyn Shape + Send>, shape_list: &mut ShapeList) {
+        self.add_children(std::slice::from_mut(child), shape_list);
+    }
 
-        child.set_parent(self.id(), shape_list);
+    /// Adds every child and only recomputes the bounding box once at the end,
+    /// much faster than `add_child` for large meshes
+    pub fn add_children(&mut self, children: &mut [Box<dyn Shape + Send>], shape_list: &mut ShapeList) {
+        if children.is_empty() {
+            return;
+        }
 
-        self.children_ids.push(child.id());
+        // Children are stored as they are when added, including fields set on them directly
+        for child in children.iter_mut() {
+            child.set_parent(self.id(), shape_list);
+            shape_list.update(child.clone());
+            self.children_ids.push(child.id());
+        }
 
         shape_list.update(Box::new(self.clone()));
 
         // Update group bounding box
         let group_shape: Box<dyn Shape + Send> = Box::new(self.clone());
         self.bounding_box = Bounds::bounds(group_shape, shape_list).unwrap();
+        shape_list.update(Box::new(self.clone()));
     }
 }
 
@@ -63,6 +100,10 @@ impl Shape for Group {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
     fn as_shape(&self) -> Box<&dyn Shape> {
         Box::new(self)
     }
@@ -72,21 +113,42 @@ impl Shape for Group {
     }
 
     fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        write!(f, "Box {:?}", self)
+        write!(f, "{}", self.shape_name())
     }
 
     fn shape_clone(&self) -> Box<dyn Shape + Send> {
         Box::new(self.clone())
     }
 
+    fn clone_with_material(&self, material: Material) -> Box<dyn Shape + Send> {
+        let mut shape = self.clone();
+        shape.material = material;
+        Box::new(shape)
+    }
+
     fn id(&self) -> i32 {
         self.id
     }
 
+    fn parent_id(&self) -> Option<i32> {
+        self.parent_id
+    }
+
     fn shape_type(&self) -> String {
         self.shape_type.clone()
     }
 
+    fn shape_name(&self) -> String {
+        format!("Group#{}[{} children]", self.id, self.children_ids.len())
+    }
+
+    /// Uses the bounding box kept up to date by `add_child`
+    /// instead of walking every child again
+    fn bbox_world(&self, shape_list: &ShapeList) -> (Tuple, Tuple) {
+        Bounds::transform_bounds(self.bounding_box.min_point, self.bounding_box.max_point,
+                                 &super::object_to_world_transform(self, shape_list))
+    }
+
     fn parent(&self, shape_list: &mut ShapeList) -> Option<Box<dyn Shape + Send>> {
         if self.parent_id.is_some() {
             Some(shape_list[self.parent_id.unwrap() as usize].clone())
@@ -101,19 +163,26 @@ impl Shape for Group {
 
     fn set_parent(&mut self, parent_id: i32, shape_list: &mut ShapeList) {
         self.parent_id = Some(parent_id);
-        shape_list.update(Box::new(self.clone()));
+        shape_list.get_mut_as::<Self>(self.id()).parent_id = self.parent_id;
     }
 
     fn transform(&self) -> Matrix4 {
-        self.transform
+        self.instance_transform * self.transform
     }
 
     fn set_transform(&mut self, transform: Matrix4, shape_list: &mut ShapeList) {
+        // The bounding box lives in group space so it is unaffected
         self.transform = transform;
-        shape_list.update(Box::new(self.clone()));
+        shape_list.get_mut_as::<Self>(self.id()).transform = self.transform;
+    }
 
-        // Update bounding box
-        self.bounding_box.cube.set_transform(transform, shape_list);
+    fn instance_transform(&self) -> Matrix4 {
+        self.instance_transform
+    }
+
+    fn set_instance_transform(&mut self, instance_transform: Matrix4, shape_list: &mut ShapeList) {
+        self.instance_transform = instance_transform;
+        shape_list.get_mut_as::<Self>(self.id()).instance_transform = self.instance_transform;
     }
 
     fn material(&self) -> Material {
@@ -122,29 +191,52 @@ impl Shape for Group {
 
     fn set_material(&mut self, material: Material, shape_list: &mut ShapeList) {
         self.material = material;
-        shape_list.update(Box::new(self.clone()))
+        shape_list.get_mut_as::<Self>(self.id()).material = self.material.clone();
+    }
+
+    /// The sphere through the corners of the group's bounding box
+    fn bounding_sphere_center(&self) -> Tuple {
+        if self.bounding_sphere_radius().is_finite() {
+            (self.bounding_box.min_point + self.bounding_box.max_point) * 0.5
+        } else {
+            point(0.0, 0.0, 0.0)
+        }
+    }
+
+    fn bounding_sphere_radius(&self) -> f64 {
+        (self.bounding_box.max_point - self.bounding_box.min_point).magnitude() / 2.0
     }
 
     fn intersects(&self, ray: &Ray, shape_list: &mut ShapeList) -> Vec<Intersection<Box<dyn Shape + Send>>> {
         // Transform the ray
-        let t_ray = ray.transform(&self.transform.inverse());
+        let t_ray = ray.transform(&self.transform().inverse());
 
         let mut xs: Vec<Intersection<Box<dyn Shape + Send>>> = vec![];
-        let xgroup = self.bounding_box.cube.intersects(ray, shape_list);
-
         // Only test for child intersections if the group's bounding box is hit
-        if !xgroup.is_empty() {
+        // Children apply their own transforms, so the ray is only transformed
+        // once per group boundary
+        if self.bounding_box.check_hit(&t_ray) {
             for child_id in self.children_ids.iter() {
-                xs.append(&mut shape_list.get(*child_id).intersects(&t_ray, shape_list)); // Or ray?
+                xs.append(&mut shape_list.get(*child_id).intersects(&t_ray, shape_list));
+            }
+        }
+
+        if self.override_material {
+            for x in xs.iter_mut() {
+                x.object = x.object.clone_with_material(self.material.clone());
             }
         }
         return xs
     }
 
+    fn contains_point(&self, world_point: Tuple, shape_list: &mut ShapeList) -> bool {
+        self.children_ids.iter().any(|child_id| shape_list.get(*child_id).contains_point(world_point, shape_list))
+    }
+
     fn normal_at(&self, world_point: &Tuple) -> Tuple {
-        let object_point = self.transform.inverse() * world_point;
+        let object_point = self.transform().inverse() * world_point;
         let object_normal = object_point - point(0.0, 0.0, 0.0);
-        let mut world_normal = self.transform.inverse().transpose() * object_normal;
+        let mut world_normal = self.transform().inverse_transpose() * object_normal;
         world_normal.w = Float(0.0);
         world_normal.normalize()
     }
@@ -155,9 +247,10 @@ impl Shape for Group {
 mod tests {
     use super::*;
     use crate::shape::test_shape::TestShape;
-    use crate::tuple::vector;
+    use crate::tuple::{point, vector};
     use crate::shape::sphere::Sphere;
     use crate::transformation::{translation, scaling};
+    use crate::color::Color;
 
     #[test]
     fn groups_creation() {
@@ -186,6 +279,32 @@ mod tests {
 //        assert_eq!(Some(s.parent()), g);
     }
 
+    #[test]
+    fn groups_add_children() {
+        let mut shape_list = ShapeList::new();
+        let mut g = Group::new(&mut shape_list);
+        let mut s1 = Sphere::new(&mut shape_list);
+        s1.transform = translation(-2.0, 0.0, 0.0);
+        let mut s2 = Sphere::new(&mut shape_list);
+        s2.transform = translation(2.0, 0.0, 0.0) * scaling(1.0, 3.0, 1.0);
+        let mut children: Vec<Box<dyn Shape + Send>> = vec![Box::new(s1.clone()), Box::new(s2.clone())];
+        g.add_children(&mut children, &mut shape_list);
+
+        assert_eq!(g.children_ids, vec![s1.id, s2.id]);
+        assert_eq!(shape_list.get(s1.id).parent_id(), Some(g.id));
+        assert_eq!(shape_list.get(s2.id).parent_id(), Some(g.id));
+        assert_eq!(g.bounding_box.min_point, point(-3.0, -3.0, -1.0));
+        assert_eq!(g.bounding_box.max_point, point(3.0, 3.0, 1.0));
+
+        // Same result as adding them one at a time
+        let mut one_by_one = Group::new(&mut shape_list);
+        for child in children.iter_mut() {
+            one_by_one.add_child(child, &mut shape_list);
+        }
+        assert_eq!(one_by_one.bounding_box.min_point, g.bounding_box.min_point);
+        assert_eq!(one_by_one.bounding_box.max_point, g.bounding_box.max_point);
+    }
+
     #[test]
     fn groups_intersects_empty() {
         let mut shape_list = ShapeList::new();
@@ -236,6 +355,91 @@ mod tests {
         g.set_transform(scaling(2.0, 2.0, 2.0), &mut shape_list);
         let r = Ray::new(point(10.0, 0.0, -10.0), vector(0.0, 0.0, 1.0));
         let xs = g.intersects(&r, &mut shape_list);
-//        assert_eq!(xs.len(), 2);
+        assert_eq!(xs.len(), 2);
+    }
+
+    #[test]
+    fn groups_nested_transformations() {
+        let mut shape_list = ShapeList::new();
+        let mut g1 = Group::new(&mut shape_list);
+        g1.set_transform(translation(0.0, 2.0, 0.0), &mut shape_list);
+        let mut g2 = Group::new(&mut shape_list);
+        g2.set_transform(scaling(2.0, 2.0, 2.0), &mut shape_list);
+        let mut g3 = Group::new(&mut shape_list);
+        g3.set_transform(translation(1.0, 0.0, 0.0), &mut shape_list);
+        let mut s: Box<dyn Shape + Send> = Box::new(Sphere::new(&mut shape_list));
+
+        // Sphere ends up centered at (2, 2, 0) with a radius of 2
+        g3.add_child(&mut s, &mut shape_list);
+        let mut g3: Box<dyn Shape + Send> = Box::new(g3);
+        g2.add_child(&mut g3, &mut shape_list);
+        let mut g2: Box<dyn Shape + Send> = Box::new(g2);
+        g1.add_child(&mut g2, &mut shape_list);
+
+        let r = Ray::new(point(2.0, 2.0, -10.0), vector(0.0, 0.0, 1.0));
+        let mut xs = g1.intersects(&r, &mut shape_list);
+        xs.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 8.0);
+        assert_eq!(xs[1].t, 12.0);
+        assert_eq!(xs[0].object.id(), s.id());
+
+        // Grazing the top of the sphere
+        let r = Ray::new(point(2.0, 3.9, -10.0), vector(0.0, 0.0, 1.0));
+        let xs = g1.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 2);
+
+        // Missing the sphere but hitting where an untransformed sphere would be
+        let r = Ray::new(point(0.0, 0.0, -10.0), vector(0.0, 0.0, 1.0));
+        let xs = g1.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 0);
+    }
+
+    #[test]
+    fn groups_override_material() {
+        let mut shape_list = ShapeList::new();
+        let mut g = Group::new(&mut shape_list);
+        let mut material = Material::new();
+        material.color = Color::new(1.0, 0.0, 0.0);
+        g.set_material(material.clone(), &mut shape_list);
+
+        let mut s: Box<dyn Shape + Send> = Box::new(Sphere::new(&mut shape_list));
+        g.add_child(&mut s, &mut shape_list);
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+
+        // Children keep their own material by default
+        let xs = g.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].object.material(), Material::new());
+
+        g.set_override_material(true, &mut shape_list);
+        let xs = g.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].object.id(), s.id());
+        assert_eq!(xs[0].object.material(), material);
+        assert_eq!(xs[1].object.material(), material);
+
+        // The child in the shape list is left untouched
+        assert_eq!(shape_list.get(s.id()).material(), Material::new());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn groups_contains_point() {
+        let mut shape_list = ShapeList::new();
+        let mut g1 = Group::new(&mut shape_list);
+        g1.set_transform(translation(0.0, 2.0, 0.0), &mut shape_list);
+        let mut g2 = Group::new(&mut shape_list);
+        g2.set_transform(scaling(2.0, 2.0, 2.0), &mut shape_list);
+        let mut s: Box<dyn Shape + Send> = Box::new(Sphere::new(&mut shape_list));
+
+        // Sphere ends up centered at (0, 2, 0) with a radius of 2
+        g2.add_child(&mut s, &mut shape_list);
+        let mut g2: Box<dyn Shape + Send> = Box::new(g2);
+        g1.add_child(&mut g2, &mut shape_list);
+
+        assert!(g1.contains_point(point(0.0, 3.9, 0.0), &mut shape_list));
+        assert!(g1.contains_point(point(1.0, 1.0, 1.0), &mut shape_list));
+        assert!(!g1.contains_point(point(0.0, -0.5, 0.0), &mut shape_list));
+        assert!(!Group::new(&mut shape_list).contains_point(point(0.0, 0.0, 0.0), &mut shape_list));
+    }
+}
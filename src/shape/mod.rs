@@ -6,23 +6,28 @@ use crate::ray::Ray;
 use std::sync::Mutex;
 use crate::intersection::Intersection;
 use crate::matrix::Matrix4;
-use crate::tuple::{Tuple};
+use crate::tuple::{Tuple, point, dot};
 use std::any::Any;
 use std::fmt::{Debug, Formatter, Error};
 use crate::material::Material;
 use crate::float::Float;
 use crate::shape::shape_list::ShapeList;
+use crate::bounds::Bounds;
 
 pub mod shape_list;
 
 pub mod test_shape;
 pub mod sphere;
 pub mod plane;
+pub mod disk;
 pub mod cube;
 pub mod cylinder;
 pub mod cone;
+pub mod paraboloid;
 pub mod group;
 pub mod triangle;
+pub mod smooth_triangle;
+pub mod torus;
 
 pub mod csg;
 
@@ -39,6 +44,8 @@ pub fn get_shape_id() -> i32{
 pub trait Shape: Any {
     fn as_any(&self) -> &dyn Any;
 
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
     fn as_shape(&self) -> Box<&dyn Shape>;
 
     fn box_eq(&self, other: &dyn Any) -> bool;
@@ -47,20 +54,46 @@ pub trait Shape: Any {
 
     fn shape_clone(&self) -> Box<dyn Shape + Send>;
 
+    /// Returns a copy of the shape using a different material,
+    /// the copy in the shape list is left untouched
+    fn clone_with_material(&self, material: Material) -> Box<dyn Shape + Send>;
+
     fn id(&self) -> i32;
 
     fn shape_type(&self) -> String;
 
+    /// Human-readable name of the shape for debug output, e.g. "Sphere#42"
+    fn shape_name(&self) -> String {
+        let name: String = self.shape_type().split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect(),
+                    None => String::new(),
+                }
+            })
+            .collect();
+        format!("{}#{}", name, self.id())
+    }
+
     fn parent(&self, shape_list: &mut ShapeList) -> Option<Box<dyn Shape + Send>>;
 
+    fn parent_id(&self) -> Option<i32>;
+
     fn includes(&self, id: i32) -> bool;
 
     fn set_parent(&mut self, parent_id: i32, shape_list: &mut ShapeList) ;
 
+    /// Returns the effective transform, the instance transform applied after the shape's own
     fn transform(&self) -> Matrix4;
 
     fn set_transform(&mut self, transform: Matrix4, shape_list: &mut ShapeList);
 
+    fn instance_transform(&self) -> Matrix4;
+
+    /// Sets where this copy of the shape is placed without changing its base transform
+    fn set_instance_transform(&mut self, instance_transform: Matrix4, shape_list: &mut ShapeList);
+
     fn material(&self) -> Material;
 
     fn set_material(&mut self, material: Material, shape_list: &mut ShapeList);
@@ -68,6 +101,86 @@ pub trait Shape: Any {
     fn intersects(&self, ray: &Ray, shape_list: &mut ShapeList) -> Vec<Intersection<Box<dyn Shape + Send>>>;
 
     fn normal_at(&self, point: &Tuple) -> Tuple;
+
+    /// Returns the object space normal for shapes that need to look at other shapes in the list,
+    /// like smooth triangles reading their group's vertex normals
+    fn local_normal_at(&self, point: &Tuple, _shape_list: &ShapeList) -> Tuple {
+        self.normal_at(point)
+    }
+
+    /// Returns the object space normal at a hit with barycentric coordinates (u, v),
+    /// only shapes that interpolate across their surface like smooth triangles use them
+    fn local_normal_at_uv(&self, point: &Tuple, _u: f64, _v: f64, shape_list: &ShapeList) -> Tuple {
+        self.local_normal_at(point, shape_list)
+    }
+
+    /// Returns the (u, v) texture coordinates of a point on the shape in object space,
+    /// each in [0, 1], shapes without a mapping return (0, 0)
+    fn uv_at(&self, _object_point: &Tuple) -> (f64, f64) {
+        (0.0, 0.0)
+    }
+
+    /// Returns whether a world space point is inside of the shape or on its surface
+    ///
+    /// Shapes without a volume like planes and triangles never contain a point
+    fn contains_point(&self, _world_point: Tuple, _shape_list: &mut ShapeList) -> bool {
+        false
+    }
+
+    /// Returns whether the shape is a closed convex solid, so a ray enters it
+    /// at most once and leaves it at most once
+    fn is_convex(&self) -> bool {
+        false
+    }
+
+    /// Returns the center of a sphere enclosing the shape in object space
+    fn bounding_sphere_center(&self) -> Tuple {
+        point(0.0, 0.0, 0.0)
+    }
+
+    /// Returns the radius of a sphere around `bounding_sphere_center` enclosing the shape
+    /// in object space
+    ///
+    /// Unbounded shapes and shapes made of other shapes in the list, like CSGs,
+    /// are infinite so they are never culled
+    fn bounding_sphere_radius(&self) -> f64 {
+        f64::INFINITY
+    }
+
+    /// Returns whether the ray hits the shape's bounding sphere, an even cheaper test
+    /// than `intersects_bounds` to skip shapes before doing a full intersection
+    fn intersects_bounding_sphere(&self, ray: &Ray) -> bool {
+        let radius = self.bounding_sphere_radius();
+        if radius.is_infinite() {
+            return true;
+        }
+        let t_ray = ray.transform(&self.transform().inverse());
+        let to_center = self.bounding_sphere_center() - t_ray.origin;
+
+        let c = dot(&to_center, &to_center) - radius * radius;
+        if c <= 0.0 {
+            return true; // Starts inside of the sphere
+        }
+        let b = dot(&to_center, &t_ray.direction);
+        if b < 0.0 {
+            return false; // Heading away from the sphere
+        }
+        b * b - dot(&t_ray.direction, &t_ray.direction) * c >= 0.0
+    }
+
+    /// Returns the (min, max) corners of the shape's axis aligned bounding box in world space,
+    /// after applying its transform and its parents', unlike `Bounds::bounds` which is in object space
+    fn bbox_world(&self, shape_list: &ShapeList) -> (Tuple, Tuple) {
+        let (min, max) = Bounds::object_bounds(*self.as_shape(), shape_list);
+        Bounds::transform_bounds(min, max, &object_to_world_transform(*self.as_shape(), shape_list))
+    }
+
+    /// Returns whether the ray hits the shape's bounding box from `bbox_world`,
+    /// a cheap test to skip shapes before doing a full intersection
+    fn intersects_bounds(&self, ray: &Ray, shape_list: &ShapeList) -> bool {
+        let (min, max) = self.bbox_world(shape_list);
+        Bounds::ray_hits_box(ray, min, max)
+    }
 }
 
 impl PartialEq for Box<dyn Shape + Send> {
@@ -91,6 +204,19 @@ impl Clone for Box<dyn Shape + Send> {
     }
 }
 
+/// Returns the transform taking points in the shape's object space to world space,
+/// the shape's transform followed by each of its parents'
+pub fn object_to_world_transform(shape: &dyn Shape, shape_list: &ShapeList) -> Matrix4 {
+    let mut transform = shape.transform();
+    let mut parent_id = shape.parent_id();
+    while let Some(id) = parent_id {
+        let parent = &shape_list[id as usize];
+        transform = parent.transform() * transform;
+        parent_id = parent.parent_id();
+    }
+    transform
+}
+
 /// Recursively converts a point to its parent's point until
 /// getting a world space point
 pub fn world_to_object(shape: Box<dyn Shape + Send>, point: Tuple, shape_list: &mut ShapeList) -> Tuple {
@@ -103,7 +229,7 @@ pub fn world_to_object(shape: Box<dyn Shape + Send>, point: Tuple, shape_list: &
 
 /// Recursively convert a normal to world space
 pub fn normal_to_world(shape: Box<dyn Shape + Send>, normal: Tuple, shape_list: &mut ShapeList) -> Tuple {
-    let mut new_normal: Tuple = shape.transform().inverse().transpose() * normal;
+    let mut new_normal: Tuple = shape.transform().inverse_transpose() * normal;
     new_normal.w = Float(0.0);
     new_normal = new_normal.normalize();
 
@@ -116,10 +242,17 @@ pub fn normal_to_world(shape: Box<dyn Shape + Send>, normal: Tuple, shape_list:
 
 pub fn normal_at(shape: Box<dyn Shape + Send>, world_point: Tuple, shape_list: &mut ShapeList) -> Tuple {
     let local_point = world_to_object(shape.clone(), world_point, shape_list);
-    let local_normal = shape.normal_at(&local_point);
+    let local_normal = shape.local_normal_at(&local_point, shape_list);
     return normal_to_world(shape, local_normal, shape_list);
 }
 
+/// Returns the world space normal at a hit, passing the hit's (u, v) to the shape
+pub fn normal_at_uv(shape: Box<dyn Shape + Send>, world_point: Tuple, u: f64, v: f64, shape_list: &mut ShapeList) -> Tuple {
+    let local_point = world_to_object(shape.clone(), world_point, shape_list);
+    let local_normal = shape.local_normal_at_uv(&local_point, u, v, shape_list);
+    normal_to_world(shape, local_normal, shape_list)
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -131,6 +264,10 @@ mod tests {
     use crate::transformation::{rotation_y, scaling, translation};
     use std::f64::consts::PI;
     use crate::shape::sphere::Sphere;
+    use crate::shape::cube::Cube;
+    use crate::shape::cylinder::Cylinder;
+    use crate::shape::cone::Cone;
+    use crate::shape::plane::Plane;
     use crate::shape::shape_list::ShapeList;
     use crate::tuple::{point, vector};
 
@@ -170,6 +307,103 @@ mod tests {
         assert_eq!(s.parent_id, None);
     }
 
+    #[test]
+    fn shape_name() {
+        let mut shape_list = ShapeList::new();
+        let s = Sphere::new(&mut shape_list);
+        assert_eq!(s.shape_name(), format!("Sphere#{}", s.id()));
+        let t = TestShape::new(&mut shape_list);
+        assert_eq!(t.shape_name(), format!("TestShape#{}", t.id()));
+
+        let mut g = Group::new(&mut shape_list);
+        let mut s: Box<dyn Shape + Send> = Box::new(s);
+        g.add_child(&mut s, &mut shape_list);
+        assert_eq!(g.shape_name(), format!("Group#{}[1 children]", g.id()));
+
+        let shape: Box<dyn Shape + Send> = Box::new(t.clone());
+        assert_eq!(format!("{:?}", shape), format!("TestShape#{}", t.id()));
+    }
+
+    #[test]
+    fn shape_list_get_mut() {
+        let mut shape_list = ShapeList::new();
+        let s = Sphere::new(&mut shape_list);
+
+        let listed = shape_list.get_mut(s.id);
+        let sphere = listed.as_any_mut().downcast_mut::<Sphere>().unwrap();
+        sphere.transform = scaling(2.0, 2.0, 2.0);
+        assert_eq!(shape_list.get(s.id).transform(), scaling(2.0, 2.0, 2.0));
+
+        // Replacing through get_mut is the same as update
+        let t = TestShape::new(&mut shape_list);
+        *shape_list.get_mut(t.id) = Box::new(s.clone());
+        assert_eq!(shape_list.get(t.id).shape_type(), "sphere");
+    }
+
+    #[test]
+    fn shape_list_consistency_check() {
+        let mut shape_list = ShapeList::new();
+        let mut g = Group::new(&mut shape_list);
+        let mut s: Box<dyn Shape + Send> = Box::new(Sphere::new(&mut shape_list));
+        g.add_child(&mut s, &mut shape_list);
+        assert_eq!(shape_list.consistency_check(), Vec::<String>::new());
+
+        let mut orphan = TestShape::new(&mut shape_list);
+        orphan.parent_id = Some(40);
+        shape_list.update(Box::new(orphan.clone()));
+        g.children_ids.push(41);
+        shape_list.update(Box::new(g.clone()));
+        let mut selfish = TestShape::new(&mut shape_list);
+        selfish.parent_id = Some(selfish.id);
+        shape_list.update(Box::new(selfish.clone()));
+
+        let errors = shape_list.consistency_check();
+        assert_eq!(errors, vec![
+            format!("Group#{}[2 children] has missing child 41", g.id),
+            format!("TestShape#{} has missing parent 40", orphan.id),
+            format!("TestShape#{} is its own parent", selfish.id),
+        ]);
+    }
+
+    #[test]
+    fn shape_is_convex() {
+        let mut shape_list = ShapeList::new();
+        assert!(Sphere::new(&mut shape_list).is_convex());
+        assert!(Cube::new(&mut shape_list).is_convex());
+        assert!(Cylinder::new_closed(0.0, 1.0, &mut shape_list).is_convex());
+        assert!(!Cylinder::new_open(0.0, 1.0, &mut shape_list).is_convex());
+
+        let mut cone = Cone::new_bounded(-1.0, 0.0, &mut shape_list);
+        assert!(!cone.is_convex());
+        cone.closed = true;
+        assert!(cone.is_convex());
+        cone.maximum = 1.0;
+        assert!(!cone.is_convex());
+
+        assert!(!Plane::new(&mut shape_list).is_convex());
+        assert!(!Group::new(&mut shape_list).is_convex());
+    }
+
+    #[test]
+    fn shape_list_to_dot() {
+        let mut shape_list = ShapeList::new();
+        let mut g = Group::new(&mut shape_list);
+        let mut s: Box<dyn Shape + Send> = Box::new(Sphere::new(&mut shape_list));
+        g.add_child(&mut s, &mut shape_list);
+        let mut orphan = TestShape::new(&mut shape_list);
+        orphan.parent_id = Some(40);
+        shape_list.update(Box::new(orphan.clone()));
+
+        let dot = shape_list.to_dot();
+        assert!(dot.starts_with("digraph shapes {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains(&format!("    {} [label=\"{}\"];\n", s.id(), s.shape_name())));
+        assert!(dot.contains(&format!("    {} -> {};\n", g.id, s.id())));
+        assert!(dot.contains(&format!("    40 -> {};\n", orphan.id)));
+        assert!(dot.contains("    40 [label=\"missing #40\", color=red];\n"));
+        assert_eq!(dot.matches(" -> ").count(), 2);
+    }
+
     #[test]
     fn shape_world_to_object() {
         let mut shape_list = ShapeList::new();
@@ -221,4 +455,43 @@ mod tests {
         assert_eq!(n, vector(0.28570368, 0.428543, -0.857160))
     }
 
+    #[test]
+    fn shape_bounding_sphere() {
+        let mut shape_list = ShapeList::new();
+        let sphere = Sphere::new(&mut shape_list);
+        assert_eq!(sphere.bounding_sphere_center(), point(0.0, 0.0, 0.0));
+        assert_eq!(sphere.bounding_sphere_radius(), 1.0);
+        assert_eq!(Cube::new(&mut shape_list).bounding_sphere_radius(), 3.0_f64.sqrt());
+
+        let cylinder = Cylinder::new_closed(0.0, 2.0, &mut shape_list);
+        assert_eq!(cylinder.bounding_sphere_center(), point(0.0, 1.0, 0.0));
+        assert_eq!(cylinder.bounding_sphere_radius(), 2.0_f64.sqrt());
+        assert_eq!(Cone::new_bounded(-1.0, 1.0, &mut shape_list).bounding_sphere_radius(), 2.0_f64.sqrt());
+        assert!(Cylinder::new(&mut shape_list).bounding_sphere_radius().is_infinite());
+        assert!(Plane::new(&mut shape_list).bounding_sphere_radius().is_infinite());
+
+        let mut g = Group::new(&mut shape_list);
+        let mut s: Box<dyn Shape + Send> = Box::new(Sphere::new(&mut shape_list));
+        s.set_transform(translation(5.0, 0.0, 0.0), &mut shape_list);
+        g.add_child(&mut s, &mut shape_list);
+        assert_eq!(g.bounding_sphere_center(), point(5.0, 0.0, 0.0));
+        assert_eq!(Float(g.bounding_sphere_radius()), Float(3.0_f64.sqrt()));
+    }
+
+    #[test]
+    fn shape_intersects_bounding_sphere() {
+        let mut shape_list = ShapeList::new();
+        let mut s = Sphere::new(&mut shape_list);
+        s.set_transform(translation(5.0, 0.0, 0.0), &mut shape_list);
+
+        assert!(s.intersects_bounding_sphere(&Ray::new(point(5.0, 0.5, -5.0), vector(0.0, 0.0, 1.0))));
+        assert!(s.intersects_bounding_sphere(&Ray::new(point(5.0, 0.0, 0.0), vector(0.0, 0.0, 1.0))));
+        assert!(!s.intersects_bounding_sphere(&Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0))));
+        assert!(!s.intersects_bounding_sphere(&Ray::new(point(5.0, 0.0, 5.0), vector(0.0, 0.0, 1.0))));
+
+        // Unbounded shapes are never culled
+        let p = Plane::new(&mut shape_list);
+        assert!(p.intersects_bounding_sphere(&Ray::new(point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0))));
+    }
+
 }
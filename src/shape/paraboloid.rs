@@ -0,0 +1,360 @@
+/// # Paraboloid
+/// `paraboloid` is a module to represent a paraboloid shape opening up the y axis
+use crate::shape::Shape;
+use crate::ray::Ray;
+use crate::{FLOAT_THRESHOLD};
+use crate::intersection::Intersection;
+use crate::matrix::Matrix4;
+use crate::tuple::{Tuple, point, vector};
+use crate::float::Float;
+use crate::material::Material;
+use std::any::Any;
+use std::fmt::{Formatter, Error};
+use num_traits::float::Float as NumFloat;
+use crate::shape::shape_list::ShapeList;
+use crate::normal_perturber::NormalPerturber;
+
+/// The surface y = x² + z²
+#[derive(Debug, PartialEq, Clone)]
+pub struct Paraboloid {
+    pub id: i32,
+    pub shape_type: String,
+    pub parent_id: Option<i32>,
+    pub transform: Matrix4,
+    /// Placement of this copy of the shape, applied after `transform`
+    pub instance_transform: Matrix4,
+    pub material: Material,
+    pub minimum: f64,
+    pub maximum: f64,
+    pub closed: bool,
+}
+
+impl Paraboloid {
+    pub fn new(shape_list: &mut ShapeList) -> Paraboloid {
+        Paraboloid::new_bounded(NumFloat::neg_infinity(), NumFloat::infinity(), shape_list)
+    }
+
+    pub fn new_with_material(material: Material, shape_list: &mut ShapeList) -> Paraboloid {
+        let id = shape_list.get_id();
+        let shape = Paraboloid {id, shape_type: String::from("paraboloid"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material, minimum: NumFloat::neg_infinity(), maximum: NumFloat::infinity(), closed: false};
+        shape_list.push(Box::new(shape.clone()));
+        shape
+    }
+
+    pub fn new_bounded(minimum: f64, maximum: f64, shape_list: &mut ShapeList) -> Paraboloid {
+        let id = shape_list.get_id();
+        let shape = Paraboloid {id, shape_type: String::from("paraboloid"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new(), minimum, maximum, closed: false};
+        shape_list.push(Box::new(shape.clone()));
+        shape
+    }
+
+    /// Creates a paraboloid truncated at `minimum` and `maximum` along the y axis
+    /// with both ends capped
+    pub fn new_closed(minimum: f64, maximum: f64, shape_list: &mut ShapeList) -> Paraboloid {
+        let id = shape_list.get_id();
+        let shape = Paraboloid {id, shape_type: String::from("paraboloid"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new(), minimum, maximum, closed: true};
+        shape_list.push(Box::new(shape.clone()));
+        shape
+    }
+
+    /// Check if the intersection at t is inside of the paraboloid's cross section at y
+    fn check_cap(ray: &Ray, t: Float, y: Float) -> bool {
+        let x = ray.origin.x + t * ray.direction.x;
+        let z = ray.origin.z + t * ray.direction.z;
+        (x * x + z * z) <= y
+    }
+
+    fn intersect_caps(&self, ray: &Ray, xs: &mut Vec<Intersection<Box<dyn Shape + Send>>>) {
+        if !self.closed || ray.direction.y == Float(0.0) {
+            return
+        }
+
+        // The lower cap only exists above the apex
+        if self.minimum > 0.0 {
+            let t = (self.minimum - ray.origin.y.value()) / ray.direction.y.value();
+            if Paraboloid::check_cap(ray, Float(t), Float(self.minimum)) {
+                xs.push(Intersection::new(t, Box::new(self.clone())));
+            }
+        }
+
+        if self.maximum.is_finite() {
+            let t = (self.maximum - ray.origin.y.value()) / ray.direction.y.value();
+            if Paraboloid::check_cap(ray, Float(t), Float(self.maximum)) {
+                xs.push(Intersection::new(t, Box::new(self.clone())));
+            }
+        }
+    }
+
+    fn perturb(&self, normal: Tuple, point: &Tuple) -> Tuple {
+        if self.material.normal_perturb.is_some() {
+            let perturb = NormalPerturber::perturb_normal(self.material.clone().normal_perturb.unwrap(),
+                                                          point, self.material.clone().normal_perturb_factor, self.material.clone().normal_perturb_perlin);
+            normal + perturb
+        } else {
+            normal
+        }
+    }
+}
+
+impl Shape for Paraboloid {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_shape(&self) -> Box<&dyn Shape> {
+        Box::new(self)
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| self == a)
+    }
+
+    fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}", self.shape_name())
+    }
+
+    fn shape_clone(&self) -> Box<dyn Shape + Send> {
+        Box::new(self.clone())
+    }
+
+    fn clone_with_material(&self, material: Material) -> Box<dyn Shape + Send> {
+        let mut shape = self.clone();
+        shape.material = material;
+        Box::new(shape)
+    }
+
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn parent_id(&self) -> Option<i32> {
+        self.parent_id
+    }
+
+    fn shape_type(&self) -> String {
+        self.shape_type.clone()
+    }
+
+    fn parent(&self, shape_list: &mut ShapeList) -> Option<Box<dyn Shape + Send>> {
+        self.parent_id.map(|parent_id| shape_list[parent_id as usize].clone())
+    }
+
+    fn includes(&self, id: i32) -> bool {
+        self.id == id
+    }
+
+    fn set_parent(&mut self, parent_id: i32, shape_list: &mut ShapeList) {
+        self.parent_id = Some(parent_id);
+        shape_list.get_mut_as::<Self>(self.id()).parent_id = self.parent_id;
+    }
+
+    fn transform(&self) -> Matrix4 {
+        self.instance_transform * self.transform
+    }
+
+    fn set_transform(&mut self, transform: Matrix4, shape_list: &mut ShapeList) {
+        self.transform = transform;
+        shape_list.get_mut_as::<Self>(self.id()).transform = self.transform;
+    }
+
+    fn instance_transform(&self) -> Matrix4 {
+        self.instance_transform
+    }
+
+    fn set_instance_transform(&mut self, instance_transform: Matrix4, shape_list: &mut ShapeList) {
+        self.instance_transform = instance_transform;
+        shape_list.get_mut_as::<Self>(self.id()).instance_transform = self.instance_transform;
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, material: Material, shape_list: &mut ShapeList) {
+        self.material = material;
+        shape_list.get_mut_as::<Self>(self.id()).material = self.material.clone();
+    }
+
+    fn bounding_sphere_center(&self) -> Tuple {
+        if self.maximum.is_finite() {
+            point(0.0, (self.minimum.max(0.0) + self.maximum) / 2.0, 0.0)
+        } else {
+            point(0.0, 0.0, 0.0)
+        }
+    }
+
+    /// The sphere around a cylinder as wide as the top of the paraboloid, infinite when unbounded
+    fn bounding_sphere_radius(&self) -> f64 {
+        let half_height = (self.maximum - self.minimum.max(0.0)) / 2.0;
+        // The widest point is at the top, r² = y
+        (self.maximum + half_height * half_height).sqrt()
+    }
+
+    fn intersects(&self, ray: &Ray, _shape_list: &mut ShapeList) -> Vec<Intersection<Box<dyn Shape + Send>>> {
+        // Transform the ray
+        let t_ray = ray.transform(&self.transform().inverse());
+        let (ox, oy, oz) = (t_ray.origin.x.value(), t_ray.origin.y.value(), t_ray.origin.z.value());
+        let (dx, dy, dz) = (t_ray.direction.x.value(), t_ray.direction.y.value(), t_ray.direction.z.value());
+
+        // Substitute the ray into x² + z² - y = 0
+        let a = dx * dx + dz * dz;
+        let b = 2.0 * (ox * dx + oz * dz) - dy;
+        let c = ox * ox + oz * oz - oy;
+
+        let mut ts = vec![];
+        if Float(a) == Float(0.0) {
+            // Ray parallel to the axis crosses the surface once
+            if Float(b) != Float(0.0) {
+                ts.push(-c / b);
+            }
+        } else {
+            let discriminant = b * b - 4.0 * a * c;
+            if Float(discriminant) < Float(0.0) {
+                return vec![]
+            }
+            let disc_sqrt = discriminant.max(0.0).sqrt();
+            ts.push((-b - disc_sqrt) / (2.0 * a));
+            ts.push((-b + disc_sqrt) / (2.0 * a));
+        }
+
+        let mut xs: Vec<Intersection<Box<dyn Shape + Send>>> = vec![];
+        for t in ts {
+            let y = oy + t * dy;
+            if Float(self.minimum) < Float(y) && Float(y) < Float(self.maximum) {
+                xs.push(Intersection::new(t, Box::new(self.clone())));
+            }
+        }
+        self.intersect_caps(&t_ray, &mut xs);
+        xs.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+        xs
+    }
+
+    fn contains_point(&self, world_point: Tuple, shape_list: &mut ShapeList) -> bool {
+        let p = super::world_to_object(Box::new(self.clone()), world_point, shape_list);
+        let dist = p.x.value().powi(2) + p.z.value().powi(2);
+        dist <= p.y.value() + FLOAT_THRESHOLD &&
+            p.y.value() >= self.minimum - FLOAT_THRESHOLD &&
+            p.y.value() <= self.maximum + FLOAT_THRESHOLD
+    }
+
+    fn is_convex(&self) -> bool {
+        self.closed
+    }
+
+    fn normal_at(&self, point: &Tuple) -> Tuple {
+        let distance = point.x * point.x + point.z * point.z;
+
+        let normal = if self.closed && distance < Float(self.maximum) && point.y >= Float(self.maximum) - FLOAT_THRESHOLD {
+            vector(0.0, 1.0, 0.0) // Top cap
+        } else if self.closed && self.minimum > 0.0 && distance < Float(self.minimum) && point.y <= Float(self.minimum) + FLOAT_THRESHOLD {
+            vector(0.0, -1.0, 0.0) // Bottom cap
+        } else {
+            // Gradient of x² + z² - y, pointing out of the solid like the caps
+            vector(2.0 * point.x.value(), -1.0, 2.0 * point.z.value()).normalize()
+        };
+        self.perturb(normal, point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tuple::point;
+    use crate::intersection::prepare_computations_single_intersection;
+
+    #[test]
+    fn paraboloid_intersects() {
+        let mut shape_list = ShapeList::new();
+        let paraboloid = Paraboloid::new(&mut shape_list);
+        let examples = vec![
+            // origin, direction, expected ts
+            // Straight down the axis through the vertex
+            (point(0.0, 5.0, 0.0), vector(0.0, -1.0, 0.0), vec![5.0]),
+            (point(-5.0, 1.0, 0.0), vector(1.0, 0.0, 0.0), vec![4.0, 6.0]),
+            (point(0.0, 4.0, -5.0), vector(0.0, 0.0, 1.0), vec![3.0, 7.0]),
+            // Tangent to the vertex
+            (point(-5.0, 0.0, 0.0), vector(1.0, 0.0, 0.0), vec![5.0, 5.0]),
+            // Below the vertex
+            (point(-5.0, -1.0, 0.0), vector(1.0, 0.0, 0.0), vec![]),
+        ];
+
+        for (origin, direction, expected) in examples {
+            let r = Ray::new(origin, direction);
+            let xs = paraboloid.intersects(&r, &mut shape_list);
+            let ts: Vec<Float> = xs.iter().map(|x| x.t).collect();
+            let expected: Vec<Float> = expected.into_iter().map(Float).collect();
+            assert_eq!(ts, expected, "{:?} {:?}", origin, direction);
+        }
+    }
+
+    #[test]
+    fn paraboloid_intersects_bounded() {
+        let mut shape_list = ShapeList::new();
+        let paraboloid = Paraboloid::new_bounded(1.0, 4.0, &mut shape_list);
+
+        // Open ends let rays down the axis through
+        let down = Ray::new(point(0.0, 5.0, 0.0), vector(0.0, -1.0, 0.0));
+        assert!(paraboloid.intersects(&down, &mut shape_list).is_empty());
+        let above = Ray::new(point(0.0, 5.0, -5.0), vector(0.0, 0.0, 1.0));
+        assert!(paraboloid.intersects(&above, &mut shape_list).is_empty());
+        let between = Ray::new(point(-5.0, 1.0 + 1.25, 0.0), vector(1.0, 0.0, 0.0));
+        assert_eq!(paraboloid.intersects(&between, &mut shape_list).len(), 2);
+
+        // Closed caps
+        let paraboloid = Paraboloid::new_closed(1.0, 4.0, &mut shape_list);
+        let xs = paraboloid.intersects(&down, &mut shape_list);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 1.0);
+        assert_eq!(xs[1].t, 4.0);
+
+        // A cap at the vertex is a single point
+        let paraboloid = Paraboloid::new_closed(0.0, 2.0, &mut shape_list);
+        let xs = paraboloid.intersects(&down, &mut shape_list);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 3.0);
+    }
+
+    #[test]
+    fn paraboloid_normal_at() {
+        let mut shape_list = ShapeList::new();
+        let paraboloid = Paraboloid::new(&mut shape_list);
+        assert_eq!(paraboloid.normal_at(&point(0.0, 0.0, 0.0)), vector(0.0, -1.0, 0.0));
+        assert_eq!(paraboloid.normal_at(&point(1.0, 1.0, 0.0)), vector(2.0, -1.0, 0.0).normalize());
+        assert_eq!(paraboloid.normal_at(&point(1.0, 2.0, -1.0)), vector(2.0, -1.0, -2.0).normalize());
+
+        let paraboloid = Paraboloid::new_closed(1.0, 4.0, &mut shape_list);
+        assert_eq!(paraboloid.normal_at(&point(0.5, 4.0, 0.0)), vector(0.0, 1.0, 0.0));
+        assert_eq!(paraboloid.normal_at(&point(0.5, 1.0, 0.0)), vector(0.0, -1.0, 0.0));
+        assert_eq!(paraboloid.normal_at(&point(2.0, 4.0, 0.0)), vector(4.0, -1.0, 0.0).normalize());
+
+        // The side wall and the caps both point out of a closed paraboloid
+        let inside = Ray::new(point(0.0, 2.0, 0.0), vector(1.0, 0.0, 0.0));
+        let xs = paraboloid.intersects(&inside, &mut shape_list);
+        assert_eq!(xs.len(), 2);
+        let comps = prepare_computations_single_intersection(xs[1].clone(), &inside, &mut shape_list);
+        assert!(comps.inside);
+        assert_eq!(comps.normalv, vector(-2.0 * 2.0f64.sqrt(), 1.0, 0.0).normalize());
+        let outside = Ray::new(point(-5.0, 2.0, 0.0), vector(1.0, 0.0, 0.0));
+        let xs = paraboloid.intersects(&outside, &mut shape_list);
+        let comps = prepare_computations_single_intersection(xs[0].clone(), &outside, &mut shape_list);
+        assert!(!comps.inside);
+        let up = Ray::new(point(0.0, 2.0, 0.0), vector(0.0, 1.0, 0.0));
+        let xs = paraboloid.intersects(&up, &mut shape_list);
+        let comps = prepare_computations_single_intersection(xs[1].clone(), &up, &mut shape_list);
+        assert!(comps.inside);
+    }
+
+    #[test]
+    fn paraboloid_contains_point() {
+        let mut shape_list = ShapeList::new();
+        let paraboloid = Paraboloid::new_bounded(0.0, 4.0, &mut shape_list);
+        assert!(paraboloid.contains_point(point(0.0, 1.0, 0.0), &mut shape_list));
+        assert!(paraboloid.contains_point(point(1.0, 2.0, 0.5), &mut shape_list));
+        assert!(!paraboloid.contains_point(point(2.0, 1.0, 0.0), &mut shape_list));
+        assert!(!paraboloid.contains_point(point(0.0, 5.0, 0.0), &mut shape_list));
+    }
+}
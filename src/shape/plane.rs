@@ -9,9 +9,10 @@ use std::fmt::{Formatter, Error};
 use crate::ray::Ray;
 use crate::intersection::Intersection;
 use crate::tuple::{Tuple, vector};
-use crate::float::Float;
 use crate::shape::shape_list::ShapeList;
 use crate::normal_perturber::NormalPerturber;
+use crate::pattern::Pattern;
+use crate::transformation::scaling;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Plane {
@@ -19,23 +20,34 @@ pub struct Plane {
     pub shape_type: String,
     pub parent_id: Option<i32>,
     pub transform: Matrix4,
+    /// Placement of this copy of the shape, applied after `transform`
+    pub instance_transform: Matrix4,
     pub material: Material,
 }
 
 impl Plane {
     pub fn new(shape_list: &mut ShapeList) -> Plane {
         let id = shape_list.get_id();
-        let shape = Plane {id, shape_type: String::from("plane"), parent_id: None, transform: Matrix4::identity(), material: Material::new()};
+        let shape = Plane {id, shape_type: String::from("plane"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new()};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
 
     pub fn new_with_material(material: Material, shape_list: &mut ShapeList) -> Plane {
         let id = shape_list.get_id();
-        let shape = Plane {id, shape_type: String::from("plane"), parent_id: None, transform: Matrix4::identity(), material};
+        let shape = Plane {id, shape_type: String::from("plane"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
+
+    /// Creates a plane patterned with `pattern` scaled uniformly by `scale`, on top of
+    /// the pattern's own transform
+    pub fn with_pattern_scale(mut pattern: Box<dyn Pattern + Send>, scale: f64, shape_list: &mut ShapeList) -> Plane {
+        pattern.set_transform(pattern.transform() * scaling(scale, scale, scale));
+        let mut material = Material::new();
+        material.set_pattern(pattern);
+        Plane::new_with_material(material, shape_list)
+    }
 }
 
 impl Shape for Plane {
@@ -43,6 +55,10 @@ impl Shape for Plane {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
     fn as_shape(&self) -> Box<&dyn Shape> {
         Box::new(self)
     }
@@ -52,17 +68,27 @@ impl Shape for Plane {
     }
 
     fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        write!(f, "Box {:?}", self)
+        write!(f, "{}", self.shape_name())
     }
 
     fn shape_clone(&self) -> Box<dyn Shape + Send> {
         Box::new(self.clone())
     }
 
+    fn clone_with_material(&self, material: Material) -> Box<dyn Shape + Send> {
+        let mut shape = self.clone();
+        shape.material = material;
+        Box::new(shape)
+    }
+
     fn id(&self) -> i32 {
         self.id
     }
 
+    fn parent_id(&self) -> Option<i32> {
+        self.parent_id
+    }
+
     fn shape_type(&self) -> String {
         self.shape_type.clone()
     }
@@ -81,17 +107,26 @@ impl Shape for Plane {
 
     fn set_parent(&mut self, parent_id: i32, shape_list: &mut ShapeList) {
         self.parent_id = Some(parent_id);
-        shape_list.update(Box::new(self.clone()));
+        shape_list.get_mut_as::<Self>(self.id()).parent_id = self.parent_id;
     }
 
     fn transform(&self) -> Matrix4 {
-        self.transform
+        self.instance_transform * self.transform
     }
 
 
     fn set_transform(&mut self, transform: Matrix4, shape_list: &mut ShapeList) {
         self.transform = transform;
-        shape_list.update(Box::new(self.clone()))
+        shape_list.get_mut_as::<Self>(self.id()).transform = self.transform;
+    }
+
+    fn instance_transform(&self) -> Matrix4 {
+        self.instance_transform
+    }
+
+    fn set_instance_transform(&mut self, instance_transform: Matrix4, shape_list: &mut ShapeList) {
+        self.instance_transform = instance_transform;
+        shape_list.get_mut_as::<Self>(self.id()).instance_transform = self.instance_transform;
     }
 
     fn material(&self) -> Material {
@@ -100,21 +135,20 @@ impl Shape for Plane {
 
     fn set_material(&mut self, material: Material, shape_list: &mut ShapeList) {
         self.material = material;
-        shape_list.update(Box::new(self.clone()))
+        shape_list.get_mut_as::<Self>(self.id()).material = self.material.clone();
     }
 
     fn intersects(&self, ray: &Ray, _shape_list: &mut ShapeList) -> Vec<Intersection<Box<dyn Shape + Send>>> {
         // Transform the ray
-        let t_ray = ray.transform(&self.transform.inverse());
+        let t_ray = ray.transform(&self.transform().inverse());
 
         // If the ray is parallel with the plane (including coplanar)
         // return an empty vec
-        if t_ray.direction.y == Float(0.0) {
-            return vec![]
-        }
-
-        let t = (t_ray.origin.y * -1.0) / t_ray.direction.y;
-        return vec![Intersection::new(t.value(), Box::new(self.clone()))]
+        let t = match t_ray.hits_plane(vector(0.0, 1.0, 0.0), 0.0) {
+            Some(t) => t,
+            None => return vec![],
+        };
+        vec![Intersection::new(t, Box::new(self.clone()))]
     }
 
     fn normal_at(&self, point: &Tuple) -> Tuple {
@@ -134,6 +168,10 @@ impl Shape for Plane {
 mod tests {
     use super::*;
     use crate::tuple::point;
+    use crate::pattern::stripe_pattern::StripePattern;
+    use crate::transformation::rotation_y;
+    use crate::color::Color;
+    use std::f64::consts::PI;
 
     #[test]
     fn plane_normal() {
@@ -178,4 +216,16 @@ mod tests {
         assert_eq!(xs[0].t, 1.0);
         assert!(xs[0].object.box_eq(p.as_any()));
     }
+
+    #[test]
+    fn plane_with_pattern_scale() {
+        let mut shape_list = ShapeList::new();
+        let mut pattern = StripePattern::new(Color::white(), Color::black());
+        pattern.set_transform(rotation_y(PI/2.0));
+        let p = Plane::with_pattern_scale(Box::new(pattern), 0.1, &mut shape_list);
+
+        let pattern = p.material.pattern.as_ref().unwrap();
+        assert_eq!(pattern.transform(), rotation_y(PI/2.0) * scaling(0.1, 0.1, 0.1));
+        assert_eq!(shape_list.get(p.id).material(), p.material);
+    }
 }
\ No newline at end of file
@@ -1,7 +1,8 @@
 use crate::shape::{Shape};
+use crate::shape::group::Group;
+use crate::shape::csg::CSG;
 use std::fmt::{Debug};
 use std::ops::{Index, IndexMut};
-use std::borrow::BorrowMut;
 
 /// # Shape list
 /// `shape_list` is the module containing of all copies of shapes
@@ -36,8 +37,84 @@ impl ShapeList {
         self.shapes[id as usize].clone()
     }
 
+    /// Returns the shape with the id for mutating in place without cloning it
+    pub fn get_mut(&mut self, id: i32) -> &mut Box<dyn Shape + Send> {
+        &mut self.shapes[id as usize]
+    }
+
+    /// Returns the shape with the id as its concrete type, so setters can change
+    /// a single field of the stored copy
+    ///
+    /// Panics if the shape stored at the id is not a `T`
+    pub fn get_mut_as<T: 'static>(&mut self, id: i32) -> &mut T {
+        self.get_mut(id).as_any_mut().downcast_mut::<T>().expect("Shape stored at the id has a different type")
+    }
+
     pub fn update(&mut self, val: Box<dyn Shape + Send>) {
-        std::mem::replace(self.shapes[val.id() as usize].borrow_mut(), val);
+        let id = val.id();
+        *self.get_mut(id) = val;
+    }
+
+    /// Returns a message for every broken link between shapes in the list
+    ///
+    /// Checks that each shape is stored at its id, that parents and
+    /// children exist, and that no shape is its own parent
+    pub fn consistency_check(&self) -> Vec<String> {
+        let mut errors = vec![];
+        let exists = |id: i32| id >= 0 && (id as usize) < self.shapes.len();
+
+        for (index, shape) in self.shapes.iter().enumerate() {
+            let name = shape.shape_name();
+            if shape.id() != index as i32 {
+                errors.push(format!("{} is stored at index {}", name, index));
+            }
+
+            if let Some(parent_id) = shape.parent_id() {
+                if parent_id == shape.id() {
+                    errors.push(format!("{} is its own parent", name));
+                } else if !exists(parent_id) {
+                    errors.push(format!("{} has missing parent {}", name, parent_id));
+                }
+            }
+
+            let mut children = vec![];
+            if let Some(group) = shape.as_any().downcast_ref::<Group>() {
+                children = group.children_ids.clone();
+            } else if let Some(csg) = shape.as_any().downcast_ref::<CSG>() {
+                children = csg.left_id.iter().chain(csg.right_id.iter()).cloned().collect();
+            }
+            for child_id in children {
+                if !exists(child_id) {
+                    errors.push(format!("{} has missing child {}", name, child_id));
+                }
+            }
+        }
+        errors
+    }
+
+    /// Returns a Graphviz DOT graph of the hierarchy with an edge from each parent to its children
+    ///
+    /// Parents that are not in the list are drawn in red so orphans stand out,
+    /// and shapes that are their own parent show up as loops
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph shapes {\n");
+        let mut missing = vec![];
+        for shape in self.shapes.iter() {
+            dot.push_str(&format!("    {} [label=\"{}\"];\n", shape.id(), shape.shape_name()));
+        }
+        for shape in self.shapes.iter() {
+            if let Some(parent_id) = shape.parent_id() {
+                if (parent_id < 0 || parent_id as usize >= self.shapes.len()) && !missing.contains(&parent_id) {
+                    missing.push(parent_id);
+                }
+                dot.push_str(&format!("    {} -> {};\n", parent_id, shape.id()));
+            }
+        }
+        for parent_id in missing {
+            dot.push_str(&format!("    {} [label=\"missing #{}\", color=red];\n", parent_id, parent_id));
+        }
+        dot.push_str("}\n");
+        dot
     }
 }
 
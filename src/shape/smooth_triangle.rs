@@ -0,0 +1,234 @@
+/// # SmoothTriangle
+/// `smooth_triangle` is a module to represent a triangle shaded with a normal at each point
+use crate::material::Material;
+use crate::matrix::Matrix4;
+use crate::tuple;
+use crate::shape::Shape;
+use crate::shape::triangle::{bounding_sphere, barycentric, intersect, perturb};
+use std::any::Any;
+use std::fmt::{Formatter, Error};
+use crate::ray::Ray;
+use crate::intersection::Intersection;
+use crate::tuple::{Tuple};
+use crate::shape::shape_list::ShapeList;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SmoothTriangle {
+    pub id: i32,
+    pub shape_type: String,
+    pub parent_id: Option<i32>,
+    pub transform: Matrix4,
+    /// Placement of this copy of the shape, applied after `transform`
+    pub instance_transform: Matrix4,
+    pub material: Material,
+
+    // 3 points
+    pub p1: Tuple,
+    pub p2: Tuple,
+    pub p3: Tuple,
+
+    // Normals at each point
+    pub n1: Tuple,
+    pub n2: Tuple,
+    pub n3: Tuple,
+
+    // 2 edges
+    pub e1: Tuple,
+    pub e2: Tuple,
+}
+
+impl SmoothTriangle {
+    pub fn new(p1: Tuple, p2: Tuple, p3: Tuple, n1: Tuple, n2: Tuple, n3: Tuple, shape_list: &mut ShapeList) -> SmoothTriangle {
+        SmoothTriangle::new_with_material(p1, p2, p3, n1, n2, n3, Material::new(), shape_list)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_material(p1: Tuple, p2: Tuple, p3: Tuple, n1: Tuple, n2: Tuple, n3: Tuple, material: Material, shape_list: &mut ShapeList) -> SmoothTriangle {
+        let id = shape_list.get_id();
+        let shape = SmoothTriangle {id, shape_type: String::from("smooth_triangle"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material,
+            p1, p2, p3, n1, n2, n3, e1: p2 - p1, e2: p3 - p1};
+        shape_list.push(Box::new(shape.clone()));
+        shape
+    }
+
+    /// Returns the normals blended by the barycentric coordinates of a point,
+    /// u weighting `n2` and v weighting `n3`
+    pub fn interpolated_normal(&self, u: f64, v: f64) -> Tuple {
+        (self.n2 * u + self.n3 * v + self.n1 * (1.0 - u - v)).normalize()
+    }
+}
+
+impl Shape for SmoothTriangle {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_shape(&self) -> Box<&dyn Shape> {
+        Box::new(self)
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| self == a)
+    }
+
+    fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}", self.shape_name())
+    }
+
+    fn shape_clone(&self) -> Box<dyn Shape + Send> {
+        Box::new(self.clone())
+    }
+
+    fn clone_with_material(&self, material: Material) -> Box<dyn Shape + Send> {
+        let mut shape = self.clone();
+        shape.material = material;
+        Box::new(shape)
+    }
+
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn parent_id(&self) -> Option<i32> {
+        self.parent_id
+    }
+
+    fn shape_type(&self) -> String {
+        self.shape_type.clone()
+    }
+
+    fn parent(&self, shape_list: &mut ShapeList) -> Option<Box<dyn Shape + Send>> {
+        self.parent_id.map(|parent_id| shape_list[parent_id as usize].clone())
+    }
+
+    fn includes(&self, id: i32) -> bool {
+        self.id == id
+    }
+
+    fn set_parent(&mut self, parent_id: i32, shape_list: &mut ShapeList) {
+        self.parent_id = Some(parent_id);
+        shape_list.get_mut_as::<Self>(self.id()).parent_id = self.parent_id;
+    }
+
+    fn transform(&self) -> Matrix4 {
+        self.instance_transform * self.transform
+    }
+
+    fn set_transform(&mut self, transform: Matrix4, shape_list: &mut ShapeList) {
+        self.transform = transform;
+        shape_list.get_mut_as::<Self>(self.id()).transform = self.transform;
+    }
+
+    fn instance_transform(&self) -> Matrix4 {
+        self.instance_transform
+    }
+
+    fn set_instance_transform(&mut self, instance_transform: Matrix4, shape_list: &mut ShapeList) {
+        self.instance_transform = instance_transform;
+        shape_list.get_mut_as::<Self>(self.id()).instance_transform = self.instance_transform;
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, material: Material, shape_list: &mut ShapeList) {
+        self.material = material;
+        shape_list.get_mut_as::<Self>(self.id()).material = self.material.clone();
+    }
+
+    /// Barycentric (u, v), the same as an intersection's
+    fn uv_at(&self, object_point: &Tuple) -> (f64, f64) {
+        barycentric(object_point, self.p1, self.e1, self.e2)
+    }
+
+    fn bounding_sphere_center(&self) -> Tuple {
+        bounding_sphere(self.p1, self.p2, self.p3).0
+    }
+
+    fn bounding_sphere_radius(&self) -> f64 {
+        bounding_sphere(self.p1, self.p2, self.p3).1
+    }
+
+    fn intersects(&self, ray: &Ray, _shape_list: &mut ShapeList) -> Vec<Intersection<Box<dyn Shape + Send>>> {
+        // Transform the ray
+        let t_ray = ray.transform(&self.transform().inverse());
+
+        let normal = tuple::cross(&self.e2, &self.e1).normalize();
+        match intersect(&t_ray, self.p1, self.e1, self.e2, normal) {
+            Some((t, u, v)) => vec![Intersection::new_with_uv(t, Box::new(self.clone()), u, v)],
+            None => vec![],
+        }
+    }
+
+    fn normal_at(&self, point: &Tuple) -> Tuple {
+        let (u, v) = barycentric(point, self.p1, self.e1, self.e2);
+        perturb(&self.material, self.interpolated_normal(u, v), point)
+    }
+
+    fn local_normal_at_uv(&self, point: &Tuple, u: f64, v: f64, _shape_list: &ShapeList) -> Tuple {
+        perturb(&self.material, self.interpolated_normal(u, v), point)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tuple::{point, vector};
+    use crate::intersection;
+
+    fn test_triangle(shape_list: &mut ShapeList) -> SmoothTriangle {
+        SmoothTriangle::new(point(0.0, 1.0, 0.0), point(-1.0, 0.0, 0.0), point(1.0, 0.0, 0.0),
+                            vector(0.0, 1.0, 0.0), vector(-1.0, 0.0, 0.0), vector(1.0, 0.0, 0.0), shape_list)
+    }
+
+    #[test]
+    fn smooth_triangle_creation() {
+        let mut shape_list = ShapeList::new();
+        let t = test_triangle(&mut shape_list);
+        assert_eq!(t.n1, vector(0.0, 1.0, 0.0));
+        assert_eq!(t.n2, vector(-1.0, 0.0, 0.0));
+        assert_eq!(t.n3, vector(1.0, 0.0, 0.0));
+        assert_eq!(shape_list.get(t.id).shape_type(), "smooth_triangle");
+    }
+
+    #[test]
+    fn smooth_triangle_intersects_uv() {
+        let mut shape_list = ShapeList::new();
+        let t = test_triangle(&mut shape_list);
+        let r = Ray::new(point(-0.2, 0.3, -2.0), vector(0.0, 0.0, 1.0));
+        let xs = t.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 2.0);
+        assert_eq!(xs[0].u, 0.45);
+        assert_eq!(xs[0].v, 0.25);
+
+        let r = Ray::new(point(1.0, 1.0, -2.0), vector(0.0, 0.0, 1.0));
+        assert!(t.intersects(&r, &mut shape_list).is_empty());
+    }
+
+    #[test]
+    fn smooth_triangle_interpolates_normal() {
+        let mut shape_list = ShapeList::new();
+        let t = test_triangle(&mut shape_list);
+        let face_normal = vector(0.0, 0.0, -1.0);
+
+        let n = t.interpolated_normal(0.45, 0.25);
+        assert_eq!(n, vector(-0.5547, 0.83205, 0.0));
+        assert_ne!(n, face_normal);
+        // Same normal from the hit point alone
+        assert_eq!(t.normal_at(&point(-0.2, 0.3, 0.0)), n);
+
+        // The hit's u and v reach the normal through precomputed data
+        let tb: Box<dyn Shape + Send> = Box::new(t.clone());
+        let i = Intersection::new_with_uv(2.0, tb, 0.45, 0.25);
+        let r = Ray::new(point(-0.2, 0.3, -2.0), vector(0.0, 0.0, 1.0));
+        let comps = intersection::prepare_computations_single_intersection(i, &r, &mut shape_list);
+        assert_eq!(comps.normalv, n);
+    }
+}
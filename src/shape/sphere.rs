@@ -6,13 +6,15 @@ use crate::ray::Ray;
 use crate::tuple;
 use crate::intersection::Intersection;
 use crate::matrix::Matrix4;
-use crate::tuple::{Tuple, point};
+use crate::tuple::{Tuple, point, vector};
 use crate::float::Float;
 use crate::material::Material;
 use std::any::Any;
 use std::fmt::{Formatter, Error};
 use crate::shape::shape_list::ShapeList;
 use crate::normal_perturber::NormalPerturber;
+use std::f64::consts::PI;
+use crate::FLOAT_THRESHOLD;
 
 
 #[derive(Debug, PartialEq, Clone)]
@@ -21,23 +23,68 @@ pub struct Sphere {
     pub shape_type: String,
     pub parent_id: Option<i32>,
     pub transform: Matrix4,
+    /// Placement of this copy of the shape, applied after `transform`
+    pub instance_transform: Matrix4,
     pub material: Material,
 }
 
 impl Sphere {
     pub fn new(shape_list: &mut ShapeList) -> Sphere {
         let id = shape_list.get_id();
-        let shape = Sphere {id, shape_type: String::from("sphere"), parent_id: None, transform: Matrix4::identity(), material: Material::new()};
+        let shape = Sphere {id, shape_type: String::from("sphere"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new()};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
 
     pub fn new_with_material(material: Material, shape_list: &mut ShapeList) -> Sphere {
         let id = shape_list.get_id();
-        let shape = Sphere{id, shape_type: String::from("sphere"), parent_id: None, transform: Matrix4::identity(), material};
+        let shape = Sphere{id, shape_type: String::from("sphere"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
+
+    /// Returns the (u, v) coordinates of a point on the sphere using a spherical mapping
+    /// where u wraps around the y axis and v goes from the south to the north pole
+    pub fn uv_at(local_point: &Tuple) -> (f64, f64) {
+        let x = local_point.x.value();
+        let y = local_point.y.value();
+        let z = local_point.z.value();
+
+        // Azimuthal angle from -pi to pi
+        let theta = x.atan2(z);
+        let radius = (x * x + y * y + z * z).sqrt();
+        // Polar angle from 0 to pi
+        let phi = (y / radius).acos();
+
+        let raw_u = theta / (2.0 * PI);
+        let u = 1.0 - (raw_u + 0.5);
+        let v = 1.0 - phi / PI;
+        (u, v)
+    }
+
+    /// Returns the gradients (dU/dp, dV/dp) of the sphere's uv mapping at a local point
+    ///
+    /// These point along the directions u and v increase on the surface, so they
+    /// serve as the tangent and bitangent for normal mapping.
+    /// Both are zero vectors at the poles where the mapping is degenerate.
+    pub fn uv_derivatives(local_point: &Tuple) -> (Tuple, Tuple) {
+        let x = local_point.x.value();
+        let y = local_point.y.value();
+        let z = local_point.z.value();
+
+        let xz_squared = x * x + z * z;
+        if Float(xz_squared) == Float(0.0) {
+            return (vector(0.0, 0.0, 0.0), vector(0.0, 0.0, 0.0));
+        }
+        let xz_length = xz_squared.sqrt();
+        let radius_squared = xz_squared + y * y;
+
+        // u = 0.5 - atan2(x, z) / 2pi
+        let du = vector(-z, 0.0, x) / (2.0 * PI * xz_squared);
+        // v = 1 - acos(y / r) / pi
+        let dv = vector(-x * y / radius_squared, 1.0 - y * y / radius_squared, -z * y / radius_squared) / (PI * xz_length);
+        (du, dv)
+    }
 }
 
 impl Shape for Sphere {
@@ -45,6 +92,10 @@ impl Shape for Sphere {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
     fn as_shape(&self) -> Box<&dyn Shape> {
         Box::new(self)
     }
@@ -54,17 +105,27 @@ impl Shape for Sphere {
     }
 
     fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        write!(f, "Box {:?}", self)
+        write!(f, "{}", self.shape_name())
     }
 
     fn shape_clone(&self) -> Box<dyn Shape + Send> {
         Box::new(self.clone())
     }
 
+    fn clone_with_material(&self, material: Material) -> Box<dyn Shape + Send> {
+        let mut shape = self.clone();
+        shape.material = material;
+        Box::new(shape)
+    }
+
     fn id(&self) -> i32 {
         self.id
     }
 
+    fn parent_id(&self) -> Option<i32> {
+        self.parent_id
+    }
+
     fn shape_type(&self) -> String {
         self.shape_type.clone()
     }
@@ -83,16 +144,25 @@ impl Shape for Sphere {
 
     fn set_parent(&mut self, parent_id: i32, shape_list: &mut ShapeList) {
         self.parent_id = Some(parent_id);
-        shape_list.update(Box::new(self.clone()));
+        shape_list.get_mut_as::<Self>(self.id()).parent_id = self.parent_id;
     }
 
     fn transform(&self) -> Matrix4 {
-        self.transform
+        self.instance_transform * self.transform
     }
 
     fn set_transform(&mut self, transform: Matrix4, shape_list: &mut ShapeList) {
         self.transform = transform;
-        shape_list.update(Box::new(self.clone()))
+        shape_list.get_mut_as::<Self>(self.id()).transform = self.transform;
+    }
+
+    fn instance_transform(&self) -> Matrix4 {
+        self.instance_transform
+    }
+
+    fn set_instance_transform(&mut self, instance_transform: Matrix4, shape_list: &mut ShapeList) {
+        self.instance_transform = instance_transform;
+        shape_list.get_mut_as::<Self>(self.id()).instance_transform = self.instance_transform;
     }
 
     fn material(&self) -> Material {
@@ -101,12 +171,20 @@ impl Shape for Sphere {
 
     fn set_material(&mut self, material: Material, shape_list: &mut ShapeList) {
         self.material = material;
-        shape_list.update(Box::new(self.clone()));
+        shape_list.get_mut_as::<Self>(self.id()).material = self.material.clone();
+    }
+
+    fn uv_at(&self, object_point: &Tuple) -> (f64, f64) {
+        Sphere::uv_at(object_point)
+    }
+
+    fn bounding_sphere_radius(&self) -> f64 {
+        1.0
     }
 
     fn intersects(&self, ray: &Ray, _shape_list: &mut ShapeList) -> Vec<Intersection<Box<dyn Shape + Send>>> {
         // Transform the ray
-        let t_ray = ray.transform(&self.transform.inverse());
+        let t_ray = ray.transform(&self.transform().inverse());
         // vector from the sphere's center to the ray origin
         let sphere_to_ray =t_ray.origin - point(0.0, 0.0, 0.0);
 
@@ -127,9 +205,18 @@ impl Shape for Sphere {
         }
     }
 
+    fn contains_point(&self, world_point: Tuple, shape_list: &mut ShapeList) -> bool {
+        let object_point = super::world_to_object(Box::new(self.clone()), world_point, shape_list);
+        (object_point - point(0.0, 0.0, 0.0)).magnitude() <= 1.0 + FLOAT_THRESHOLD
+    }
+
+    fn is_convex(&self) -> bool {
+        true
+    }
+
     fn normal_at(&self, object_point: &Tuple) -> Tuple {
         let object_normal = object_point - point(0.0, 0.0, 0.0);
-        let mut world_normal = self.transform.inverse().transpose() * object_normal;
+        let mut world_normal = self.transform().inverse_transpose() * object_normal;
         world_normal.w = Float(0.0);
         if self.material.normal_perturb.is_some() {
             let perturb = NormalPerturber::perturb_normal(self.material.clone().normal_perturb.unwrap(),
@@ -144,7 +231,6 @@ impl Shape for Sphere {
 mod tests {
     use super::*;
     use crate::transformation;
-    use crate::tuple::vector;
 
     #[test]
     fn sphere_intersection() {
@@ -249,4 +335,90 @@ mod tests {
         assert_eq!(s.material.transparency, 1.0);
         assert_eq!(s.material.refractive_index, 1.5);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn sphere_uv_at() {
+        let examples = vec![
+            // point, u, v
+            (point(0.0, 0.0, -1.0), 0.0, 0.5),
+            (point(1.0, 0.0, 0.0), 0.25, 0.5),
+            (point(0.0, 0.0, 1.0), 0.5, 0.5),
+            (point(-1.0, 0.0, 0.0), 0.75, 0.5),
+            (point(0.0, 1.0, 0.0), 0.5, 1.0),
+            (point(0.0, -1.0, 0.0), 0.5, 0.0),
+            (point(2.0f64.sqrt()/2.0, 2.0f64.sqrt()/2.0, 0.0), 0.25, 0.75),
+        ];
+
+        for (p, u, v) in examples {
+            let uv = Sphere::uv_at(&p);
+            assert_eq!(Float(uv.0), Float(u));
+            assert_eq!(Float(uv.1), Float(v));
+        }
+    }
+
+    #[test]
+    fn sphere_uv_derivatives() {
+        let (du, dv) = Sphere::uv_derivatives(&point(0.0, 0.0, -1.0));
+        assert_eq!(du, vector(1.0 / (2.0 * PI), 0.0, 0.0));
+        assert_eq!(dv, vector(0.0, 1.0 / PI, 0.0));
+
+        let (du, dv) = Sphere::uv_derivatives(&point(1.0, 0.0, 0.0));
+        assert_eq!(du, vector(0.0, 0.0, 1.0 / (2.0 * PI)));
+        assert_eq!(dv, vector(0.0, 1.0 / PI, 0.0));
+
+        // Derivatives agree with a finite difference along the surface
+        let p = point(0.48, 0.6, -0.64);
+        let (du, _) = Sphere::uv_derivatives(&p);
+        let step = vector(0.0001, 0.0, 0.0);
+        let (u1, _) = Sphere::uv_at(&(p + step));
+        let (u0, _) = Sphere::uv_at(&(p - step));
+        assert_eq!(Float((u1 - u0) / 0.0002), du.x);
+
+        // Degenerate at the poles
+        let (du, dv) = Sphere::uv_derivatives(&point(0.0, 1.0, 0.0));
+        assert_eq!(du, vector(0.0, 0.0, 0.0));
+        assert_eq!(dv, vector(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn sphere_contains_point() {
+        let mut shape_list = ShapeList::new();
+        let mut s = Sphere::new(&mut shape_list);
+        s.set_transform(transformation::translation(0.0, 1.0, 0.0) * transformation::scaling(2.0, 1.0, 1.0), &mut shape_list);
+
+        assert!(s.contains_point(point(0.0, 1.0, 0.0), &mut shape_list));
+        assert!(s.contains_point(point(1.9, 1.0, 0.0), &mut shape_list));
+        assert!(s.contains_point(point(0.0, 2.0, 0.0), &mut shape_list)); // On the surface
+        assert!(!s.contains_point(point(0.0, 1.0, 1.1), &mut shape_list));
+        assert!(!s.contains_point(point(0.0, -0.1, 0.0), &mut shape_list));
+    }
+
+    #[test]
+    fn sphere_instance_transform() {
+        let mut shape_list = ShapeList::new();
+        let mut s = Sphere::new(&mut shape_list);
+        assert_eq!(s.instance_transform(), Matrix4::identity());
+
+        let base = transformation::scaling(2.0, 2.0, 2.0);
+        let instance = transformation::translation(5.0, 0.0, 0.0);
+        s.set_transform(base, &mut shape_list);
+        s.set_instance_transform(instance, &mut shape_list);
+        assert_eq!(s.transform, base);
+        assert_eq!(s.transform(), instance * base);
+        assert_eq!(shape_list.get(s.id()).instance_transform(), instance);
+
+        // The instance is moved as a whole, keeping its base scale
+        let r = Ray::new(point(5.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let xs = s.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 3.0);
+        assert_eq!(xs[1].t, 7.0);
+
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        assert_eq!(s.intersects(&r, &mut shape_list).len(), 0);
+
+        // Normals follow the effective transform
+        let n = super::super::normal_at(Box::new(s.clone()), point(5.0, 2.0, 0.0), &mut shape_list);
+        assert_eq!(n, vector(0.0, 1.0, 0.0));
+    }
+}
@@ -0,0 +1,435 @@
+/// # Torus
+/// `torus` is a module to represent a torus shape lying in the xz plane around the y axis
+use crate::shape::Shape;
+use crate::ray::Ray;
+use crate::tuple::{self, Tuple, point, vector};
+use crate::intersection::Intersection;
+use crate::matrix::Matrix4;
+use crate::material::Material;
+use std::any::Any;
+use std::fmt::{Formatter, Error};
+use crate::shape::shape_list::ShapeList;
+use crate::normal_perturber::NormalPerturber;
+use std::f64::consts::PI;
+
+const DEFAULT_MAJOR_RADIUS: f64 = 1.0;
+const DEFAULT_MINOR_RADIUS: f64 = 0.25;
+/// Coefficients closer to zero than this are treated as zero when solving polynomials
+const EQUATION_EPSILON: f64 = 1e-9;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Torus {
+    pub id: i32,
+    pub shape_type: String,
+    pub parent_id: Option<i32>,
+    pub transform: Matrix4,
+    /// Placement of this copy of the shape, applied after `transform`
+    pub instance_transform: Matrix4,
+    pub material: Material,
+    /// Distance from the center of the torus to the center of the tube
+    pub major_radius: f64,
+    /// Radius of the tube
+    pub minor_radius: f64,
+}
+
+impl Torus {
+    pub fn new(shape_list: &mut ShapeList) -> Torus {
+        Torus::new_with_radii(DEFAULT_MAJOR_RADIUS, DEFAULT_MINOR_RADIUS, shape_list)
+    }
+
+    pub fn new_with_radii(major_radius: f64, minor_radius: f64, shape_list: &mut ShapeList) -> Torus {
+        let id = shape_list.get_id();
+        let shape = Torus {id, shape_type: String::from("torus"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new(),
+            major_radius, minor_radius};
+        shape_list.push(Box::new(shape.clone()));
+        shape
+    }
+
+    pub fn new_with_material(material: Material, shape_list: &mut ShapeList) -> Torus {
+        let id = shape_list.get_id();
+        let shape = Torus {id, shape_type: String::from("torus"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material,
+            major_radius: DEFAULT_MAJOR_RADIUS, minor_radius: DEFAULT_MINOR_RADIUS};
+        shape_list.push(Box::new(shape.clone()));
+        shape
+    }
+
+    /// Returns the value of the torus' implicit function at a local point,
+    /// negative inside of the tube, zero on the surface and positive outside
+    fn implicit(&self, p: &Tuple) -> f64 {
+        let (x, y, z) = (p.x.value(), p.y.value(), p.z.value());
+        let r2 = self.major_radius * self.major_radius;
+        let sum = x * x + y * y + z * z + r2 - self.minor_radius * self.minor_radius;
+        sum * sum - 4.0 * r2 * (x * x + z * z)
+    }
+}
+
+impl Shape for Torus {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_shape(&self) -> Box<&dyn Shape> {
+        Box::new(self)
+    }
+
+    fn box_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<Self>().is_some_and(|a| self == a)
+    }
+
+    fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}", self.shape_name())
+    }
+
+    fn shape_clone(&self) -> Box<dyn Shape + Send> {
+        Box::new(self.clone())
+    }
+
+    fn clone_with_material(&self, material: Material) -> Box<dyn Shape + Send> {
+        let mut shape = self.clone();
+        shape.material = material;
+        Box::new(shape)
+    }
+
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn parent_id(&self) -> Option<i32> {
+        self.parent_id
+    }
+
+    fn shape_type(&self) -> String {
+        self.shape_type.clone()
+    }
+
+    fn parent(&self, shape_list: &mut ShapeList) -> Option<Box<dyn Shape + Send>> {
+        self.parent_id.map(|parent_id| shape_list[parent_id as usize].clone())
+    }
+
+    fn includes(&self, id: i32) -> bool {
+        self.id == id
+    }
+
+    fn set_parent(&mut self, parent_id: i32, shape_list: &mut ShapeList) {
+        self.parent_id = Some(parent_id);
+        shape_list.get_mut_as::<Self>(self.id()).parent_id = self.parent_id;
+    }
+
+    fn transform(&self) -> Matrix4 {
+        self.instance_transform * self.transform
+    }
+
+    fn set_transform(&mut self, transform: Matrix4, shape_list: &mut ShapeList) {
+        self.transform = transform;
+        shape_list.get_mut_as::<Self>(self.id()).transform = self.transform;
+    }
+
+    fn instance_transform(&self) -> Matrix4 {
+        self.instance_transform
+    }
+
+    fn set_instance_transform(&mut self, instance_transform: Matrix4, shape_list: &mut ShapeList) {
+        self.instance_transform = instance_transform;
+        shape_list.get_mut_as::<Self>(self.id()).instance_transform = self.instance_transform;
+    }
+
+    fn material(&self) -> Material {
+        self.material.clone()
+    }
+
+    fn set_material(&mut self, material: Material, shape_list: &mut ShapeList) {
+        self.material = material;
+        shape_list.get_mut_as::<Self>(self.id()).material = self.material.clone();
+    }
+
+    fn bounding_sphere_radius(&self) -> f64 {
+        self.major_radius + self.minor_radius
+    }
+
+    fn intersects(&self, ray: &Ray, _shape_list: &mut ShapeList) -> Vec<Intersection<Box<dyn Shape + Send>>> {
+        // Transform the ray
+        let t_ray = ray.transform(&self.transform().inverse());
+
+        // Skip rays missing the bounding sphere, otherwise start the ray on it
+        // so the quartic's coefficients stay small and precise
+        let bounding_radius = self.major_radius + self.minor_radius;
+        let center_to_origin = t_ray.origin - point(0.0, 0.0, 0.0);
+        let a = tuple::dot(&t_ray.direction, &t_ray.direction);
+        let b = 2.0 * tuple::dot(&t_ray.direction, &center_to_origin);
+        let c = tuple::dot(&center_to_origin, &center_to_origin) - bounding_radius * bounding_radius;
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return vec![];
+        }
+        let t_start = (-b - discriminant.sqrt()) / (2.0 * a);
+        let origin = t_ray.position(t_start);
+
+        // Substitute the ray into (x² + y² + z² + R² - r²)² = 4R²(x² + z²)
+        let (ox, oy, oz) = (origin.x.value(), origin.y.value(), origin.z.value());
+        let (dx, dy, dz) = (t_ray.direction.x.value(), t_ray.direction.y.value(), t_ray.direction.z.value());
+        let r2 = self.major_radius * self.major_radius;
+        let dd = dx * dx + dy * dy + dz * dz;
+        let od = ox * dx + oy * dy + oz * dz;
+        let k = ox * ox + oy * oy + oz * oz + r2 - self.minor_radius * self.minor_radius;
+        let coefficients = [
+            k * k - 4.0 * r2 * (ox * ox + oz * oz),
+            4.0 * od * k - 8.0 * r2 * (ox * dx + oz * dz),
+            2.0 * dd * k + 4.0 * od * od - 4.0 * r2 * (dx * dx + dz * dz),
+            4.0 * dd * od,
+            dd * dd,
+        ];
+
+        let mut ts: Vec<f64> = solve_quartic(&coefficients).into_iter()
+            .map(|t| polish_root(&coefficients, t) + t_start)
+            .collect();
+        ts.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        ts.into_iter().map(|t| Intersection::new(t, Box::new(self.clone()) as Box<dyn Shape + Send>)).collect()
+    }
+
+    fn contains_point(&self, world_point: Tuple, shape_list: &mut ShapeList) -> bool {
+        let object_point = super::world_to_object(Box::new(self.clone()), world_point, shape_list);
+        self.implicit(&object_point) <= EQUATION_EPSILON
+    }
+
+    fn normal_at(&self, point: &Tuple) -> Tuple {
+        // Gradient of the implicit surface
+        let (x, y, z) = (point.x.value(), point.y.value(), point.z.value());
+        let r2 = self.major_radius * self.major_radius;
+        let sum = x * x + y * y + z * z + r2 - self.minor_radius * self.minor_radius;
+        let mut normal = vector(x * (sum - 2.0 * r2), y * sum, z * (sum - 2.0 * r2)).normalize();
+        if self.material.normal_perturb.is_some() {
+            let perturb = NormalPerturber::perturb_normal(self.material.clone().normal_perturb.unwrap(),
+                                                          point, self.material.clone().normal_perturb_factor, self.material.clone().normal_perturb_perlin);
+            normal = normal + perturb;
+        }
+        normal
+    }
+}
+
+fn is_zero(x: f64) -> bool {
+    x.abs() < EQUATION_EPSILON
+}
+
+/// Returns the real roots of c[0] + c[1]x + c[2]x²
+fn solve_quadratic(c: &[f64; 3]) -> Vec<f64> {
+    let p = c[1] / (2.0 * c[2]);
+    let q = c[0] / c[2];
+    let discriminant = p * p - q;
+
+    if is_zero(discriminant) {
+        vec![-p]
+    } else if discriminant < 0.0 {
+        vec![]
+    } else {
+        let sqrt_d = discriminant.sqrt();
+        vec![sqrt_d - p, -sqrt_d - p]
+    }
+}
+
+/// Returns the real roots of c[0] + c[1]x + c[2]x² + c[3]x³
+fn solve_cubic(c: &[f64; 4]) -> Vec<f64> {
+    // Normal form x³ + Ax² + Bx + C
+    let a = c[2] / c[3];
+    let b = c[1] / c[3];
+    let c = c[0] / c[3];
+
+    // Substitute x = y - A/3 to remove the quadratic term, y³ + 3py + 2q
+    let sq_a = a * a;
+    let p = (-sq_a / 3.0 + b) / 3.0;
+    let q = (2.0 / 27.0 * a * sq_a - a * b / 3.0 + c) / 2.0;
+    let cb_p = p * p * p;
+    let discriminant = q * q + cb_p;
+
+    let roots = if is_zero(discriminant) {
+        if is_zero(q) {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if discriminant < 0.0 {
+        // Three real roots
+        let phi = (-q / (-cb_p).sqrt()).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![t * phi.cos(), -t * (phi + PI / 3.0).cos(), -t * (phi - PI / 3.0).cos()]
+    } else {
+        let sqrt_d = discriminant.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+
+    roots.into_iter().map(|y| y - a / 3.0).collect()
+}
+
+/// Returns the real roots of c[0] + c[1]x + c[2]x² + c[3]x³ + c[4]x⁴ using Ferrari's method
+fn solve_quartic(c: &[f64; 5]) -> Vec<f64> {
+    // Normal form x⁴ + Ax³ + Bx² + Cx + D
+    let a = c[3] / c[4];
+    let b = c[2] / c[4];
+    let c_ = c[1] / c[4];
+    let d = c[0] / c[4];
+
+    // Substitute x = y - A/4 to remove the cubic term, y⁴ + py² + qy + r
+    let sq_a = a * a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = sq_a * a / 8.0 - a * b / 2.0 + c_;
+    let r = -3.0 / 256.0 * sq_a * sq_a + sq_a * b / 16.0 - a * c_ / 4.0 + d;
+
+    let roots = if is_zero(r) {
+        // No absolute term, y(y³ + py + q) = 0
+        let mut roots = solve_cubic(&[q, p, 0.0, 1.0]);
+        roots.push(0.0);
+        roots
+    } else {
+        // Solve the resolvent cubic and use one of its roots to split into two quadratics
+        let z = solve_cubic(&[r * p / 2.0 - q * q / 8.0, -r, -p / 2.0, 1.0])[0];
+
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        let u = if is_zero(u) { 0.0 } else if u > 0.0 { u.sqrt() } else { return vec![] };
+        let v = if is_zero(v) { 0.0 } else if v > 0.0 { v.sqrt() } else { return vec![] };
+
+        let mut roots = solve_quadratic(&[z - u, if q < 0.0 { -v } else { v }, 1.0]);
+        roots.append(&mut solve_quadratic(&[z + u, if q < 0.0 { v } else { -v }, 1.0]));
+        roots
+    };
+
+    roots.into_iter().map(|y| y - a / 4.0).collect()
+}
+
+/// Refines a root of the polynomial with a few steps of Newton's method
+fn polish_root(c: &[f64; 5], root: f64) -> f64 {
+    let mut x = root;
+    for _ in 0..2 {
+        let f = (((c[4] * x + c[3]) * x + c[2]) * x + c[1]) * x + c[0];
+        let df = ((4.0 * c[4] * x + 3.0 * c[3]) * x + 2.0 * c[2]) * x + c[1];
+        if df.abs() < EQUATION_EPSILON {
+            break;
+        }
+        x -= f / df;
+    }
+    x
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::float::Float;
+    use crate::transformation;
+    use crate::shape;
+
+    #[test]
+    fn torus_creation() {
+        let mut shape_list = ShapeList::new();
+        let t = Torus::new(&mut shape_list);
+        assert_eq!(t.major_radius, 1.0);
+        assert_eq!(t.minor_radius, 0.25);
+        assert_eq!(t.transform, Matrix4::identity());
+        assert_eq!(shape_list.get(t.id).shape_type(), "torus");
+
+        let t = Torus::new_with_radii(2.0, 0.5, &mut shape_list);
+        assert_eq!(t.major_radius, 2.0);
+        assert_eq!(t.minor_radius, 0.5);
+    }
+
+    #[test]
+    fn torus_ray_misses() {
+        let mut shape_list = ShapeList::new();
+        let t = Torus::new(&mut shape_list);
+        let examples = vec![
+            // Through the hole
+            (point(0.0, 5.0, 0.0), vector(0.0, -1.0, 0.0)),
+            // Above the tube
+            (point(1.0, 0.5, -5.0), vector(0.0, 0.0, 1.0)),
+            // Outside of the bounding sphere
+            (point(2.0, 0.0, -5.0), vector(0.0, 0.0, 1.0)),
+            // Inside of the bounding sphere but outside of the tube
+            (point(1.24, 0.0, -5.0), vector(0.0, 0.2, 1.0)),
+        ];
+        for (origin, direction) in examples {
+            let r = Ray::new(origin, direction.normalize());
+            assert!(t.intersects(&r, &mut shape_list).is_empty(), "{:?} {:?}", origin, direction);
+        }
+    }
+
+    #[test]
+    fn torus_ray_tangent() {
+        let mut shape_list = ShapeList::new();
+        let t = Torus::new(&mut shape_list);
+
+        // Grazing the outer edge
+        let r = Ray::new(point(1.25, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let xs = t.intersects(&r, &mut shape_list);
+        assert!(!xs.is_empty());
+        assert!(xs.iter().all(|x| x.t == Float(5.0)));
+
+        // Grazing the top of the tube on both sides of the hole
+        let r = Ray::new(point(0.0, 0.25, -5.0), vector(0.0, 0.0, 1.0));
+        let xs = t.intersects(&r, &mut shape_list);
+        assert!(!xs.is_empty());
+        assert!(xs.iter().all(|x| x.t == Float(4.0) || x.t == Float(6.0)));
+        assert!(xs.iter().any(|x| x.t == Float(4.0)) && xs.iter().any(|x| x.t == Float(6.0)));
+    }
+
+    #[test]
+    fn torus_ray_hits() {
+        let mut shape_list = ShapeList::new();
+        let t = Torus::new(&mut shape_list);
+
+        // Through the tube once
+        let r = Ray::new(point(1.0, 5.0, 0.0), vector(0.0, -1.0, 0.0));
+        let xs = t.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.75);
+        assert_eq!(xs[1].t, 5.25);
+
+        // Through both sides of the ring
+        let r = Ray::new(point(-5.0, 0.0, 0.0), vector(1.0, 0.0, 0.0));
+        let xs = t.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 4);
+        assert_eq!(xs[0].t, 3.75);
+        assert_eq!(xs[1].t, 4.25);
+        assert_eq!(xs[2].t, 5.75);
+        assert_eq!(xs[3].t, 6.25);
+
+        // Transformed torus
+        let mut t = Torus::new_with_radii(2.0, 0.5, &mut shape_list);
+        t.set_transform(transformation::translation(0.0, 1.0, 0.0) * transformation::rotation_x(PI / 2.0), &mut shape_list);
+        let r = Ray::new(point(2.0, 1.0, -5.0), vector(0.0, 0.0, 1.0));
+        let xs = t.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.5);
+        assert_eq!(xs[1].t, 5.5);
+    }
+
+    #[test]
+    fn torus_normal_at() {
+        let mut shape_list = ShapeList::new();
+        let t = Torus::new(&mut shape_list);
+        assert_eq!(t.normal_at(&point(1.25, 0.0, 0.0)), vector(1.0, 0.0, 0.0));
+        assert_eq!(t.normal_at(&point(0.75, 0.0, 0.0)), vector(-1.0, 0.0, 0.0));
+        assert_eq!(t.normal_at(&point(0.0, 0.25, 1.0)), vector(0.0, 1.0, 0.0));
+        assert_eq!(t.normal_at(&point(0.0, -0.25, -1.0)), vector(0.0, -1.0, 0.0));
+        let n = t.normal_at(&point(1.0 + 0.25 * 0.6, 0.25 * 0.8, 0.0));
+        assert_eq!(n, vector(0.6, 0.8, 0.0));
+
+        let mut t = Torus::new(&mut shape_list);
+        t.set_transform(transformation::rotation_x(PI / 2.0), &mut shape_list);
+        let n = shape::normal_at(Box::new(t), point(0.0, -1.0, 0.25), &mut shape_list);
+        assert_eq!(n, vector(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn torus_contains_point() {
+        let mut shape_list = ShapeList::new();
+        let t = Torus::new(&mut shape_list);
+        assert!(t.contains_point(point(1.0, 0.0, 0.0), &mut shape_list));
+        assert!(t.contains_point(point(0.0, 0.2, -1.0), &mut shape_list));
+        assert!(!t.contains_point(point(0.0, 0.0, 0.0), &mut shape_list));
+        assert!(!t.contains_point(point(1.0, 0.3, 0.0), &mut shape_list));
+    }
+}
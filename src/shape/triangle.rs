@@ -9,10 +9,11 @@ use std::any::Any;
 use std::fmt::{Formatter, Error};
 use crate::ray::Ray;
 use crate::intersection::Intersection;
-use crate::tuple::{Tuple};
+use crate::tuple::{Tuple, point};
 use crate::float::Float;
 use crate::shape::shape_list::ShapeList;
 use crate::normal_perturber::NormalPerturber;
+use crate::shape::group::Group;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Triangle {
@@ -20,6 +21,8 @@ pub struct Triangle {
     pub shape_type: String,
     pub parent_id: Option<i32>,
     pub transform: Matrix4,
+    /// Placement of this copy of the shape, applied after `transform`
+    pub instance_transform: Matrix4,
     pub material: Material,
 
     // 3 points
@@ -32,6 +35,14 @@ pub struct Triangle {
     pub e2: Tuple,
 
     pub normal: Tuple,
+
+    /// Ignore rays hitting the side facing away from the normal
+    pub cull_back_face: bool,
+
+    /// Interpolate the parent group's vertex normals instead of using the flat normal
+    pub smooth: bool,
+    /// OBJ indices of the 3 points, used to look up the group's vertex normals
+    pub vertex_indices: Option<[usize; 3]>,
 }
 
 impl Triangle {
@@ -39,8 +50,8 @@ impl Triangle {
         let id = shape_list.get_id();
         let e1 = p2 - p1;
         let e2 = p3 - p1;
-        let shape = Triangle {id, shape_type: String::from("triangle"), parent_id: None, transform: Matrix4::identity(), material: Material::new(),
-            p1, p2, p3, e1, e2, normal: tuple::cross(&e2, &e1).normalize()};
+        let shape = Triangle {id, shape_type: String::from("triangle"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material: Material::new(),
+            p1, p2, p3, e1, e2, normal: tuple::cross(&e2, &e1).normalize(), cull_back_face: false, smooth: false, vertex_indices: None};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
@@ -49,11 +60,107 @@ impl Triangle {
         let id = shape_list.get_id();
         let e1 = p2 - p1;
         let e2 = p3 - p1;
-        let shape = Triangle {id, shape_type: String::from("triangle"), parent_id: None, transform: Matrix4::identity(), material,
-            p1, p2, p3, e1, e2, normal: tuple::cross(&e2, &e1).normalize()};
+        let shape = Triangle {id, shape_type: String::from("triangle"), parent_id: None, transform: Matrix4::identity(), instance_transform: Matrix4::identity(), material,
+            p1, p2, p3, e1, e2, normal: tuple::cross(&e2, &e1).normalize(), cull_back_face: false, smooth: false, vertex_indices: None};
         shape_list.push(Box::new(shape.clone()));
         shape
     }
+
+    /// Smooth shades the triangle with the vertex normals of its group at `vertex_indices`
+    pub fn set_smooth(&mut self, vertex_indices: [usize; 3], shape_list: &mut ShapeList) {
+        self.smooth = true;
+        self.vertex_indices = Some(vertex_indices);
+        let stored = shape_list.get_mut_as::<Self>(self.id());
+        stored.smooth = true;
+        stored.vertex_indices = self.vertex_indices;
+    }
+
+    /// Returns the normal interpolated from the parent group's vertex normals,
+    /// None if the triangle isn't smooth or the group has no vertex normals
+    fn smooth_normal(&self, point: &Tuple, shape_list: &ShapeList) -> Option<Tuple> {
+        let indices = self.vertex_indices.filter(|_| self.smooth)?;
+        let parent = &shape_list[self.parent_id? as usize];
+        let group = parent.as_any().downcast_ref::<Group>().filter(|group| !group.vertex_normals.is_empty())?;
+        let (u, v) = barycentric(point, self.p1, self.e1, self.e2);
+
+        let normal = group.vertex_normal(indices[1]) * u +
+            group.vertex_normal(indices[2]) * v +
+            group.vertex_normal(indices[0]) * (1.0 - u - v);
+        Some(normal.normalize())
+    }
+
+    pub fn set_cull_back_face(&mut self, cull_back_face: bool, shape_list: &mut ShapeList) {
+        self.cull_back_face = cull_back_face;
+        shape_list.get_mut_as::<Self>(self.id()).cull_back_face = self.cull_back_face;
+    }
+}
+
+/// Returns the (t, u, v) where a ray hits the triangle at `p1` with edges `e1` and `e2`
+/// using the Möller–Trumbore algorithm, None if it misses
+///
+/// Shared by `Triangle` and `SmoothTriangle`, `normal` is the unit face normal
+pub fn intersect(ray: &Ray, p1: Tuple, e1: Tuple, e2: Tuple, normal: Tuple) -> Option<(f64, f64, f64)> {
+    // Reject rays parallel to the triangle's plane, the unit normal keeps this
+    // independent of the triangle's size unlike a threshold on the determinant
+    let t = ray.hits_plane(normal, tuple::dot(&normal, &(p1 - point(0.0, 0.0, 0.0))))?;
+
+    let dir_cross_e2 = tuple::cross(&ray.direction, &e2);
+    let det = tuple::dot(&e1, &dir_cross_e2);
+    let f = 1.0 / det;
+    let p1_to_origin = ray.origin - p1;
+    let u = f * tuple::dot(&p1_to_origin, &dir_cross_e2);
+    if Float(u) < Float(0.0) || Float(u) > Float(1.0) {
+        return None // miss the edge p1-p3
+    }
+
+    let origin_cross_e1 = tuple::cross(&p1_to_origin, &e1);
+    let v = f * tuple::dot(&ray.direction, &origin_cross_e1);
+    if Float(v) < Float(0.0) || Float(u + v) > Float(1.0) {
+        return None // miss the edge p2-p3
+    }
+    Some((t, u, v))
+}
+
+/// Returns the barycentric coordinates of a point on the triangle at `p1`, u along `e1` and v along `e2`
+pub fn barycentric(point: &Tuple, p1: Tuple, e1: Tuple, e2: Tuple) -> (f64, f64) {
+    let p1_to_point = point - p1;
+    let d00 = tuple::dot(&e1, &e1);
+    let d01 = tuple::dot(&e1, &e2);
+    let d11 = tuple::dot(&e2, &e2);
+    let d20 = tuple::dot(&p1_to_point, &e1);
+    let d21 = tuple::dot(&p1_to_point, &e2);
+    let denom = d00 * d11 - d01 * d01;
+    ((d11 * d20 - d01 * d21) / denom, (d00 * d21 - d01 * d20) / denom)
+}
+
+/// Returns the normal bumped by the material's normal perturbation, if it has one
+pub fn perturb(material: &Material, normal: Tuple, point: &Tuple) -> Tuple {
+    match &material.normal_perturb {
+        Some(normal_perturb) => normal + NormalPerturber::perturb_normal(normal_perturb.clone(), point,
+                                                                          material.normal_perturb_factor, material.normal_perturb_perlin.clone()),
+        None => normal,
+    }
+}
+
+/// Returns the center and radius of the smallest sphere enclosing the 3 points
+pub fn bounding_sphere(p1: Tuple, p2: Tuple, p3: Tuple) -> (Tuple, f64) {
+    // Unless the triangle is acute the longest edge is the sphere's diameter
+    let edges = [(p1, p2, p3), (p2, p3, p1), (p3, p1, p2)];
+    let (a, b, c) = edges.iter()
+        .max_by(|x, y| (x.1 - x.0).magnitude().partial_cmp(&(y.1 - y.0).magnitude()).unwrap())
+        .unwrap();
+    let center = (a + b) * 0.5;
+    let radius = (b - a).magnitude() / 2.0;
+    if (c - center).magnitude() <= radius + FLOAT_THRESHOLD {
+        return (center, radius);
+    }
+
+    // Otherwise the sphere passes through all 3 points
+    let (ab, ac) = (p2 - p1, p3 - p1);
+    let normal = tuple::cross(&ab, &ac);
+    let offset = (tuple::cross(&normal, &ab) * tuple::dot(&ac, &ac) + tuple::cross(&ac, &normal) * tuple::dot(&ab, &ab))
+        / (2.0 * tuple::dot(&normal, &normal));
+    (p1 + offset, offset.magnitude())
 }
 
 impl Shape for Triangle {
@@ -61,6 +168,10 @@ impl Shape for Triangle {
         self
     }
 
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
     fn as_shape(&self) -> Box<&dyn Shape> {
         Box::new(self)
     }
@@ -70,17 +181,27 @@ impl Shape for Triangle {
     }
 
     fn debug_fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        write!(f, "Box {:?}", self)
+        write!(f, "{}", self.shape_name())
     }
 
     fn shape_clone(&self) -> Box<dyn Shape + Send> {
         Box::new(self.clone())
     }
 
+    fn clone_with_material(&self, material: Material) -> Box<dyn Shape + Send> {
+        let mut shape = self.clone();
+        shape.material = material;
+        Box::new(shape)
+    }
+
     fn id(&self) -> i32 {
         self.id
     }
 
+    fn parent_id(&self) -> Option<i32> {
+        self.parent_id
+    }
+
     fn shape_type(&self) -> String {
         self.shape_type.clone()
     }
@@ -99,16 +220,25 @@ impl Shape for Triangle {
 
     fn set_parent(&mut self, parent_id: i32, shape_list: &mut ShapeList) {
         self.parent_id = Some(parent_id);
-        shape_list.update(Box::new(self.clone()));
+        shape_list.get_mut_as::<Self>(self.id()).parent_id = self.parent_id;
     }
 
     fn transform(&self) -> Matrix4 {
-        self.transform
+        self.instance_transform * self.transform
     }
 
     fn set_transform(&mut self, transform: Matrix4, shape_list: &mut ShapeList) {
         self.transform = transform;
-        shape_list.update(Box::new(self.clone()))
+        shape_list.get_mut_as::<Self>(self.id()).transform = self.transform;
+    }
+
+    fn instance_transform(&self) -> Matrix4 {
+        self.instance_transform
+    }
+
+    fn set_instance_transform(&mut self, instance_transform: Matrix4, shape_list: &mut ShapeList) {
+        self.instance_transform = instance_transform;
+        shape_list.get_mut_as::<Self>(self.id()).instance_transform = self.instance_transform;
     }
 
     fn material(&self) -> Material {
@@ -117,44 +247,43 @@ impl Shape for Triangle {
 
     fn set_material(&mut self, material: Material, shape_list: &mut ShapeList) {
         self.material = material;
-        shape_list.update(Box::new(self.clone()))
+        shape_list.get_mut_as::<Self>(self.id()).material = self.material.clone();
+    }
+
+    /// Barycentric (u, v), the same as an intersection's
+    fn uv_at(&self, object_point: &Tuple) -> (f64, f64) {
+        barycentric(object_point, self.p1, self.e1, self.e2)
+    }
+
+    fn bounding_sphere_center(&self) -> Tuple {
+        bounding_sphere(self.p1, self.p2, self.p3).0
+    }
+
+    fn bounding_sphere_radius(&self) -> f64 {
+        bounding_sphere(self.p1, self.p2, self.p3).1
     }
 
     fn intersects(&self, ray: &Ray, _shape_list: &mut ShapeList) -> Vec<Intersection<Box<dyn Shape + Send>>> {
         // Transform the ray
-        let t_ray = ray.transform(&self.transform.inverse());
+        let t_ray = ray.transform(&self.transform().inverse());
 
-        let dir_cross_e2 = tuple::cross(&t_ray.direction, &self.e2);
-        let det = tuple::dot(&self.e1, &dir_cross_e2);
-        if Float(det.abs()) < Float(FLOAT_THRESHOLD) {
+        if self.cull_back_face && tuple::dot(&t_ray.direction, &self.normal) > 0.0 {
             return vec![]
         }
 
-        let f = 1.0 / det;
-        let p1_to_origin = t_ray.origin - self.p1;
-        let u = f * tuple::dot(&p1_to_origin, &dir_cross_e2);
-        if Float(u) < Float(0.0) || Float(u) > Float(1.0) {
-            return vec![] // miss the edge p1-p3
+        match intersect(&t_ray, self.p1, self.e1, self.e2, self.normal) {
+            Some((t, u, v)) => vec![Intersection::new_with_uv(t, Box::new(self.clone()), u, v)],
+            None => vec![],
         }
-
-        let origin_cross_e1 = tuple::cross(&p1_to_origin, &self.e1);
-        let v = f * tuple::dot(&t_ray.direction, &origin_cross_e1);
-        if Float(v) < Float(0.0) || Float(u + v) > Float(1.0) {
-            return vec![] // miss the edge p2-p3
-        }
-
-        let t= f * tuple::dot(&self.e2, &origin_cross_e1);
-        return vec![Intersection::new(t, Box::new(self.clone()))]
     }
 
     fn normal_at(&self, point: &Tuple) -> Tuple {
-        let mut normal = self.normal;
-        if self.material.normal_perturb.is_some() {
-            let perturb = NormalPerturber::perturb_normal(self.material.clone().normal_perturb.unwrap(),
-                                                          point, self.material.clone().normal_perturb_factor, self.material.clone().normal_perturb_perlin);
-            normal = normal + perturb;
-        }
-        normal
+        perturb(&self.material, self.normal, point)
+    }
+
+    fn local_normal_at(&self, point: &Tuple, shape_list: &ShapeList) -> Tuple {
+        let normal = self.smooth_normal(point, shape_list).unwrap_or(self.normal);
+        perturb(&self.material, normal, point)
     }
 }
 
@@ -191,6 +320,27 @@ mod tests {
         assert_eq!(n3, t.normal);
     }
 
+    #[test]
+    fn triangle_smooth_normal() {
+        let mut shape_list = ShapeList::new();
+        let mut g = Group::new(&mut shape_list);
+        let mut t = Triangle::new(point(0.0, 1.0, 0.0), point(-1.0, 0.0, 0.0), point(1.0, 0.0, 0.0), &mut shape_list);
+        t.set_smooth([1, 2, 3], &mut shape_list);
+        let mut tb: Box<dyn Shape + Send> = Box::new(t.clone());
+        g.add_child(&mut tb, &mut shape_list);
+
+        // Flat until the group has vertex normals
+        assert_eq!(crate::shape::normal_at(tb.clone(), point(0.0, 0.5, 0.0), &mut shape_list), t.normal);
+
+        g.set_vertex_normals(vec![vector(0.0, 1.0, 0.0), vector(-1.0, 0.0, 0.0), vector(1.0, 0.0, 0.0)], &mut shape_list);
+        assert_eq!(g.vertex_normal(2), vector(-1.0, 0.0, 0.0));
+        let n = |p: Tuple, shape_list: &mut ShapeList| crate::shape::normal_at(tb.clone(), p, shape_list);
+        assert_eq!(n(point(0.0, 1.0, 0.0), &mut shape_list), vector(0.0, 1.0, 0.0));
+        assert_eq!(n(point(-1.0, 0.0, 0.0), &mut shape_list), vector(-1.0, 0.0, 0.0));
+        assert_eq!(n(point(0.0, 1.0 / 3.0, 0.0), &mut shape_list), vector(0.0, 1.0, 0.0));
+        assert_eq!(n(point(-0.5, 0.5, 0.0), &mut shape_list), vector(-1.0, 1.0, 0.0).normalize());
+    }
+
     #[test]
     fn triangle_intersects() {
         let mut shape_list = ShapeList::new();
@@ -227,4 +377,69 @@ mod tests {
         assert_eq!(xs.len(), 1);
         assert_eq!(xs[0].t, 2.0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn triangle_intersects_uv() {
+        let mut shape_list = ShapeList::new();
+        let t = Triangle::new(point(0.0, 1.0, 0.0), point(-1.0, 0.0, 0.0), point(1.0, 0.0, 0.0), &mut shape_list);
+        let r = Ray::new(point(-0.2, 0.3, -2.0), vector(0.0, 0.0, 1.0));
+        let xs = t.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].u, 0.45);
+        assert_eq!(xs[0].v, 0.25);
+    }
+
+    #[test]
+    fn triangle_parallel_rejection() {
+        let mut shape_list = ShapeList::new();
+        // Steep rays still hit tiny triangles, the determinant is below the threshold here
+        let tiny = Triangle::new(point(0.0, 0.004, 0.0), point(-0.004, 0.0, 0.0), point(0.004, 0.0, 0.0), &mut shape_list);
+        let direction = vector(0.96f64.sqrt(), 0.0, 0.2);
+        let r = Ray::new(point(0.0, 0.001, 0.0) - direction * 2.0, direction);
+        let xs = tiny.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 2.0);
+
+        // Rays grazing large triangles closer than the threshold are treated as parallel
+        let large = Triangle::new(point(0.0, 100.0, 0.0), point(-100.0, 0.0, 0.0), point(100.0, 0.0, 0.0), &mut shape_list);
+        let r = Ray::new(point(-50.0, 10.0, -0.0001), vector(1.0, 0.0, 0.000005).normalize());
+        assert!(large.intersects(&r, &mut shape_list).is_empty());
+        let r = Ray::new(point(-50.0, 10.0, -0.0001), vector(1.0, 0.0, 0.0001).normalize());
+        assert_eq!(large.intersects(&r, &mut shape_list).len(), 1);
+    }
+
+    #[test]
+    fn triangle_cull_back_face() {
+        let mut shape_list = ShapeList::new();
+        let mut t = Triangle::new(point(0.0, 1.0, 0.0), point(-1.0, 0.0, 0.0), point(1.0, 0.0, 0.0), &mut shape_list);
+        let front = Ray::new(point(0.0, 0.5, -2.0), vector(0.0, 0.0, 1.0));
+        let back = Ray::new(point(0.0, 0.5, 2.0), vector(0.0, 0.0, -1.0));
+        assert_eq!(t.intersects(&back, &mut shape_list).len(), 1);
+
+        t.set_cull_back_face(true, &mut shape_list);
+        assert_eq!(t.intersects(&front, &mut shape_list).len(), 1);
+        assert_eq!(t.intersects(&back, &mut shape_list).len(), 0);
+        assert_eq!(shape_list.get(t.id).intersects(&back, &mut shape_list).len(), 0);
+    }
+
+    #[test]
+    fn triangle_bounding_sphere() {
+        // Acute, the sphere passes through every point
+        let (center, radius) = bounding_sphere(point(0.0, 2.0, 0.0), point(-1.0, 0.0, 0.0), point(1.0, 0.0, 0.0));
+        assert_eq!(center, point(0.0, 0.75, 0.0));
+        assert_eq!(Float(radius), Float(1.25));
+
+        // Right and obtuse, the longest edge is the diameter
+        let (center, radius) = bounding_sphere(point(0.0, 0.0, 0.0), point(2.0, 0.0, 0.0), point(0.0, 2.0, 0.0));
+        assert_eq!(center, point(1.0, 1.0, 0.0));
+        assert_eq!(Float(radius), Float(2.0_f64.sqrt()));
+        let (center, radius) = bounding_sphere(point(-2.0, 0.0, 0.0), point(0.0, 0.5, 1.0), point(2.0, 0.0, 0.0));
+        assert_eq!(center, point(0.0, 0.0, 0.0));
+        assert_eq!(Float(radius), Float(2.0));
+
+        let mut shape_list = ShapeList::new();
+        let t = Triangle::new(point(0.0, 2.0, 0.0), point(-1.0, 0.0, 0.0), point(1.0, 0.0, 0.0), &mut shape_list);
+        assert_eq!(t.bounding_sphere_center(), point(0.0, 0.75, 0.0));
+        assert_eq!(Float(t.bounding_sphere_radius()), Float(1.25));
+    }
+}
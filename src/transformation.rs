@@ -5,6 +5,7 @@ use super::float::Float;
 use super::matrix::Matrix4;
 use crate::tuple::Tuple;
 use crate::tuple;
+use std::io::{Error, ErrorKind};
 
 
 /// Returns a 4x4 matrix used to translate either a tuple or matrix
@@ -57,6 +58,18 @@ pub fn rotation_z(radians: f64) -> Matrix4 {
     new_mat
 }
 
+/// Returns a 4x4 matrix rotating by Euler angles in radians,
+/// `rotation_x(rx) * rotation_y(ry) * rotation_z(rz)` so the z rotation is applied first
+pub fn from_euler_xyz(rx: f64, ry: f64, rz: f64) -> Matrix4 {
+    rotation_x(rx) * rotation_y(ry) * rotation_z(rz)
+}
+
+/// Returns a 4x4 matrix rotating by Euler angles in radians,
+/// `rotation_z(rz) * rotation_y(ry) * rotation_x(rx)` so the x rotation is applied first
+pub fn from_euler_zyx(rx: f64, ry: f64, rz: f64) -> Matrix4 {
+    rotation_z(rz) * rotation_y(ry) * rotation_x(rx)
+}
+
 /// Returns a 4x4 matrix used in shearing
 ///
 /// x_y denotes "x moved in proportion to y"
@@ -91,6 +104,213 @@ pub fn view_transform(from: Tuple, to: Tuple, up: Tuple) -> Matrix4 {
     orientation * translation(-from.x.value(), -from.y.value(), -from.z.value())
 }
 
+/// Returns a 4x4 matrix that translates and scales the box between
+/// `shape_min` and `shape_max` to exactly fill the box between `target_min` and `target_max`
+///
+/// Each axis is scaled independently, an axis where the shape is flat is only centered
+pub fn scale_to_fit(shape_min: Tuple, shape_max: Tuple, target_min: Tuple, target_max: Tuple) -> Matrix4 {
+    let shape_center = (shape_min + shape_max) / 2.0;
+    let target_center = (target_min + target_max) / 2.0;
+    let shape_size = shape_max - shape_min;
+    let target_size = target_max - target_min;
+
+    let scale = |shape: Float, target: Float| {
+        if shape == Float(0.0) { 1.0 } else { (target / shape).value() }
+    };
+
+    translation(target_center.x.value(), target_center.y.value(), target_center.z.value()) *
+        scaling(scale(shape_size.x, target_size.x), scale(shape_size.y, target_size.y), scale(shape_size.z, target_size.z)) *
+        translation(-shape_center.x.value(), -shape_center.y.value(), -shape_center.z.value())
+}
+
+/// Returns a 4x4 matrix that uniformly scales the box between `current_min` and `current_max`
+/// to the largest size that fits inside the box between `target_min` and `target_max`, centered in it
+///
+/// Unlike `scale_to_fit` the proportions are kept, useful for bringing imported models to a common size
+pub fn fit_in_box(current_min: Tuple, current_max: Tuple, target_min: Tuple, target_max: Tuple) -> Matrix4 {
+    let current_center = (current_min + current_max) / 2.0;
+    let target_center = (target_min + target_max) / 2.0;
+    let current_size = current_max - current_min;
+    let target_size = target_max - target_min;
+
+    // The tightest axis limits the scale, flat axes don't constrain it
+    let scale = [(current_size.x, target_size.x), (current_size.y, target_size.y), (current_size.z, target_size.z)].iter()
+        .filter(|(current, _)| *current != Float(0.0))
+        .map(|(current, target)| (target / current).value())
+        .fold(f64::INFINITY, f64::min);
+    let scale = if scale.is_finite() { scale } else { 1.0 };
+
+    translation(target_center.x.value(), target_center.y.value(), target_center.z.value()) *
+        scaling(scale, scale, scale) *
+        translation(-current_center.x.value(), -current_center.y.value(), -current_center.z.value())
+}
+
+/// Returns a YAML flow mapping describing the transformation, e.g. `{type: rotation_y, angle: 1.57}`
+///
+/// The simplest transform type that reproduces the matrix is used,
+/// falling back to `{type: matrix, values: [...]}` with the 16 values in row major order
+pub fn to_yaml(m: Matrix4) -> String {
+    let v = |row: usize, col: usize| m[row][col].value();
+
+    if m == Matrix4::identity() {
+        return String::from("{type: identity}");
+    }
+    if translation(v(0, 3), v(1, 3), v(2, 3)) == m {
+        return format!("{{type: translation, x: {}, y: {}, z: {}}}", v(0, 3), v(1, 3), v(2, 3));
+    }
+    if scaling(v(0, 0), v(1, 1), v(2, 2)) == m {
+        return format!("{{type: scaling, x: {}, y: {}, z: {}}}", v(0, 0), v(1, 1), v(2, 2));
+    }
+
+    let rotations = [
+        ("rotation_x", rotation_x as fn(f64) -> Matrix4, v(2, 1).atan2(v(1, 1))),
+        ("rotation_y", rotation_y, v(0, 2).atan2(v(0, 0))),
+        ("rotation_z", rotation_z, v(1, 0).atan2(v(0, 0))),
+    ];
+    for (name, rotation, angle) in rotations.iter() {
+        if rotation(*angle) == m {
+            return format!("{{type: {}, angle: {}}}", name, angle);
+        }
+    }
+
+    if shearing(v(0, 1), v(0, 2), v(1, 0), v(1, 2), v(2, 0), v(2, 1)) == m {
+        return format!("{{type: shearing, x_y: {}, x_z: {}, y_x: {}, y_z: {}, z_x: {}, z_y: {}}}",
+                       v(0, 1), v(0, 2), v(1, 0), v(1, 2), v(2, 0), v(2, 1));
+    }
+
+    let values: Vec<String> = (0..16).map(|i| v(i / 4, i % 4).to_string()).collect();
+    format!("{{type: matrix, values: [{}]}}", values.join(", "))
+}
+
+/// Parses a YAML flow mapping written by `to_yaml` back into a matrix
+pub fn from_yaml(yaml: &str) -> Result<Matrix4, Error> {
+    let invalid = |msg: String| Error::new(ErrorKind::InvalidData, msg);
+
+    let yaml = yaml.trim();
+    if !yaml.starts_with('{') || !yaml.ends_with('}') {
+        return Err(invalid(format!("Expected a mapping surrounded by braces: {}", yaml)));
+    }
+
+    // Split the mapping into key value pairs, ignoring commas inside of lists
+    let mut entries = vec![];
+    let mut depth = 0;
+    let mut start = 1;
+    for (i, c) in yaml.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ',' if depth == 0 => {
+                entries.push(&yaml[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    entries.push(&yaml[start..yaml.len() - 1]);
+
+    let mut pairs = vec![];
+    for entry in entries.iter().filter(|e| !e.trim().is_empty()) {
+        match entry.find(':') {
+            Some(i) => pairs.push((entry[..i].trim(), entry[i + 1..].trim())),
+            None => return Err(invalid(format!("Expected a key value pair: {}", entry.trim()))),
+        }
+    }
+
+    let value = |key: &str| -> Result<&str, Error> {
+        pairs.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+            .ok_or_else(|| invalid(format!("Missing key: {}", key)))
+    };
+    let number = |key: &str| -> Result<f64, Error> {
+        let v = value(key)?;
+        v.parse::<f64>().map_err(|_| invalid(format!("Expected a number for {}: {}", key, v)))
+    };
+
+    match value("type")? {
+        "identity" => Ok(Matrix4::identity()),
+        "translation" => Ok(translation(number("x")?, number("y")?, number("z")?)),
+        "scaling" => Ok(scaling(number("x")?, number("y")?, number("z")?)),
+        "rotation_x" => Ok(rotation_x(number("angle")?)),
+        "rotation_y" => Ok(rotation_y(number("angle")?)),
+        "rotation_z" => Ok(rotation_z(number("angle")?)),
+        "shearing" => Ok(shearing(number("x_y")?, number("x_z")?, number("y_x")?,
+                                  number("y_z")?, number("z_x")?, number("z_y")?)),
+        "matrix" => {
+            let list = value("values")?;
+            if !list.starts_with('[') || !list.ends_with(']') {
+                return Err(invalid(format!("Expected a list of values: {}", list)));
+            }
+            let values = list[1..list.len() - 1].split(',')
+                .map(|n| n.trim().parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|_| invalid(format!("Expected a list of numbers: {}", list)))?;
+            if values.len() != 16 {
+                return Err(invalid(format!("Expected 16 values but found {}", values.len())));
+            }
+
+            let mut mat = [[0.0; 4]; 4];
+            for (i, value) in values.iter().enumerate() {
+                mat[i / 4][i % 4] = *value;
+            }
+            Ok(Matrix4::new(mat))
+        },
+        other => Err(invalid(format!("Unknown transformation type: {}", other))),
+    }
+}
+
+/// Chains transformations in the order they would be written as a product,
+/// `TransformBuilder::new().translate(1.0, 0.0, 0.0).scale(2.0, 2.0, 2.0).build()`
+/// is `translation(1.0, 0.0, 0.0) * scaling(2.0, 2.0, 2.0)` so the last step is applied first
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct TransformBuilder {
+    matrix: Matrix4,
+}
+
+impl TransformBuilder {
+    pub fn new() -> TransformBuilder {
+        TransformBuilder { matrix: Matrix4::identity() }
+    }
+
+    pub fn then(mut self, transform: Matrix4) -> TransformBuilder {
+        self.matrix = self.matrix * transform;
+        self
+    }
+
+    pub fn translate(self, x: f64, y: f64, z: f64) -> TransformBuilder {
+        self.then(translation(x, y, z))
+    }
+
+    pub fn scale(self, x: f64, y: f64, z: f64) -> TransformBuilder {
+        self.then(scaling(x, y, z))
+    }
+
+    pub fn rotate_x(self, radians: f64) -> TransformBuilder {
+        self.then(rotation_x(radians))
+    }
+
+    pub fn rotate_y(self, radians: f64) -> TransformBuilder {
+        self.then(rotation_y(radians))
+    }
+
+    pub fn rotate_z(self, radians: f64) -> TransformBuilder {
+        self.then(rotation_z(radians))
+    }
+
+    /// See `shearing`
+    pub fn shear(self, x_y: f64, x_z: f64, y_x: f64, y_z: f64, z_x: f64, z_y: f64) -> TransformBuilder {
+        self.then(shearing(x_y, x_z, y_x, y_z, z_x, z_y))
+    }
+
+    pub fn build(self) -> Matrix4 {
+        self.matrix
+    }
+}
+
+impl Default for TransformBuilder {
+    fn default() -> Self {
+        TransformBuilder::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -167,6 +387,19 @@ mod tests {
         assert_eq!(full_quarter * &p, point(-1.0, 0.0, 0.0));
     }
 
+    #[test]
+    fn transformation_euler() {
+        assert_eq!(from_euler_xyz(PI/3.0, 0.0, 0.0), rotation_x(PI/3.0));
+        assert_eq!(from_euler_zyx(0.0, PI/3.0, 0.0), rotation_y(PI/3.0));
+        assert_eq!(from_euler_xyz(0.0, 0.0, 0.0), Matrix4::identity());
+        assert_eq!(from_euler_xyz(0.1, 0.2, 0.3), rotation_x(0.1) * rotation_y(0.2) * rotation_z(0.3));
+
+        // The conventions apply the rotations in opposite orders
+        let p = point(0.0, 0.0, 1.0);
+        assert_eq!(from_euler_xyz(PI/2.0, PI/2.0, 0.0) * p, point(1.0, 0.0, 0.0));
+        assert_eq!(from_euler_zyx(PI/2.0, PI/2.0, 0.0) * p, point(0.0, -1.0, 0.0));
+    }
+
     #[test]
     fn transformation_shearing() {
         // A shearing transformation moves x in proportion to y
@@ -225,6 +458,71 @@ mod tests {
         assert_eq!(t * p, point(15.0, 0.0, 7.0));
     }
 
+    #[test]
+    fn transformation_scale_to_fit() {
+        let t = scale_to_fit(point(-2.0, 0.0, 1.0), point(2.0, 10.0, 3.0),
+                             point(-1.0, -1.0, -1.0), point(1.0, 1.0, 1.0));
+        assert_eq!(t * point(-2.0, 0.0, 1.0), point(-1.0, -1.0, -1.0));
+        assert_eq!(t * point(2.0, 10.0, 3.0), point(1.0, 1.0, 1.0));
+        assert_eq!(t * point(0.0, 5.0, 2.0), point(0.0, 0.0, 0.0));
+
+        // A flat shape is centered on the flat axis
+        let t = scale_to_fit(point(0.0, 0.0, 0.0), point(4.0, 0.0, 4.0),
+                             point(0.0, 0.0, 0.0), point(1.0, 1.0, 1.0));
+        assert_eq!(t * point(4.0, 0.0, 4.0), point(1.0, 0.5, 1.0));
+    }
+
+    #[test]
+    fn transformation_fit_in_box() {
+        // The tallest axis fills the target, the others keep their proportions
+        let t = fit_in_box(point(-2.0, 0.0, 1.0), point(2.0, 10.0, 3.0),
+                           point(-1.0, -1.0, -1.0), point(1.0, 1.0, 1.0));
+        assert_eq!(t * point(0.0, 5.0, 2.0), point(0.0, 0.0, 0.0));
+        assert_eq!(t * point(-2.0, 0.0, 1.0), point(-0.4, -1.0, -0.2));
+        assert_eq!(t * point(2.0, 10.0, 3.0), point(0.4, 1.0, 0.2));
+
+        // A flat shape is centered and scaled by its other axes
+        let t = fit_in_box(point(0.0, 0.0, 0.0), point(4.0, 0.0, 2.0),
+                           point(0.0, 0.0, 0.0), point(2.0, 2.0, 2.0));
+        assert_eq!(t * point(4.0, 0.0, 2.0), point(2.0, 1.0, 1.5));
+
+        // A single point is only moved to the center
+        let t = fit_in_box(point(1.0, 1.0, 1.0), point(1.0, 1.0, 1.0),
+                           point(0.0, 0.0, 0.0), point(2.0, 2.0, 2.0));
+        assert_eq!(t, translation(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn transformation_yaml() {
+        assert_eq!(to_yaml(Matrix4::identity()), "{type: identity}");
+        assert_eq!(to_yaml(translation(1.0, -2.0, 3.5)), "{type: translation, x: 1, y: -2, z: 3.5}");
+        assert_eq!(to_yaml(scaling(2.0, 2.0, 0.5)), "{type: scaling, x: 2, y: 2, z: 0.5}");
+        assert_eq!(to_yaml(rotation_y(1.5)), "{type: rotation_y, angle: 1.5}");
+        assert_eq!(to_yaml(shearing(1.0, 0.0, 0.0, 0.0, 0.0, 2.0)),
+                   "{type: shearing, x_y: 1, x_z: 0, y_x: 0, y_z: 0, z_x: 0, z_y: 2}");
+
+        let transforms = vec![
+            Matrix4::identity(),
+            translation(1.0, -2.0, 3.5),
+            scaling(-1.0, 1.0, 1.0),
+            rotation_x(PI/3.0),
+            rotation_y(-PI/4.0),
+            rotation_z(2.0),
+            shearing(0.5, 0.0, 1.0, 0.0, 0.0, 0.25),
+            translation(1.0, 2.0, 3.0) * rotation_x(PI/2.0) * scaling(5.0, 5.0, 5.0),
+        ];
+        for t in transforms {
+            assert_eq!(from_yaml(&to_yaml(t)).unwrap(), t);
+        }
+
+        assert_eq!(from_yaml(" { type : rotation_z , angle : 0.5 } ").unwrap(), rotation_z(0.5));
+        assert!(from_yaml("type: identity").is_err());
+        assert!(from_yaml("{type: rotation_x}").is_err());
+        assert!(from_yaml("{type: translation, x: 1, y: a, z: 3}").is_err());
+        assert!(from_yaml("{type: matrix, values: [1, 2, 3]}").is_err());
+        assert!(from_yaml("{type: skew}").is_err());
+    }
+
     #[test]
     fn transformation_view() {
         // Default view
@@ -260,4 +558,19 @@ mod tests {
              [0.00000, 0.00000, 0.00000, 1.00000]]);
         assert_eq!(t, m);
     }
+
+    #[test]
+    fn transformation_builder() {
+        assert_eq!(TransformBuilder::new().build(), Matrix4::identity());
+
+        let built = TransformBuilder::new().translate(1.0, 0.0, 0.0).rotate_y(PI/4.0).scale(2.0, 2.0, 2.0).build();
+        assert_eq!(built, translation(1.0, 0.0, 0.0) * rotation_y(PI/4.0) * scaling(2.0, 2.0, 2.0));
+
+        let built = TransformBuilder::default().rotate_x(0.5).rotate_z(-1.0).shear(1.0, 0.0, 0.0, 0.0, 0.0, 2.0).build();
+        assert_eq!(built, rotation_x(0.5) * rotation_z(-1.0) * shearing(1.0, 0.0, 0.0, 0.0, 0.0, 2.0));
+
+        // The last step is applied to a point first
+        let p = TransformBuilder::new().translate(10.0, 5.0, 7.0).scale(5.0, 5.0, 5.0).build() * point(1.0, 0.0, 1.0);
+        assert_eq!(p, point(15.0, 5.0, 12.0));
+    }
 }
\ No newline at end of file
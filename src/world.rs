@@ -8,23 +8,89 @@ use crate::material::Material;
 use crate::color::Color;
 use crate::float::Float;
 use crate::{transformation, intersection, tuple};
-use crate::tuple::{point, Tuple};
+use crate::tuple::{point, Tuple, dot};
 use crate::ray::Ray;
-use crate::intersection::{Intersection, PrecomputedData, schlick};
+use crate::intersection::{Intersection, IntersectionResult, PrecomputedData, schlick};
 use crate::shape::shape_list::ShapeList;
+use crate::shape::group::Group;
+use crate::shape::csg::CSG;
+use rand::Rng;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::cell::Cell;
+use std::fmt::{self, Display, Formatter};
 
 const DEFAULT_RAY_BOUNCES: i32 = 4;
+const DEFAULT_PATH_MIN_DEPTH: i32 = 3;
+const AO_SAMPLES: usize = 16;
+const AO_DISTANCE: f64 = 1.0;
+
+thread_local! {
+    /// Internal reflections on the current path traced without using up `remaining`
+    static FREE_INTERNAL_REFLECTIONS: Cell<i32> = const { Cell::new(0) };
+}
+
+/// Function returning the color of the sky seen along a ray direction
+pub type SkyFn = Arc<dyn Fn(Tuple) -> Color + Send + Sync>;
+
+/// How `sample_light` picks a light
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum LightSelection {
+    /// Every light is equally likely
+    Uniform,
+    /// Larger area lights are more likely, point lights count as an average sized area light
+    AreaWeighted,
+}
+
+/// Size of a scene as returned by `World::complexity_report`
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct SceneComplexity {
+    /// Shapes reachable from the world's objects, including groups and CSGs
+    pub total_objects: usize,
+    /// Shapes that are not groups or CSGs
+    pub total_primitives: usize,
+    pub max_group_depth: usize,
+    pub total_lights: usize,
+    /// Nodes of a binary BVH with one primitive per leaf
+    pub estimated_bvh_nodes: usize,
+}
+
+impl Display for SceneComplexity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Objects: {}\nPrimitives: {}\nMax group depth: {}\nLights: {}\nEstimated BVH nodes: {}",
+               self.total_objects, self.total_primitives, self.max_group_depth, self.total_lights, self.estimated_bvh_nodes)
+    }
+}
 
 #[derive(Clone)]
 pub struct World {
     pub objects: Vec<Box<dyn Shape + Send>>,
     pub lights: Vec<Light>,
     pub max_recursion: i32,
+    /// Bounces a path is guaranteed before russian roulette may terminate it
+    pub path_min_depth: i32,
+    /// Reflections off the inside surface of transparent objects use up one of the `remaining`
+    /// recursions, when false up to `max_recursion` of them on a path are traced for free
+    pub trace_internal_reflections: bool,
+    /// Color returned by rays that escape the scene
+    pub sky_fn: SkyFn,
+    /// How much ambient occlusion darkens surfaces, 0.0 turns it off
+    ///
+    /// This darkens the whole lit surface while `ao_samples` only darkens the diffuse light,
+    /// when both are set their effects multiply
+    pub ao_weight: f64,
+    /// Hemisphere rays cast at each hit to darken the diffuse light of occluded surfaces,
+    /// 0 turns it off
+    pub ao_samples: usize,
+    /// Rays traced before new rays only see the background, shared between clones of the world
+    pub photon_budget: usize,
+    photons_traced: Arc<AtomicUsize>,
+    pub light_selection: LightSelection,
 }
 
 impl World {
     pub fn new() -> World {
-        World {objects: vec![], lights: vec![], max_recursion: DEFAULT_RAY_BOUNCES}
+        World {objects: vec![], lights: vec![], max_recursion: DEFAULT_RAY_BOUNCES, path_min_depth: DEFAULT_PATH_MIN_DEPTH, trace_internal_reflections: true, sky_fn: Arc::new(|_| Color::black()), ao_weight: 0.0, ao_samples: 0, photon_budget: usize::MAX, photons_traced: Arc::new(AtomicUsize::new(0)), light_selection: LightSelection::Uniform}
     }
 
     pub fn default_world(shape_list: &mut ShapeList) -> World {
@@ -39,7 +105,153 @@ impl World {
         let mut sphere2 = Sphere::new(shape_list);
         sphere2.set_transform(transformation::scaling(0.5, 0.5, 0.5), shape_list);
 
-        World {objects: vec![Box::new(sphere1), Box::new(sphere2)], lights: vec![light], max_recursion: DEFAULT_RAY_BOUNCES}
+        World {objects: vec![Box::new(sphere1), Box::new(sphere2)], lights: vec![light], max_recursion: DEFAULT_RAY_BOUNCES, path_min_depth: DEFAULT_PATH_MIN_DEPTH, trace_internal_reflections: true, sky_fn: Arc::new(|_| Color::black()), ao_weight: 0.0, ao_samples: 0, photon_budget: usize::MAX, photons_traced: Arc::new(AtomicUsize::new(0)), light_selection: LightSelection::Uniform}
+    }
+
+    /// Replaces the background with a function of the ray direction
+    /// # Arguments
+    /// * `sky_fn` Function returning the sky color for a direction
+    pub fn set_sky_fn(&mut self, sky_fn: SkyFn) {
+        self.sky_fn = sky_fn;
+    }
+
+    /// Replaces the background with a single color in every direction
+    pub fn set_background(&mut self, color: Color) {
+        self.sky_fn = Arc::new(move |_| color);
+    }
+
+    /// Returns the color seen by a ray that doesn't hit anything in the world
+    pub fn ray_miss_color(&self, ray: &Ray) -> Color {
+        (self.sky_fn)(ray.direction)
+    }
+
+    /// Returns a sky blending from the horizon color to the zenith color
+    /// as a ray points further up, the horizon color is used below the horizon
+    /// # Arguments
+    /// * `horizon` Color at the horizon
+    /// * `zenith` Color straight up
+    pub fn horizon_sky(horizon: Color, zenith: Color) -> SkyFn {
+        Arc::new(move |direction: Tuple| {
+            let t = direction.normalize().y.value().max(0.0);
+            horizon * (1.0 - t) + zenith * t
+        })
+    }
+
+    /// Returns a sky blending between colors placed at the given directions,
+    /// each color is weighted by how closely the ray points towards its direction
+    /// # Arguments
+    /// * `colors` Colors of the sky
+    /// * `directions` Direction each color is centered on
+    pub fn gradient_sky(colors: Vec<Color>, directions: Vec<Tuple>) -> SkyFn {
+        let directions: Vec<Tuple> = directions.iter().map(|d| d.normalize()).collect();
+        Arc::new(move |direction: Tuple| {
+            let direction = direction.normalize();
+            let cosines: Vec<f64> = directions.iter().map(|d| dot(&direction, d)).collect();
+
+            let total: f64 = cosines.iter().map(|c| c.max(0.0)).sum();
+            if total > 0.0 {
+                colors.iter().zip(cosines.iter())
+                    .fold(Color::black(), |sum, (color, c)| sum + color * (c.max(0.0) / total))
+            } else {
+                // Facing away from every direction, use the closest one
+                let closest = cosines.iter().enumerate()
+                    .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+                    .map(|(i, _)| i);
+                match closest {
+                    Some(i) => colors[i],
+                    None => Color::black(),
+                }
+            }
+        })
+    }
+
+    /// Prints a summary of the scene's size, see `scene_info`
+    pub fn dump_scene_info(&self, shape_list: &ShapeList) {
+        println!("{}", self.scene_info(shape_list));
+    }
+
+    /// Returns the world's objects that give off light, these can be sampled
+    /// directly as area lights when path tracing
+    ///
+    /// The boxes themselves are borrowed so they can be cloned into intersections
+    #[allow(clippy::borrowed_box)]
+    pub fn collect_emissive_shapes(&self) -> Vec<&Box<dyn Shape + Send>> {
+        self.objects.iter()
+            .filter(|object| !object.material().emitted().is_black())
+            .collect()
+    }
+
+    /// Returns a summary of the scene with the amount of shapes and triangles
+    /// reachable from the world's objects, the amount of lights, the estimated
+    /// memory used by the shape list, and how deeply groups are nested
+    pub fn scene_info(&self, shape_list: &ShapeList) -> String {
+        let mut shapes = 0;
+        let mut triangles = 0;
+        let mut max_depth = 0;
+        for object in self.objects.iter() {
+            let (s, t, _, d) = World::shape_counts(&**object, shape_list);
+            shapes += s;
+            triangles += t;
+            max_depth = max_depth.max(d);
+        }
+
+        let memory: usize = shape_list.shapes.iter()
+            .map(|shape| std::mem::size_of_val(&**shape) + std::mem::size_of::<Box<dyn Shape + Send>>() +
+                shape.as_any().downcast_ref::<Group>().map_or(0, |g| g.children_ids.capacity() * std::mem::size_of::<i32>()))
+            .sum();
+
+        format!("Shapes: {} ({} in shape list)\nTriangles: {}\nLights: {}\nShape list memory: {:.1} KiB\nMax group depth: {}",
+                shapes, shape_list.len(), triangles, self.lights.len(), memory as f64 / 1024.0, max_depth)
+    }
+
+    /// Returns the size of the scene as data, see `scene_info` for a printable summary
+    pub fn complexity_report(&self, shape_list: &ShapeList) -> SceneComplexity {
+        let mut report = SceneComplexity { total_objects: 0, total_primitives: 0, max_group_depth: 0, total_lights: self.lights.len(), estimated_bvh_nodes: 0 };
+        for object in self.objects.iter() {
+            let (s, _, p, d) = World::shape_counts(&**object, shape_list);
+            report.total_objects += s;
+            report.total_primitives += p;
+            report.max_group_depth = report.max_group_depth.max(d);
+        }
+        // A binary tree with n leaves has n - 1 inner nodes
+        report.estimated_bvh_nodes = (2 * report.total_primitives).saturating_sub(1);
+        report
+    }
+
+    /// Returns the amount of shapes in the world including groups and everything inside them
+    pub fn total_shapes_count(&self, shape_list: &ShapeList) -> usize {
+        self.objects.iter().map(|object| World::shape_counts(&**object, shape_list).0).sum()
+    }
+
+    pub fn total_lights_count(&self) -> usize {
+        self.lights.len()
+    }
+
+    /// Returns the amount of shapes, triangles, primitives, and the group nesting depth under a shape
+    fn shape_counts(shape: &dyn Shape, shape_list: &ShapeList) -> (usize, usize, usize, usize) {
+        let mut children = vec![];
+        let mut depth = 0;
+        let mut primitives = 0;
+        if let Some(group) = shape.as_any().downcast_ref::<Group>() {
+            children = group.children_ids.clone();
+            depth = 1;
+        } else if let Some(csg) = shape.as_any().downcast_ref::<CSG>() {
+            children = csg.left_id.iter().chain(csg.right_id.iter()).cloned().collect();
+        } else {
+            primitives = 1;
+        }
+
+        let mut shapes = 1;
+        let mut triangles = if shape.shape_type() == "triangle" || shape.shape_type() == "smooth_triangle" { 1 } else { 0 };
+        let mut max_child_depth = 0;
+        for id in children {
+            let (s, t, p, d) = World::shape_counts(&*shape_list[id as usize], shape_list);
+            shapes += s;
+            triangles += t;
+            primitives += p;
+            max_child_depth = max_child_depth.max(d);
+        }
+        (shapes, triangles, primitives, depth + max_child_depth)
     }
 
     pub fn contains_object(&self, object: &Box<dyn Shape + Send>) -> bool {
@@ -54,6 +266,9 @@ impl World {
         let mut intersections = vec![];
 
         for object in self.objects.iter() {
+            if World::is_behind_ray(ray, &**object, shape_list) || !object.intersects_bounds(ray, shape_list) {
+                continue;
+            }
             intersections.append(&mut object.intersects(&ray, shape_list));
         }
         // Sort intersections ascending by t value
@@ -61,6 +276,50 @@ impl World {
         intersections
     }
 
+    /// Returns true if the shape's bounding box is entirely behind the ray's origin,
+    /// so every intersection with it would have a negative t
+    fn is_behind_ray(ray: &Ray, shape: &dyn Shape, shape_list: &ShapeList) -> bool {
+        let (min, max) = shape.bbox_world(shape_list);
+        let min = [min.x.value(), min.y.value(), min.z.value()];
+        let max = [max.x.value(), max.y.value(), max.z.value()];
+        let origin = [ray.origin.x.value(), ray.origin.y.value(), ray.origin.z.value()];
+        let direction = [ray.direction.x.value(), ray.direction.y.value(), ray.direction.z.value()];
+
+        // Furthest distance along the ray of any corner of the box,
+        // i.e. the box's max z in a coordinate system looking down the ray
+        let mut max_z = 0.0;
+        for i in 0..3 {
+            if direction[i] == 0.0 {
+                continue;
+            }
+            max_z += ((min[i] - origin[i]) * direction[i]).max((max[i] - origin[i]) * direction[i]);
+        }
+        max_z < 0.0
+    }
+
+    /// Returns the sorted intersections of a ray along with its hit
+    ///
+    /// The hit is found while the intersections are already sorted,
+    /// saving a separate scan with `intersection::hit`
+    pub fn intersect_with_all(&self, ray: &Ray, shape_list: &mut ShapeList) -> IntersectionResult<Box<dyn Shape + Send>> {
+        let all = self.intersects(ray, shape_list);
+        let hit = all.iter().find(|i| i.t > Float(0.0)).cloned();
+        IntersectionResult {hit, all}
+    }
+
+    /// Returns the amount of rays traced so far by this world and its clones
+    pub fn photons_traced(&self) -> usize {
+        self.photons_traced.load(Ordering::Relaxed)
+    }
+
+    /// Counts a ray against the photon budget, returning false once the budget is used up
+    fn take_photon(&self) -> bool {
+        if self.photon_budget == usize::MAX {
+            return true;
+        }
+        self.photons_traced.fetch_add(1, Ordering::Relaxed) < self.photon_budget
+    }
+
     /// Returns the color in the world at what the ray is intersecting with
     /// uses the default max_recursion value and is a wrapper for color_at_impl
     /// # Arguments
@@ -69,15 +328,28 @@ impl World {
         self.color_at_impl(ray, self.max_recursion, shape_list)
     }
 
+    /// Returns the color at what the ray is intersecting with lit only by the
+    /// lights, no reflected or refracted rays are traced
+    ///
+    /// Useful for fast previews and as the base case of global illumination
+    pub fn color_at_direct(&self, ray: &Ray, shape_list: &mut ShapeList) -> Color {
+        self.color_at_impl(ray, 0, shape_list)
+    }
+
     /// Returns the color in the world at what the ray is intersecting with
     /// # Arguments
     /// * `ray` Ray to shoot into the world
     /// * `remaining` Remaining amount of recursions allowed
     pub fn color_at_impl(&self, ray: &Ray, remaining: i32, shape_list: &mut ShapeList) -> Color {
-        let intersections = self.intersects(ray, shape_list);
-        let hit = intersection::hit(intersections.clone());
-        if hit == None {return Color::new(0.0, 0.0, 0.0)}  // Return black of no hits
-        let comps = intersection::prepare_computations(hit.unwrap(), ray, intersections, shape_list);
+        if !self.take_photon() {
+            return self.ray_miss_color(ray);
+        }
+        let result = self.intersect_with_all(ray, shape_list);
+        let hit = match result.hit {
+            Some(hit) => hit,
+            None => return self.ray_miss_color(ray),
+        };
+        let comps = intersection::prepare_computations(hit, ray, result.all, shape_list);
         self.shade_hit_impl(comps, remaining, shape_list)
     }
 
@@ -94,23 +366,60 @@ impl World {
     /// * `comps` Precomputed data of a ray intersection
     /// * `remaining` Remaining amount of recursions allowed
     pub fn shade_hit_impl(&self, comps: PrecomputedData<Box<dyn Shape + Send>>, remaining: i32, shape_list: &mut ShapeList) -> Color {
-        // One light implementation for now
-        let is_shadowed = self.is_shadowed(comps.over_point, shape_list);
+        // Without lights only emissive surfaces are visible
+        if self.lights.is_empty() {
+            return comps.object.material().emitted();
+        }
+
         let reflected = self.reflected_color_impl(comps.clone(), remaining, shape_list);
         let refracted = self.refracted_color_impl(comps.clone(), remaining, shape_list);
-
-        let surface = Light::lighting(&comps.object.material(), Some(comps.object.clone()), Some(self),
-                                      &self.lights[0], &comps.point, Some(&comps.over_point), &comps.eyev, &comps.normalv, is_shadowed, Some(shape_list));
+        let surface = self.surface_color(&comps, shape_list);
 
         let material = comps.object.material();
         if material.reflective > Float(0.0) && material.transparency > Float(0.0) {
             let reflectance = schlick(comps.clone()).value();
-            return surface + reflected * reflectance + refracted * (1.0 - reflectance);
+            surface + reflected * reflectance + refracted * (1.0 - reflectance)
         } else {
-            return surface + reflected + refracted
+            surface + reflected + refracted
         }
     }
 
+    /// Returns the light given off by a point in the world without following reflections
+    /// or refractions, the shading of every light plus the surface's own emission
+    /// # Arguments
+    /// * `comps` Precomputed data of a ray intersection
+    fn surface_color(&self, comps: &PrecomputedData<Box<dyn Shape + Send>>, shape_list: &mut ShapeList) -> Color {
+        // Without lights only emissive surfaces are visible
+        if self.lights.is_empty() {
+            return comps.object.material().emitted();
+        }
+
+        // Occluded surfaces reflect less of the light diffusely,
+        // scaling the material's diffuse scales only the diffuse part of the lighting
+        let mut lit_material = comps.object.material();
+        if self.ao_samples > 0 {
+            let occlusion = self.ambient_occlusion_samples(comps.over_point, comps.normalv, self.ao_samples, shape_list);
+            lit_material.diffuse = lit_material.diffuse * occlusion;
+        }
+
+        // Each light adds its own shading, shadowed separately
+        let mut surface = Color::black();
+        for light in self.lights.iter() {
+            let shadow_color = self.is_shadowed_by(light, comps.over_point, shape_list);
+            surface = surface + Light::lighting(&lit_material, Some(comps.object.clone()), Some(self),
+                                                light, &comps.point, Some(&comps.over_point), &comps.eyev, &comps.normalv, shadow_color, Some(shape_list));
+        }
+        let surface = if self.ao_weight > 0.0 {
+            let occlusion = self.ambient_occlusion(comps.over_point, comps.normalv, shape_list);
+            surface * (1.0 - self.ao_weight + self.ao_weight * occlusion)
+        } else {
+            surface
+        };
+
+        // Emissive surfaces glow on top of the light they reflect
+        surface + comps.object.material().emitted()
+    }
+
     /// Returns the color at a reflected ray in the world
     /// uses the default max_recursion value and is a wrapper for reflected_color_impl
     /// # Arguments
@@ -136,7 +445,20 @@ impl World {
 
         // Shoot a new reflected ray out into the world
         let reflected_ray = Ray::new(comps.over_point, comps.reflectv);
-        let color = self.color_at_impl(&reflected_ray, remaining-1, shape_list); // decrement remaining ray value
+
+        // Reflections inside transparent objects may skip the decrement, capped at max_recursion
+        // free bounces per path so total internal reflection still terminates
+        let internal = comps.inside && comps.object.material().transparency > Float(0.0);
+        let free = internal && !self.trace_internal_reflections
+            && FREE_INTERNAL_REFLECTIONS.with(|count| count.get()) < self.max_recursion;
+        let color = if free {
+            FREE_INTERNAL_REFLECTIONS.with(|count| count.set(count.get() + 1));
+            let color = self.color_at_impl(&reflected_ray, remaining, shape_list);
+            FREE_INTERNAL_REFLECTIONS.with(|count| count.set(count.get() - 1));
+            color
+        } else {
+            self.color_at_impl(&reflected_ray, remaining-1, shape_list) // decrement remaining ray value
+        };
 
         color * reflective.value()
     }
@@ -166,6 +488,22 @@ impl World {
         }
 
         // Check for total refraction, if so return black
+        let refract_ray = match World::refracted_ray(&comps) {
+            Some(refract_ray) => refract_ray,
+            None => return Color::black(),
+        };
+
+        // Find the color of the refracted ray in the world
+        let color = self.color_at_impl(&refract_ray, remaining-1, shape_list);
+
+        color * transparency.value()
+    }
+
+    /// Returns the ray refracted through the surface or None when there is
+    /// total internal reflection
+    /// # Arguments
+    /// * `comps` Precomputed data of a ray intersection
+    pub fn refracted_ray(comps: &PrecomputedData<Box<dyn Shape + Send>>) -> Option<Ray> {
         // First find ratio of the 2 indices of refraction
         let n_ratio = comps.n1 / comps.n2;
 
@@ -173,7 +511,7 @@ impl World {
         // via trig identity
         let sin2_t = n_ratio * n_ratio * (1.0 - cos_i * cos_i);
         if sin2_t > Float(1.0) {
-            return Color::black();
+            return None;
         }
 
         // Find cos(theta_t)
@@ -182,36 +520,206 @@ impl World {
         // Compute direction of the refracted ray
         let direction = comps.normalv * (n_ratio * cos_i - cos_t).value() - comps.eyev * n_ratio.value();
 
-        // Create the refracted ray
-        let refract_ray = Ray::new(comps.under_point, direction);
-
-        // Find the color of the refracted ray in the world
-        let color = self.color_at_impl(&refract_ray, remaining-1, shape_list);
+        Some(Ray::new(comps.under_point, direction))
+    }
 
-        color * transparency.value()
+    /// Returns the color along a single path through the world
+    ///
+    /// Instead of splitting into a reflected and refracted ray at every bounce,
+    /// one of them is chosen at random weighted by the reflectance and transmittance.
+    /// After `path_min_depth` bounces, russian roulette terminates paths early.
+    /// Averaging many paths converges to the same image as `color_at`.
+    /// # Arguments
+    /// * `ray` Ray to shoot into the world
+    /// * `max_depth` Maximum amount of bounces along the path
+    pub fn path_trace(&self, ray: &Ray, max_depth: i32, shape_list: &mut ShapeList) -> Color {
+        self.path_trace_impl(ray, 0, max_depth, shape_list)
     }
 
-    pub fn is_shadowed(&self, point: Tuple, shape_list: &mut ShapeList) -> bool {
-        // One light implementation for now
-        let vector = self.lights[0].position - point;
-        let distance = vector.magnitude();
-        let direction = vector.normalize();
+    fn path_trace_impl(&self, ray: &Ray, depth: i32, max_depth: i32, shape_list: &mut ShapeList) -> Color {
+        if depth >= max_depth {
+            return Color::black();
+        }
+        if !self.take_photon() {
+            return self.ray_miss_color(ray);
+        }
 
-        let ray = Ray::new(point, direction);
-        let intersections = self.intersects(&ray, shape_list);
+        let result = self.intersect_with_all(ray, shape_list);
+        let hit = match result.hit {
+            Some(hit) => hit,
+            None => return self.ray_miss_color(ray),
+        };
+        let comps = intersection::prepare_computations(hit, ray, result.all, shape_list);
 
-        let hit = intersection::hit(intersections);
+        // Direct light is shaded the same way as in shade_hit
+        let surface = self.surface_color(&comps, shape_list);
 
-        // If there is a hit and the t value is less than the distance to the light, return true
-        if hit != None {
-            if hit.unwrap().t < Float(distance) {
-                true
-            } else {
-                false
+        // Weights of continuing along the reflected or refracted path
+        let material = comps.object.material();
+        let (reflect_weight, refract_weight) = if material.reflective > Float(0.0) && material.transparency > Float(0.0) {
+            let reflectance = schlick(comps.clone()).value();
+            (material.reflective.value() * reflectance, material.transparency.value() * (1.0 - reflectance))
+        } else {
+            (material.reflective.value(), material.transparency.value())
+        };
+        let total_weight = reflect_weight + refract_weight;
+        if total_weight <= 0.0 {
+            return surface;
+        }
+
+        let mut rng = rand::thread_rng();
+
+        // Russian roulette, surviving paths are scaled up to stay unbiased
+        let mut throughput = total_weight;
+        if depth >= self.path_min_depth {
+            let survival = total_weight.min(1.0);
+            if rng.gen::<f64>() >= survival {
+                return surface;
             }
+            throughput = total_weight / survival;
+        }
+
+        // Choose a single path to follow
+        let reflect_ray = Ray::new(comps.over_point, comps.reflectv);
+        let next_ray = if rng.gen::<f64>() * total_weight < reflect_weight {
+            reflect_ray
         } else {
-            false
+            // Total internal reflection sends all of the light back
+            World::refracted_ray(&comps).unwrap_or(reflect_ray)
+        };
+
+        surface + self.path_trace_impl(&next_ray, depth + 1, max_depth, shape_list) * throughput
+    }
+
+    /// Returns the light reaching each point from all of the lights, taking shadows into account
+    ///
+    /// Shadow rays for every point and light are interleaved in a single pass,
+    /// one sample of each light at a time, rather than finishing each light before the next
+    /// # Arguments
+    /// * `points` Points to test, usually over points so they don't shadow themselves
+    /// * `lights` Lights to sample, area lights fire `ray_count` rays and point lights one
+    pub fn intersects_shadow_batch(&self, points: &[Tuple], lights: &[Light], shape_list: &mut ShapeList) -> Vec<Color> {
+        let mut rng = rand::thread_rng();
+        // Light let through by the samples for each point and light
+        let mut visible = vec![vec![Color::black(); lights.len()]; points.len()];
+
+        let max_samples = lights.iter().map(|light| light.shadow_ray_count()).max().unwrap_or(0);
+        for sample in 0..max_samples {
+            for (l, light) in lights.iter().enumerate() {
+                if sample >= light.shadow_ray_count() {
+                    continue;
+                }
+                let target = light.random_point(&mut rng);
+                for (p, point) in points.iter().enumerate() {
+                    let (direction, distance) = light.direction_from(point, &target);
+
+                    let ray = Ray::new(*point, direction);
+                    visible[p][l] = visible[p][l] + self.transmittance(&ray, distance, shape_list);
+                }
+            }
+        }
+
+        visible.iter()
+            .map(|counts| counts.iter().zip(lights.iter())
+                .fold(Color::black(), |sum, (transmitted, light)| {
+                    sum + light.intensity * *transmitted * (1.0 / light.shadow_ray_count() as f64)
+                }))
+            .collect()
+    }
+
+    /// Returns the fraction of the hemisphere above a point that is not blocked by nearby objects,
+    /// 1.0 for a fully open surface and 0.0 for one that is completely enclosed
+    /// # Arguments
+    /// * `point` Point to test, usually the over point so it doesn't occlude itself
+    /// * `normal` Surface normal the hemisphere is centered around
+    pub fn ambient_occlusion(&self, point: Tuple, normal: Tuple, shape_list: &mut ShapeList) -> f64 {
+        self.ambient_occlusion_samples(point, normal, AO_SAMPLES, shape_list)
+    }
+
+    /// Same as `ambient_occlusion` with `samples` hemisphere rays
+    pub fn ambient_occlusion_samples(&self, point: Tuple, normal: Tuple, samples: usize, shape_list: &mut ShapeList) -> f64 {
+        let mut rng = rand::thread_rng();
+        let mut open = 0;
+        for _ in 0..samples {
+            let ray = Ray::random_in_hemisphere(point, normal, &mut rng);
+            let hit = intersection::hit(self.intersects(&ray, shape_list));
+            if hit.is_none_or(|hit| hit.t >= Float(AO_DISTANCE)) {
+                open += 1;
+            }
         }
+        open as f64 / samples as f64
+    }
+
+    /// Returns a randomly chosen light and the probability it had of being chosen,
+    /// so a single light can be sampled at each bounce and its contribution divided by the probability
+    ///
+    /// Panics if the world has no lights
+    pub fn sample_light(&self, rng: &mut impl Rng) -> (&Light, f64) {
+        assert!(!self.lights.is_empty(), "sampling a light in a world without lights");
+        match self.light_selection {
+            LightSelection::Uniform => self.sample_light_uniform(rng),
+            LightSelection::AreaWeighted => {
+                let areas: Vec<f64> = self.lights.iter().map(|light| light.surface_area()).collect();
+                let area_lights = areas.iter().filter(|area| **area > 0.0).count();
+                if area_lights == 0 {
+                    return self.sample_light_uniform(rng);
+                }
+                let average_area = areas.iter().sum::<f64>() / area_lights as f64;
+                let weights: Vec<f64> = areas.iter().map(|area| if *area > 0.0 { *area } else { average_area }).collect();
+                let total: f64 = weights.iter().sum();
+
+                let mut target = rng.gen::<f64>() * total;
+                for (light, weight) in self.lights.iter().zip(weights.iter()) {
+                    if target < *weight {
+                        return (light, weight / total);
+                    }
+                    target -= weight;
+                }
+                // Rounding left the target past the last light
+                (self.lights.last().unwrap(), weights.last().unwrap() / total)
+            },
+        }
+    }
+
+    fn sample_light_uniform(&self, rng: &mut impl Rng) -> (&Light, f64) {
+        let index = rng.gen_range(0, self.lights.len());
+        (&self.lights[index], 1.0 / self.lights.len() as f64)
+    }
+
+    /// Returns how much of the world's first light reaches the point, see `is_shadowed_by`
+    pub fn is_shadowed(&self, point: Tuple, shape_list: &mut ShapeList) -> Color {
+        self.is_shadowed_by(&self.lights[0], point, shape_list)
+    }
+
+    /// Returns the color of the light reaching the point, white when nothing is in the way
+    /// and black when an opaque object blocks it, anything along the way blocks a directional light
+    pub fn is_shadowed_by(&self, light: &Light, point: Tuple, shape_list: &mut ShapeList) -> Color {
+        let (direction, distance) = light.direction_from(&point, &light.position);
+        self.transmittance(&Ray::new(point, direction), distance, shape_list)
+    }
+
+    /// Returns the color of the light let through by the objects along the ray before `distance`,
+    /// white when nothing is in the way and black when an opaque object blocks it
+    ///
+    /// Transparent objects tint the light towards their color, or their pattern's color where
+    /// the ray crosses them, once for every surface crossed
+    pub fn transmittance(&self, ray: &Ray, distance: f64, shape_list: &mut ShapeList) -> Color {
+        let intersections = self.intersects(ray, shape_list);
+
+        let mut color = Color::white();
+        for i in intersections.iter().filter(|i| i.t >= Float(0.0) && i.t < Float(distance)) {
+            let material = i.object.material();
+            if material.transparency <= Float(0.0) {
+                return Color::black();
+            }
+            let surface_color = match &material.pattern {
+                Some(pattern) => pattern.pattern_at_object(i.object.clone(), &ray.position(i.t.value())),
+                None => material.color,
+            };
+            let transparency = material.transparency.value().min(1.0);
+            color = color * (surface_color * (1.0 - transparency) + Color::white() * transparency);
+        }
+        color
     }
 }
 
@@ -219,14 +727,18 @@ impl World {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::assert_color_approx_eq;
     use crate::ray::Ray;
     use crate::tuple::vector;
     use crate::intersection;
     use crate::transformation::translation;
     use crate::intersection::{prepare_computations_single_intersection, prepare_computations};
     use crate::shape::plane::Plane;
+    use crate::shape::cone::Cone;
     use crate::pattern::test_pattern::TestPattern;
     use crate::shape::shape_list::ShapeList;
+    use crate::shape::triangle::Triangle;
+    use crate::FLOAT_THRESHOLD;
 
     #[test]
     fn world_creation() {
@@ -264,6 +776,87 @@ mod tests {
         assert_eq!(xs[3].t, 6.0);
     }
 
+    #[test]
+    fn world_intersects_culls_behind() {
+        let mut shape_list = ShapeList::new();
+        let w = World::default_world(&mut shape_list);
+
+        // Both spheres are behind the ray
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, -1.0));
+        assert!(World::is_behind_ray(&r, &*w.objects[0], &shape_list));
+        assert_eq!(w.intersects(&r, &mut shape_list).len(), 0);
+
+        // A ray starting inside a sphere still sees the intersection behind it
+        let r = Ray::new(point(0.0, 0.0, 0.0), vector(0.0, 0.0, 1.0));
+        assert!(!World::is_behind_ray(&r, &*w.objects[0], &shape_list));
+        let xs = w.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 4);
+        assert_eq!(xs[0].t, -1.0);
+
+        // Infinite planes are never culled
+        let mut p = Plane::new(&mut shape_list);
+        p.set_transform(translation(0.0, -1.0, 0.0), &mut shape_list);
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, -1.0));
+        assert!(!World::is_behind_ray(&r, &p, &shape_list));
+
+        // Shapes without known bounds are never culled
+        // e.g. a union whose operands are away from its origin
+        let mut w = World::new();
+        let mut left = Sphere::new(&mut shape_list);
+        left.set_transform(translation(0.0, 0.0, -10.0), &mut shape_list);
+        let right = Sphere::new(&mut shape_list);
+        let mut unknown = CSG::new_with_operation("union", left.id, right.id, &mut shape_list);
+        unknown.shape_type = String::from("unknown");
+        shape_list.update(Box::new(unknown.clone()));
+        w.objects.push(Box::new(unknown.clone()));
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, -1.0));
+        assert!(!World::is_behind_ray(&r, &unknown, &shape_list));
+        let xs = w.intersects(&r, &mut shape_list);
+        assert_eq!(xs.len(), 4);
+        assert_eq!(xs[2].t, 4.0);
+    }
+
+    #[test]
+    fn world_intersects_wide_cone() {
+        let mut shape_list = ShapeList::new();
+        let mut w = World::new();
+        // Wider than the unit box away from the apex
+        let cone = Cone::new_bounded(-3.0, 3.0, &mut shape_list);
+        w.objects.push(Box::new(cone.clone()));
+        let r = Ray::new(point(2.5, 2.9, -5.0), vector(0.0, 0.0, 1.0));
+        assert_eq!(cone.intersects(&r, &mut shape_list).len(), 2);
+        assert_eq!(w.intersects(&r, &mut shape_list).len(), 2);
+
+        // Unbounded cones are never culled by their bounds
+        let mut w = World::new();
+        w.objects.push(Box::new(Cone::new(&mut shape_list)));
+        let r = Ray::new(point(50.0, 60.0, -100.0), vector(0.0, 0.0, 1.0));
+        assert_eq!(w.intersects(&r, &mut shape_list).len(), 2);
+    }
+
+    #[test]
+    fn world_intersect_with_all() {
+        let mut shape_list = ShapeList::new();
+        let w = World::default_world(&mut shape_list);
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let result = w.intersect_with_all(&r, &mut shape_list);
+        assert_eq!(result.all.len(), 4);
+        assert_eq!(result.hit.unwrap().t, 4.0);
+
+        // Starting inside both spheres, the hit skips negative t values
+        let r = Ray::new(point(0.0, 0.0, 0.0), vector(0.0, 0.0, 1.0));
+        let result = w.intersect_with_all(&r, &mut shape_list);
+        assert_eq!(result.all.len(), 4);
+        assert_eq!(result.all[0].t, -1.0);
+        assert_eq!(result.hit.unwrap().t, 0.5);
+
+        // Missing everything
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 1.0, 0.0));
+        let result = w.intersect_with_all(&r, &mut shape_list);
+        assert!(result.all.is_empty());
+        assert_eq!(result.hit, None);
+    }
+
     #[test]
     fn world_shading() {
         let mut shape_list = ShapeList::new();
@@ -274,7 +867,7 @@ mod tests {
         let i = Intersection::new(4.0, shape);
         let comps = intersection::prepare_computations_single_intersection(i, &r, &mut shape_list);
         let c = w.shade_hit(comps, &mut shape_list);
-        assert_eq!(c, Color::new(0.38066, 0.47583, 0.2855));
+        assert_color_approx_eq!(c, Color::new(0.38066, 0.47583, 0.2855), 0.0001);
 
         // Shading an intersection from the inside
         let mut w = World::default_world(&mut shape_list);
@@ -284,7 +877,7 @@ mod tests {
         let i = Intersection::new(0.5, shape);
         let comps = intersection::prepare_computations_single_intersection(i, &r, &mut shape_list);
         let c = w.shade_hit(comps, &mut shape_list);
-        assert_eq!(c, Color::new(0.90498, 0.90498, 0.90498));
+        assert_color_approx_eq!(c, Color::new(0.90498, 0.90498, 0.90498), 0.0001);
 
         // shade hit is given an intersection in shadow (SHADOWS!)
         let mut w = World::new();
@@ -315,7 +908,7 @@ mod tests {
         let w = World::default_world(&mut shape_list);
         let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
         let c = w.color_at(&r, &mut shape_list);
-        assert_eq!(c, Color::new(0.38066, 0.47583, 0.2855));
+        assert_color_approx_eq!(c, Color::new(0.38066, 0.47583, 0.2855), 0.0001);
 
         // Pointing at inner sphere from inside outer sphere
         let mut w = World::default_world(&mut shape_list);
@@ -336,28 +929,133 @@ mod tests {
         assert_eq!(c, inner_color);
     }
 
+    #[test]
+    fn world_intersects_shadow_batch() {
+        let mut shape_list = ShapeList::new();
+        let w = World::default_world(&mut shape_list);
+        let points = [point(0.0, 10.0, 0.0), point(10.0, -10.0, 10.0), point(-20.0, 20.0, -20.0)];
+        let lights = [w.lights[0].clone(), Light::point_light(&point(0.0, 0.0, 10.0), &Color::new(0.5, 0.0, 0.0))];
+
+        let colors = w.intersects_shadow_batch(&points, &lights, &mut shape_list);
+        assert_eq!(colors, vec![Color::new(1.5, 1.0, 1.0), Color::new(0.5, 0.0, 0.0), Color::new(1.5, 1.0, 1.0)]);
+
+        // Area lights only partially shadow points near the edge of the shadow
+        let mut area = Light::area_light(&point(0.0, 0.0, 10.0), &Color::white(), 6.0);
+        area.ray_count = 200;
+        let colors = w.intersects_shadow_batch(&[point(0.0, 0.0, -5.0), point(0.0, 0.0, 20.0)], &[area], &mut shape_list);
+        assert!(colors[0].red > Float(0.0) && colors[0].red < Float(1.0));
+        assert_eq!(colors[1], Color::white());
+    }
+
+    #[test]
+    fn world_directional_light() {
+        let mut shape_list = ShapeList::new();
+        let mut w = World::new();
+        let mut material = Material::new();
+        material.diffuse = Float(0.5);
+        material.specular = Float(0.0);
+        let floor = Plane::new_with_material(material, &mut shape_list);
+        w.objects.push(Box::new(floor));
+        w.lights.push(Light::directional_light(&vector(0.0, -1.0, 0.0), &Color::white()));
+
+        // The same diffuse color however far the point is from the origin
+        for (x, z) in [(0.0, 0.0), (1000.0, -500.0), (-1.0e5, 3.0)].iter() {
+            let r = Ray::new(point(*x, 1.0, *z), vector(0.0, -1.0, 0.0));
+            assert_eq!(w.color_at(&r, &mut shape_list), Color::new(0.6, 0.6, 0.6));
+        }
+
+        // Anything in the light's direction casts a shadow, however far away
+        let mut blocker = Sphere::new(&mut shape_list);
+        blocker.set_transform(translation(0.0, 1000.0, 0.0), &mut shape_list);
+        w.objects.push(Box::new(blocker));
+        assert_eq!(w.is_shadowed(point(0.0, 0.01, 0.0), &mut shape_list), Color::black());
+        assert_eq!(w.is_shadowed(point(5.0, 0.01, 0.0), &mut shape_list), Color::white());
+        let r = Ray::new(point(0.0, 1.0, 0.0), vector(0.0, -1.0, 0.0));
+        assert_eq!(w.color_at(&r, &mut shape_list), Color::new(0.1, 0.1, 0.1));
+
+        // Each light adds its own shading, the point light below the blocker isn't shadowed
+        w.lights.push(Light::point_light(&point(0.0, 10.0, 0.0), &Color::white()));
+        assert_eq!(w.color_at(&r, &mut shape_list), Color::new(0.7, 0.7, 0.7));
+    }
+
     #[test]
     fn world_is_shadowed() {
         let mut shape_list = ShapeList::new();
         // There is no shadow when nothing is collinear with point and light
         let w = World::default_world(&mut shape_list);
         let p = point(0.0, 10.0, 0.0);
-        assert_eq!(w.is_shadowed(p, &mut shape_list), false);
+        assert_eq!(w.is_shadowed(p, &mut shape_list), Color::white());
 
         // The shadow when an object is between the point and the light
         let w = World::default_world(&mut shape_list);
         let p = point(10.0, -10.0, 10.0);
-        assert_eq!(w.is_shadowed(p, &mut shape_list), true);
+        assert_eq!(w.is_shadowed(p, &mut shape_list), Color::black());
 
         // No shadow when an object is behind the light
         let w = World::default_world(&mut shape_list);
         let p = point(-20.0, 20.0, -20.0);
-        assert_eq!(w.is_shadowed(p, &mut shape_list), false);
+        assert_eq!(w.is_shadowed(p, &mut shape_list), Color::white());
 
         // No shadow when an object is behind the point
         let w = World::default_world(&mut shape_list);
         let p = point(-2.0, 2.0, -2.0);
-        assert_eq!(w.is_shadowed(p, &mut shape_list), false);
+        assert_eq!(w.is_shadowed(p, &mut shape_list), Color::white());
+    }
+
+    #[test]
+    fn world_is_shadowed_transparent() {
+        let mut shape_list = ShapeList::new();
+        let mut w = World::new();
+        w.lights.push(Light::point_light(&point(0.0, 10.0, 0.0), &Color::white()));
+        let mut material = Material::new();
+        material.color = Color::new(1.0, 0.0, 0.0);
+        material.transparency = Float(0.5);
+        let glass = Sphere::new_with_material(material, &mut shape_list);
+        w.objects.push(Box::new(glass));
+
+        // Both surfaces of the red glass sphere tint the light
+        assert_eq!(w.is_shadowed(point(0.0, -5.0, 0.0), &mut shape_list), Color::new(1.0, 0.25, 0.25));
+        assert_eq!(w.is_shadowed(point(5.0, -5.0, 0.0), &mut shape_list), Color::white());
+
+        // A red glass sphere casts a reddish shadow on the floor
+        let mut floor = Plane::new(&mut shape_list);
+        floor.set_transform(translation(0.0, -5.0, 0.0), &mut shape_list);
+        w.objects.push(Box::new(floor));
+        let r = Ray::new(point(0.0, -4.0, 0.0), vector(0.0, -1.0, 0.0));
+        let c = w.color_at(&r, &mut shape_list);
+        assert!(c.red > c.green);
+        assert_eq!(c.green, c.blue);
+        assert!(c.green > Float(0.1));
+
+        // Area lights are tinted the same way
+        let mut area = Light::area_light(&point(0.0, 10.0, 0.0), &Color::white(), 0.1);
+        area.ray_count = 8;
+        assert_eq!(w.intersects_shadow_batch(&[point(0.0, -4.99, 0.0)], &[area.clone()], &mut shape_list), vec![Color::new(1.0, 0.25, 0.25)]);
+        w.lights = vec![area];
+        assert_eq!(w.color_at(&r, &mut shape_list), c);
+        w.lights = vec![Light::point_light(&point(0.0, 10.0, 0.0), &Color::white())];
+
+        // Opaque objects still block the light completely
+        let mut blocker = Sphere::new(&mut shape_list);
+        blocker.set_transform(translation(0.0, 5.0, 0.0), &mut shape_list);
+        w.objects.push(Box::new(blocker));
+        assert_eq!(w.is_shadowed(point(0.0, -5.0, 0.0), &mut shape_list), Color::black());
+    }
+
+    #[test]
+    fn world_is_shadowed_patterned_glass() {
+        let mut shape_list = ShapeList::new();
+        let mut w = World::new();
+        w.lights.push(Light::point_light(&point(0.0, 10.0, 0.0), &Color::white()));
+        let mut material = Material::new();
+        material.pattern = Some(Box::new(TestPattern::new()));
+        material.transparency = Float(0.5);
+        let glass = Sphere::new_with_material(material, &mut shape_list);
+        w.objects.push(Box::new(glass));
+
+        // The pattern's color where the shadow ray crosses each surface tints the light,
+        // (0, -1, 0) at the bottom of the sphere and (0, 1, 0) at the top
+        assert_eq!(w.is_shadowed(point(0.0, -5.0, 0.0), &mut shape_list), Color::new(0.25, 0.0, 0.25));
     }
 
     #[test]
@@ -404,6 +1102,12 @@ mod tests {
         let comps = prepare_computations_single_intersection(i, &r, &mut shape_list);
         let color = w.shade_hit(comps, &mut shape_list);
         assert_eq!(color, Color::new(0.87675, 0.92434, 0.82917));
+
+        // Direct lighting leaves out the reflection
+        assert_eq!(w.color_at(&r, &mut shape_list), color);
+        assert_eq!(w.color_at_direct(&r, &mut shape_list), color - Color::new(0.19033, 0.237915, 0.14274));
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        assert_eq!(w.color_at_direct(&r, &mut shape_list), w.color_at(&r, &mut shape_list));
     }
 
     #[test]
@@ -535,7 +1239,8 @@ mod tests {
         let xs = vec![Intersection::new(2.0f64.sqrt(), shape_p)];
         let comps = prepare_computations(xs[0].clone(), &r, xs.clone(), &mut shape_list);
         let color = w.shade_hit_impl(comps, 5, &mut shape_list);
-        assert_eq!(color, Color::new(0.93642, 0.68642, 0.68642));
+        // The clear pane no longer shadows the red plane below it
+        assert_eq!(color, Color::new(1.25250, 0.68642, 0.68642));
     }
     
     #[test]
@@ -559,6 +1264,366 @@ mod tests {
         let xs = vec![Intersection::new(2.0f64.sqrt(), shape_p)];
         let comps = prepare_computations(xs[0].clone(), &r, xs.clone(), &mut shape_list);
         let color = w.shade_hit_impl(comps, 5, &mut shape_list);
-        assert_eq!(color, Color::new(0.93391, 0.69643, 0.69243));
+        assert_eq!(color, Color::new(1.23669, 0.69643, 0.69243));
+    }
+
+    #[test]
+    fn world_sky() {
+        let mut shape_list = ShapeList::new();
+        let mut w = World::default_world(&mut shape_list);
+        let up = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 1.0, 0.0));
+        assert_eq!(w.color_at(&up, &mut shape_list), Color::black());
+
+        w.set_sky_fn(World::horizon_sky(Color::white(), Color::new(0.0, 0.0, 1.0)));
+        assert_eq!(w.color_at(&up, &mut shape_list), Color::new(0.0, 0.0, 1.0));
+        let flat = Ray::new(point(0.0, 0.0, -5.0), vector(1.0, 0.0, 0.0));
+        assert_eq!(w.color_at(&flat, &mut shape_list), Color::white());
+        let diagonal = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 1.0, -1.0));
+        assert_eq!(w.color_at(&diagonal, &mut shape_list), Color::new(0.29289, 0.29289, 1.0));
+        // Hits are still shaded as usual
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        assert_eq!(w.color_at(&r, &mut shape_list), Color::new(0.38066, 0.47583, 0.2855));
+
+        let sky = World::gradient_sky(vec![Color::new(1.0, 0.0, 0.0), Color::new(0.0, 1.0, 0.0)],
+                                      vec![vector(1.0, 0.0, 0.0), vector(0.0, 1.0, 0.0)]);
+        assert_eq!(sky(vector(2.0, 0.0, 0.0)), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(sky(vector(1.0, 1.0, 0.0)), Color::new(0.5, 0.5, 0.0));
+        assert_eq!(sky(vector(-1.0, -0.5, 0.0)), Color::new(0.0, 1.0, 0.0));
+
+        w.set_background(Color::new(0.1, 0.2, 0.3));
+        assert_eq!(w.ray_miss_color(&up), Color::new(0.1, 0.2, 0.3));
+        assert_eq!(w.color_at(&flat, &mut shape_list), Color::new(0.1, 0.2, 0.3));
+    }
+
+    #[test]
+    fn world_collect_emissive_shapes() {
+        let shape_list = &mut ShapeList::new();
+        let mut w = World::default_world(shape_list);
+        assert!(w.collect_emissive_shapes().is_empty());
+
+        let mut material = Material::new();
+        material.emission = Float(2.0);
+        material.emission_color = Color::new(1.0, 0.9, 0.8);
+        let lamp = Sphere::new_with_material(material, shape_list);
+        w.objects.push(Box::new(lamp.clone()));
+
+        let emissive = w.collect_emissive_shapes();
+        assert_eq!(emissive.len(), 1);
+        assert_eq!(emissive[0].id(), lamp.id);
+        assert_eq!(emissive[0].material().emitted(), Color::new(2.0, 1.8, 1.6));
+        let sampled: Box<dyn Shape + Send> = emissive[0].clone();
+        assert_eq!(sampled.id(), lamp.id);
+    }
+
+    #[test]
+    fn world_shade_hit_without_lights() {
+        let shape_list = &mut ShapeList::new();
+        let mut w = World::new();
+
+        let mut material = Material::new();
+        material.emission = Float(0.5);
+        material.emission_color = Color::new(1.0, 0.8, 0.2);
+        let lamp = Sphere::new_with_material(material, shape_list);
+        w.objects.push(Box::new(lamp.clone()));
+        let dark = Sphere::new(shape_list);
+
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let i = Intersection::new(4.0, Box::new(lamp) as Box<dyn Shape + Send>);
+        let comps = prepare_computations_single_intersection(i, &r, shape_list);
+        assert_eq!(w.shade_hit(comps, shape_list), Color::new(0.5, 0.4, 0.1));
+
+        let i = Intersection::new(4.0, Box::new(dark) as Box<dyn Shape + Send>);
+        let comps = prepare_computations_single_intersection(i, &r, shape_list);
+        assert_eq!(w.shade_hit(comps, shape_list), Color::black());
+
+        assert_eq!(w.path_trace(&r, 4, shape_list), Color::new(0.5, 0.4, 0.1));
+    }
+
+    #[test]
+    fn world_shade_hit_emissive() {
+        let shape_list = &mut ShapeList::new();
+        let mut w = World::new();
+
+        let lamp = Sphere::new_with_material(Material::emissive(Color::new(0.2, 0.9, 0.4), 1.0), shape_list);
+        w.objects.push(Box::new(lamp.clone()));
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let i = Intersection::new(4.0, Box::new(lamp.clone()) as Box<dyn Shape + Send>);
+        let comps = prepare_computations_single_intersection(i, &r, shape_list);
+        assert_eq!(w.shade_hit(comps.clone(), shape_list), Color::new(0.2, 0.9, 0.4));
+
+        // With a light the glow adds to the lit surface
+        w.lights.push(Light::point_light(&point(-10.0, 10.0, -10.0), &Color::new(1.0, 1.0, 1.0)));
+        let mut unlit = lamp.clone();
+        unlit.material = Material::new();
+        let i = Intersection::new(4.0, Box::new(unlit) as Box<dyn Shape + Send>);
+        let lit = w.shade_hit(prepare_computations_single_intersection(i, &r, shape_list), shape_list);
+        assert_eq!(w.shade_hit(comps, shape_list), lit + Color::new(0.2, 0.9, 0.4));
+    }
+
+    #[test]
+    fn world_scene_info() {
+        let mut shape_list = ShapeList::new();
+        let mut w = World::default_world(&mut shape_list);
+
+        let mut inner = Group::new(&mut shape_list);
+        for _ in 0..2 {
+            let mut t: Box<dyn Shape + Send> = Box::new(Triangle::new(point(0.0, 1.0, 0.0), point(-1.0, 0.0, 0.0), point(1.0, 0.0, 0.0), &mut shape_list));
+            inner.add_child(&mut t, &mut shape_list);
+        }
+        let mut outer = Group::new(&mut shape_list);
+        let mut inner: Box<dyn Shape + Send> = Box::new(inner);
+        outer.add_child(&mut inner, &mut shape_list);
+        let mut t: Box<dyn Shape + Send> = Box::new(Triangle::new(point(0.0, 1.0, 0.0), point(-1.0, 0.0, 0.0), point(1.0, 0.0, 0.0), &mut shape_list));
+        outer.add_child(&mut t, &mut shape_list);
+        w.objects.push(Box::new(outer));
+
+        let info = w.scene_info(&shape_list);
+        assert!(info.contains("Shapes: 7 ("));
+        assert!(info.contains("Triangles: 3\n"));
+        assert!(info.contains("Lights: 1\n"));
+        assert!(info.contains("Max group depth: 2"));
+
+        assert_eq!(w.total_shapes_count(&shape_list), 7);
+        assert_eq!(w.total_lights_count(), 1);
+
+        let report = w.complexity_report(&shape_list);
+        assert_eq!(report, SceneComplexity { total_objects: 7, total_primitives: 5, max_group_depth: 2, total_lights: 1, estimated_bvh_nodes: 9 });
+        assert!(report.to_string().contains("Primitives: 5\n"));
+        assert_eq!(World::new().complexity_report(&shape_list).estimated_bvh_nodes, 0);
+    }
+
+    #[test]
+    fn world_path_trace() {
+        let mut shape_list = ShapeList::new();
+        // A single diffuse path matches the recursive color
+        let w = World::default_world(&mut shape_list);
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let c = w.path_trace(&r, 5, &mut shape_list);
+        assert_color_approx_eq!(c, Color::new(0.38066, 0.47583, 0.2855), 0.0001);
+
+        // A path with no bounces remaining is black
+        let c = w.path_trace(&r, 0, &mut shape_list);
+        assert_eq!(c, Color::black());
+
+        // Missing everything is black
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 1.0, 0.0));
+        let c = w.path_trace(&r, 5, &mut shape_list);
+        assert_eq!(c, Color::black());
+
+        // A fully reflective plane always follows the reflected path
+        let mut w = World::default_world(&mut shape_list);
+        let mut p = Plane::new(&mut shape_list);
+        p.material.reflective = Float(1.0);
+        p.transform = translation(0.0, -1.0, 0.0);
+        w.objects.push(Box::new(p));
+        w.path_min_depth = 5;
+        let r = Ray::new(point(0.0, 0.0, -3.0), vector(0.0, -2.0f64.sqrt()/2.0, 2.0f64.sqrt()/2.0));
+        let mut w_recursive = w.clone();
+        w_recursive.max_recursion = 1;
+        assert_eq!(w.path_trace(&r, 2, &mut shape_list), w_recursive.color_at(&r, &mut shape_list));
+    }
+
+    #[test]
+    fn world_path_trace_direct_lighting() {
+        let mut shape_list = ShapeList::new();
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+
+        // Every light shades the hit, not only the first one
+        let mut w = World::default_world(&mut shape_list);
+        w.lights.push(Light::point_light(&point(10.0, 10.0, -10.0), &Color::new(0.5, 0.5, 0.5)));
+        let c = w.path_trace(&r, 5, &mut shape_list);
+        assert_eq!(c, w.color_at(&r, &mut shape_list));
+        let mut w_single = w.clone();
+        w_single.lights.truncate(1);
+        assert!(c.red > w_single.path_trace(&r, 5, &mut shape_list).red);
+
+        // Emissive surfaces glow on top of the light they reflect
+        let mut w = World::default_world(&mut shape_list);
+        let mut sphere = Sphere::new(&mut shape_list);
+        sphere.material = Material::emissive(Color::new(0.2, 0.3, 0.4), 1.0);
+        w.objects = vec![Box::new(sphere)];
+        let c = w.path_trace(&r, 5, &mut shape_list);
+        assert_color_approx_eq!(c, w.color_at(&r, &mut shape_list), 0.0001);
+        assert!(c.blue > Float(0.4));
+    }
+
+    #[test]
+    fn world_internal_reflections() {
+        let mut shape_list = ShapeList::new();
+        let mut w = World::default_world(&mut shape_list);
+        assert!(w.trace_internal_reflections);
+
+        let mut shape = w.objects[0].clone();
+        let mut material = Material::new();
+        material.color = Color::new(0.8, 1.0, 0.6);
+        material.reflective = Float(0.5);
+        material.transparency = Float(1.0);
+        material.refractive_index = Float(1.5);
+        shape.set_material(material, &mut shape_list);
+        w.objects[0] = shape.clone();
+
+        // Total internal reflection from inside the sphere
+        let r = Ray::new(point(0.0, 0.0, 2.0f64.sqrt()/2.0), vector(0.0, 1.0, 0.0));
+        let xs = vec![Intersection::new(-2.0f64.sqrt()/2.0, shape.clone()), Intersection::new(2.0f64.sqrt()/2.0, shape.clone())];
+        let comps = prepare_computations(xs[1].clone(), &r, xs.clone(), &mut shape_list);
+
+        // The internal reflection uses up the last recursion, leaving only the surface color
+        assert_eq!(w.reflected_color_impl(comps.clone(), 1, &mut shape_list), Color::new(0.04, 0.05, 0.03));
+
+        // Without the decrement the reflected ray is shaded with reflections of its own
+        w.trace_internal_reflections = false;
+        assert_eq!(w.reflected_color_impl(comps.clone(), 1, &mut shape_list), Color::new(0.16557, 0.20696, 0.12418));
+        assert_eq!(FREE_INTERNAL_REFLECTIONS.with(|count| count.get()), 0);
+
+        // Reflections outside transparent objects always use up a recursion
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let xs = vec![Intersection::new(4.0, shape.clone()), Intersection::new(6.0, shape.clone())];
+        let comps = prepare_computations(xs[0].clone(), &r, xs.clone(), &mut shape_list);
+        assert_eq!(w.reflected_color_impl(comps, 0, &mut shape_list), Color::black());
+    }
+
+    #[test]
+    fn world_ambient_occlusion() {
+        let shape_list = &mut ShapeList::new();
+
+        // An open surface is unaffected
+        let mut w = World::default_world(shape_list);
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let i = Intersection::new(4.0, w.objects[0].clone());
+        let comps = prepare_computations_single_intersection(i, &r, shape_list);
+        assert_eq!(w.ambient_occlusion(comps.over_point, comps.normalv, shape_list), 1.0);
+        w.ao_weight = 1.0;
+        assert_color_approx_eq!(w.shade_hit(comps, shape_list), Color::new(0.38066, 0.47583, 0.2855), 0.0001);
+
+        // A floor enclosed by a small shell is fully occluded
+        let mut w = World::new();
+        w.lights.push(Light::point_light(&point(-10.0, 10.0, -10.0), &Color::new(1.0, 1.0, 1.0)));
+        let floor = Plane::new(shape_list);
+        w.objects.push(Box::new(floor.clone()));
+        let mut shell = Sphere::new(shape_list);
+        shell.set_transform(transformation::scaling(0.5, 0.5, 0.5), shape_list);
+        w.objects.push(Box::new(shell));
+
+        let r = Ray::new(point(0.0, 1.0, 0.0), vector(0.0, -1.0, 0.0));
+        let i = Intersection::new(1.0, Box::new(floor) as Box<dyn Shape + Send>);
+        let comps = prepare_computations_single_intersection(i, &r, shape_list);
+        assert_eq!(w.ambient_occlusion(comps.over_point, comps.normalv, shape_list), 0.0);
+
+        assert_eq!(w.shade_hit(comps.clone(), shape_list), Color::new(0.1, 0.1, 0.1));
+        w.ao_weight = 0.5;
+        assert_eq!(w.shade_hit(comps.clone(), shape_list), Color::new(0.05, 0.05, 0.05));
+        w.ao_weight = 1.0;
+        assert_eq!(w.shade_hit(comps, shape_list), Color::black());
+    }
+
+    #[test]
+    fn world_ambient_occlusion_samples() {
+        let shape_list = &mut ShapeList::new();
+
+        // A floor under a shell, lit from the side by a light the shell doesn't block
+        let mut w = World::new();
+        w.lights.push(Light::point_light(&point(10.0, 5.0, 0.0), &Color::new(1.0, 1.0, 1.0)));
+        let mut material = Material::new();
+        material.specular = Float(0.0);
+        let floor = Plane::new_with_material(material, shape_list);
+        w.objects.push(Box::new(floor.clone()));
+        let mut shell = Sphere::new(shape_list);
+        shell.set_transform(translation(0.0, 0.6, 0.0) * transformation::scaling(0.5, 0.5, 0.5), shape_list);
+        w.objects.push(Box::new(shell));
+
+        let r = Ray::new(point(0.0, 0.05, -1.0), vector(0.0, -0.05, 1.0).normalize());
+        let i = Intersection::new(1.0, Box::new(floor) as Box<dyn Shape + Send>);
+        let comps = prepare_computations_single_intersection(i, &r, shape_list);
+        let lit = w.shade_hit(comps.clone(), shape_list);
+
+        // Off by default, some of the hemisphere is blocked once turned on
+        assert_eq!(w.ao_samples, 0);
+        w.ao_samples = 64;
+        let occlusion = w.ambient_occlusion_samples(comps.over_point, comps.normalv, 64, shape_list);
+        assert!(occlusion > 0.0 && occlusion < 1.0);
+        let occluded = w.shade_hit(comps.clone(), shape_list);
+        assert!(occluded.red < lit.red);
+
+        // Only diffuse is darkened, ambient is left alone
+        assert!(occluded.red > Float(0.1));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn world_ambient_occlusion_samples_enclosed() {
+        let shape_list = &mut ShapeList::new();
+
+        // A floor lit from inside the small shell enclosing it
+        let mut w = World::new();
+        w.lights.push(Light::point_light(&point(0.0, 0.2, 0.0), &Color::new(1.0, 1.0, 1.0)));
+        let mut material = Material::new();
+        material.specular = Float(0.0);
+        let floor = Plane::new_with_material(material, shape_list);
+        w.objects.push(Box::new(floor.clone()));
+        let mut shell = Sphere::new(shape_list);
+        shell.set_transform(transformation::scaling(0.5, 0.5, 0.5), shape_list);
+        w.objects.push(Box::new(shell));
+
+        let r = Ray::new(point(0.0, 0.1, 0.0), vector(0.0, -1.0, 0.0));
+        let i = Intersection::new(0.1, Box::new(floor) as Box<dyn Shape + Send>);
+        let comps = prepare_computations_single_intersection(i, &r, shape_list);
+        assert_color_approx_eq!(w.shade_hit(comps.clone(), shape_list), Color::new(1.0, 1.0, 1.0), 0.0001);
+
+        // All of the diffuse light is removed, the ambient light is kept
+        w.ao_samples = 16;
+        assert_color_approx_eq!(w.shade_hit(comps.clone(), shape_list), Color::new(0.1, 0.1, 0.1), 0.0001);
+
+        // ao_weight darkens what is left on top of that
+        w.ao_weight = 1.0;
+        assert_eq!(w.shade_hit(comps, shape_list), Color::black());
+    }
+
+    #[test]
+    fn world_photon_budget() {
+        let shape_list = &mut ShapeList::new();
+        let mut w = World::default_world(shape_list);
+        w.set_background(Color::new(0.0, 0.0, 1.0));
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let lit = w.color_at(&r, shape_list);
+        assert_eq!(w.photons_traced(), 0); // Unlimited budgets aren't counted
+
+        w.photon_budget = 2;
+        let clone = w.clone();
+        assert_eq!(w.color_at(&r, shape_list), lit);
+        assert_eq!(clone.color_at(&r, shape_list), lit);
+        // The budget is shared with clones so both are now out of photons
+        assert_eq!(w.color_at(&r, shape_list), Color::new(0.0, 0.0, 1.0));
+        assert_eq!(clone.path_trace(&r, 4, shape_list), Color::new(0.0, 0.0, 1.0));
+        assert_eq!(w.photons_traced(), 4);
+    }
+
+    #[test]
+    fn world_sample_light() {
+        let mut rng = rand::thread_rng();
+        let mut w = World::new();
+        w.lights.push(Light::point_light(&point(0.0, 10.0, 0.0), &Color::white()));
+        w.lights.push(Light::area_light(&point(0.0, 10.0, 5.0), &Color::white(), 1.0));
+        w.lights.push(Light::area_light(&point(0.0, 10.0, -5.0), &Color::white(), 2.0));
+
+        for _ in 0..20 {
+            let (light, probability) = w.sample_light(&mut rng);
+            assert!(w.contains_light(light));
+            assert_eq!(probability, 1.0 / 3.0);
+        }
+
+        // Areas are 4π and 16π, the point light counts as the average of 10π
+        w.light_selection = LightSelection::AreaWeighted;
+        let mut counts = [0; 3];
+        for _ in 0..3000 {
+            let (light, probability) = w.sample_light(&mut rng);
+            let index = w.lights.iter().position(|l| l == light).unwrap();
+            assert!((probability - [1.0 / 3.0, 2.0 / 15.0, 8.0 / 15.0][index]).abs() < FLOAT_THRESHOLD);
+            counts[index] += 1;
+        }
+        assert!(counts[2] > counts[0] && counts[0] > counts[1], "{:?}", counts);
+
+        // Only point lights falls back to uniform
+        let mut w = World::default_world(&mut ShapeList::new());
+        w.light_selection = LightSelection::AreaWeighted;
+        assert_eq!(w.sample_light(&mut rng), (&w.lights[0], 1.0));
+    }
+}